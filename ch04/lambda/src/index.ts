@@ -6,11 +6,14 @@ import { accounts, transactions } from "./schema";
 interface Request {
   payer_id: number;
   payee_id: number;
-  amount: number;
+  // A whole number moves balance; a decimal string such as "12.34" moves
+  // exact_balance instead, without ever becoming a float
+  amount: number | string;
 }
 
 interface Response {
   balance?: number;
+  exactBalance?: string;
   error?: string;
   errorCode?: string;
   duration: number;
@@ -20,31 +23,45 @@ interface Response {
 export const handler: Handler<Request, Response> = async (event) => {
   const startTime = Date.now();
   const db = await getDb();
+  const exact = typeof event.amount === "string";
 
   try {
-    const { result: balance, retries } = await withOccRetry(() =>
+    const { result: payer, retries } = await withOccRetry(() =>
       db.transaction(async (tx) => {
         // Deduct from payer
         const deductResult = await tx
           .update(accounts)
-          .set({ balance: sql`${accounts.balance} - ${event.amount}` })
+          .set(
+            exact
+              ? { exactBalance: sql`${accounts.exactBalance} - ${event.amount}::numeric` }
+              : { balance: sql`${accounts.balance} - ${event.amount}` },
+          )
           .where(eq(accounts.id, event.payer_id))
-          .returning({ balance: accounts.balance });
+          .returning({ balance: accounts.balance, exactBalance: accounts.exactBalance });
 
         if (deductResult.length === 0) {
           throw new Error("Payer account not found");
         }
 
-        const payerBalance = deductResult[0].balance;
+        const payer = deductResult[0];
 
-        if (payerBalance < 0) {
+        if (exact && payer.exactBalance === null) {
+          throw new Error("Payer account has no exact balance; run reset-balances");
+        }
+        // NUMERIC comes back as a string, so read its sign off the text
+        // rather than through a float
+        if (exact ? payer.exactBalance!.startsWith("-") : payer.balance < 0) {
           throw new Error("Insufficient balance");
         }
 
         // Add to payee
         const addResult = await tx
           .update(accounts)
-          .set({ balance: sql`${accounts.balance} + ${event.amount}` })
+          .set(
+            exact
+              ? { exactBalance: sql`${accounts.exactBalance} + ${event.amount}::numeric` }
+              : { balance: sql`${accounts.balance} + ${event.amount}` },
+          )
           .where(eq(accounts.id, event.payee_id))
           .returning({ id: accounts.id });
 
@@ -56,15 +73,18 @@ export const handler: Handler<Request, Response> = async (event) => {
         await tx.insert(transactions).values({
           payerId: event.payer_id,
           payeeId: event.payee_id,
-          amount: event.amount,
+          ...(typeof event.amount === "string"
+            ? { exactAmount: event.amount }
+            : { amount: event.amount }),
         });
 
-        return payerBalance;
+        return payer;
       }),
     );
 
     return {
-      balance,
+      balance: payer.balance,
+      exactBalance: exact ? payer.exactBalance! : undefined,
       duration: Date.now() - startTime,
       retries,
     };
@@ -1,9 +1,11 @@
-import { pgTable, integer, uuid, timestamp } from "drizzle-orm/pg-core";
+import { pgTable, integer, numeric, uuid, timestamp } from "drizzle-orm/pg-core";
 
 // Accounts table - uses integer PK (low write rate, reference data)
 export const accounts = pgTable("accounts", {
   id: integer("id").primaryKey(),
   balance: integer("balance").notNull(),
+  // Moved instead of balance by decimal amounts, to the cent
+  exactBalance: numeric("exact_balance", { precision: 12, scale: 2 }),
 });
 
 // Transactions table - uses UUID PK (high write rate, avoids hotspots)
@@ -11,6 +13,8 @@ export const transactions = pgTable("transactions", {
   id: uuid("id").defaultRandom().primaryKey(),
   payerId: integer("payer_id").notNull(),
   payeeId: integer("payee_id").notNull(),
-  amount: integer("amount").notNull(),
+  // One of these is set: amount for whole amounts, exactAmount for decimal
+  amount: integer("amount"),
+  exactAmount: numeric("exact_amount", { precision: 12, scale: 2 }),
   createdAt: timestamp("created_at").defaultNow(),
 });
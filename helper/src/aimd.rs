@@ -0,0 +1,155 @@
+/// Concurrency a sustained run starts with, and the floor it backs off to
+const MIN_CONCURRENCY: usize = 10;
+
+/// How much the concurrency target grows after each healthy second
+const INCREASE_STEP: usize = 10;
+
+/// Additive-increase controller for how many requests a sustained run keeps
+/// in flight.
+///
+/// Only dispatch failures (the Lambda couldn't be reached) count as
+/// congestion. Any response, even an error, means the Lambda is keeping up,
+/// so the target keeps growing. On congestion the target drops back to the
+/// last value that was sustained without failures.
+///
+/// With an OCC weight, conflicts count too, more gently: each second the
+/// target shrinks by that fraction of the share of replies that were OCC
+/// errors, as more concurrency only buys more conflicts past that point.
+#[derive(Debug)]
+pub struct AimdController {
+    current_target: usize,
+    last_good: usize,
+    max_in_flight: usize,
+    occ_weight: Option<f64>,
+}
+
+impl AimdController {
+    pub fn new(max_in_flight: usize) -> Self {
+        Self {
+            current_target: MIN_CONCURRENCY,
+            last_good: MIN_CONCURRENCY,
+            max_in_flight,
+            occ_weight: None,
+        }
+    }
+
+    /// Start from `initial`, capped at the maximum, rather than the
+    /// minimum, and treat it as sustained until a failure says otherwise
+    pub fn with_initial_concurrency(mut self, initial: usize) -> Self {
+        self.current_target = initial.min(self.max_in_flight);
+        self.last_good = self.current_target;
+        self
+    }
+
+    /// Also back off on OCC errors, by `weight` times their share of the
+    /// second's replies
+    pub fn with_occ_weight(mut self, weight: f64) -> Self {
+        self.occ_weight = Some(weight);
+        self
+    }
+
+    pub fn target(&self) -> usize {
+        self.current_target
+    }
+
+    /// Adjust the target given one second's successes, dispatch failures
+    /// and OCC errors, returning the new target
+    pub fn observe(&mut self, success: u64, dispatch_errors: u64, occ_errors: u64) -> usize {
+        let occ_backoff = match self.occ_weight {
+            Some(weight) if occ_errors > 0 => {
+                let occ_rate = occ_errors as f64 / (success + occ_errors) as f64;
+                (self.current_target as f64 * occ_rate * weight).round() as usize
+            }
+            _ => 0,
+        };
+        if dispatch_errors > 0 {
+            self.current_target = self.last_good.max(MIN_CONCURRENCY);
+        } else if occ_backoff > 0 {
+            self.current_target = self
+                .current_target
+                .saturating_sub(occ_backoff)
+                .max(MIN_CONCURRENCY);
+            self.last_good = self.last_good.min(self.current_target);
+        } else if success > 0 {
+            self.last_good = self.current_target;
+            self.current_target = (self.current_target + INCREASE_STEP).min(self.max_in_flight);
+        }
+        self.current_target
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn increases_on_success() {
+        let mut aimd = AimdController::new(1_000);
+        assert_eq!(aimd.observe(5, 0, 0), 20);
+        assert_eq!(aimd.observe(5, 0, 0), 30);
+    }
+
+    #[test]
+    fn increase_is_capped_at_max_in_flight() {
+        let mut aimd = AimdController::new(25);
+        assert_eq!(aimd.observe(5, 0, 0), 20);
+        assert_eq!(aimd.observe(5, 0, 0), 25);
+        assert_eq!(aimd.observe(5, 0, 0), 25);
+    }
+
+    #[test]
+    fn starts_from_initial_concurrency() {
+        let mut aimd = AimdController::new(1_000).with_initial_concurrency(500);
+        assert_eq!(aimd.target(), 500);
+        assert_eq!(aimd.observe(5, 0, 0), 510);
+        assert_eq!(aimd.observe(5, 1, 0), 500);
+    }
+
+    #[test]
+    fn initial_concurrency_is_capped_at_max_in_flight() {
+        let aimd = AimdController::new(100).with_initial_concurrency(500);
+        assert_eq!(aimd.target(), 100);
+    }
+
+    #[test]
+    fn holds_when_idle() {
+        let mut aimd = AimdController::new(1_000);
+        aimd.observe(5, 0, 0);
+        assert_eq!(aimd.observe(0, 0, 0), 20);
+        assert_eq!(aimd.target(), 20);
+    }
+
+    #[test]
+    fn backs_off_to_last_good_on_dispatch_error() {
+        let mut aimd = AimdController::new(1_000);
+        aimd.observe(5, 0, 0); // 10 -> 20
+        aimd.observe(5, 0, 0); // 20 -> 30
+        aimd.observe(5, 0, 0); // 30 -> 40
+        assert_eq!(aimd.observe(5, 1, 0), 30);
+    }
+
+    #[test]
+    fn ignores_occ_errors_by_default() {
+        let mut aimd = AimdController::new(1_000);
+        assert_eq!(aimd.observe(5, 0, 50), 20);
+    }
+
+    #[test]
+    fn backs_off_in_proportion_to_occ_rate() {
+        let mut aimd = AimdController::new(1_000).with_occ_weight(0.5);
+        for _ in 0..9 {
+            aimd.observe(5, 0, 0);
+        }
+        assert_eq!(aimd.target(), 100);
+        // Half the replies conflicted: down by a quarter
+        assert_eq!(aimd.observe(50, 0, 50), 75);
+        // Too few conflicts to shift the target: keep growing
+        assert_eq!(aimd.observe(99, 0, 1), 85);
+    }
+
+    #[test]
+    fn never_backs_off_below_minimum() {
+        let mut aimd = AimdController::new(1_000);
+        assert_eq!(aimd.observe(0, 3, 0), MIN_CONCURRENCY);
+    }
+}
@@ -0,0 +1,245 @@
+use crate::{cli::DbArgs, credentials::CredentialCache, db, progress, setup, stress};
+use anyhow::Result;
+use hdrhistogram::Histogram;
+use indicatif::{ProgressBar, ProgressStyle};
+use rand::Rng;
+use sqlx::{Pool, Postgres};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::task::JoinSet;
+
+/// What one worker measured
+struct WorkerStats {
+    latency: Histogram<u64>,
+    errors: usize,
+    occ_retries: u64,
+}
+
+/// Run `queries` balance lookups (or transfers, with `transfer`) straight
+/// against DSQL from `parallel` connections, and report latency the same
+/// way the Lambda runs do. Comparing the two shows what the Lambda adds.
+pub async fn db_bench(
+    creds: &CredentialCache,
+    db_args: &DbArgs,
+    queries: usize,
+    parallel: usize,
+    num_accounts: u32,
+    transfer: bool,
+) -> Result<()> {
+    anyhow::ensure!(parallel > 0, "--parallel must be at least 1");
+    anyhow::ensure!(
+        num_accounts >= 2 || !transfer,
+        "transfers need at least 2 accounts"
+    );
+    let pool = db::get_pool(creds, db_args).await?;
+    let kind = if transfer { "Transfer" } else { "Read" };
+
+    println!("DSQL Benchmark (no Lambda)");
+    println!("Queries: {}", queries);
+    println!("Parallel connections: {}", parallel);
+    println!("Account pool: {}", num_accounts);
+    println!("Query: {}", kind);
+    println!();
+
+    let pb = progress::bar(queries as u64);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("[{bar:40}] {pos}/{len} ({per_sec})")?
+            .progress_chars("=>-"),
+    );
+
+    let claimed = Arc::new(AtomicUsize::new(0));
+    let start = Instant::now();
+    let mut workers = JoinSet::new();
+    for _ in 0..parallel {
+        let pool = pool.clone();
+        let claimed = claimed.clone();
+        let pb = pb.clone();
+        workers.spawn(async move {
+            run_worker(&pool, &claimed, queries, num_accounts, transfer, &pb).await
+        });
+    }
+
+    let mut latency: Histogram<u64> = Histogram::new(3)?;
+    let mut errors = 0;
+    let mut occ_retries = 0;
+    while let Some(worker) = workers.join_next().await {
+        let worker = worker??;
+        latency.add(&worker.latency)?;
+        errors += worker.errors;
+        occ_retries += worker.occ_retries;
+    }
+    let elapsed = start.elapsed();
+    pb.finish_and_clear();
+
+    println!("Total queries:      {}", queries);
+    println!("Errors:             {}", errors);
+    println!("Total time:         {:.2}s", elapsed.as_secs_f64());
+    println!(
+        "Throughput:         {:.0} queries/second",
+        queries as f64 / elapsed.as_secs_f64()
+    );
+    if transfer {
+        println!("OCC retries:        {}", occ_retries);
+    }
+    println!();
+    println!("Latency (client-measured, including OCC retries):");
+    stress::print_percentiles(kind, &latency);
+    println!();
+
+    Ok(())
+}
+
+/// Time `queries` chapter 3 history lookups for random payers without the
+/// payer index, then with it, to show what the index buys
+pub async fn index_bench(
+    creds: &CredentialCache,
+    db_args: &DbArgs,
+    queries: usize,
+    num_accounts: u32,
+) -> Result<()> {
+    anyhow::ensure!(queries > 0, "--queries must be at least 1");
+    let pool = db::get_pool(creds, db_args).await?;
+    let (rows,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM transactions")
+        .fetch_one(&pool)
+        .await?;
+    println!("Timing {} history lookups over {} transactions\n", queries, rows);
+
+    sqlx::query("DROP INDEX IF EXISTS idx_tx_payer")
+        .execute(&pool)
+        .await?;
+    let without = time_history_queries(&pool, queries, num_accounts).await?;
+    setup::create_indexes(&pool).await?;
+    let with = time_history_queries(&pool, queries, num_accounts).await?;
+    println!();
+
+    println!("Latency (client-measured):");
+    stress::print_percentiles("No index", &without);
+    stress::print_percentiles("Index", &with);
+    let speedup =
+        without.value_at_quantile(0.5) as f64 / with.value_at_quantile(0.5).max(1) as f64;
+    println!("  p50 speedup:        {:.1}x", speedup);
+    println!();
+
+    Ok(())
+}
+
+/// The latency of `queries` history lookups, one after another
+async fn time_history_queries(
+    pool: &Pool<Postgres>,
+    queries: usize,
+    num_accounts: u32,
+) -> Result<Histogram<u64>> {
+    let pb = progress::bar(queries as u64);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("[{bar:40}] {pos}/{len} queries")?
+            .progress_chars("=>-"),
+    );
+    let mut latency: Histogram<u64> = Histogram::new(3)?;
+    let sql = format!(
+        "SELECT {}
+         FROM transactions
+         WHERE payer_id = $1
+         ORDER BY created_at DESC
+         LIMIT 5",
+        db::TRANSACTION_COLUMNS
+    );
+    for _ in 0..queries {
+        let payer_id = rand::thread_rng().gen_range(1..=num_accounts as i32);
+        let start = Instant::now();
+        let _: Vec<db::Transaction> = sqlx::query_as(&sql)
+            .bind(payer_id)
+            .fetch_all(pool)
+            .await?;
+        latency.record(start.elapsed().as_millis() as u64)?;
+        pb.inc(1);
+    }
+    pb.finish_and_clear();
+    Ok(latency)
+}
+
+/// Take queries off the shared count until all `queries` are claimed. The
+/// worker's connection is opened before the first one, so handshakes don't
+/// count towards latency.
+async fn run_worker(
+    pool: &Pool<Postgres>,
+    claimed: &AtomicUsize,
+    queries: usize,
+    num_accounts: u32,
+    transfer: bool,
+    pb: &ProgressBar,
+) -> Result<WorkerStats> {
+    let mut conn = pool.acquire().await?;
+    let mut stats = WorkerStats {
+        latency: Histogram::new(3)?,
+        errors: 0,
+        occ_retries: 0,
+    };
+
+    while claimed.fetch_add(1, Ordering::Relaxed) < queries {
+        let (payer_id, payee_id) = {
+            let mut rng = rand::thread_rng();
+            let payer_id = rng.gen_range(1..=num_accounts as i32);
+            let mut payee_id = rng.gen_range(1..=num_accounts as i32);
+            while transfer && payee_id == payer_id {
+                payee_id = rng.gen_range(1..=num_accounts as i32);
+            }
+            (payer_id, payee_id)
+        };
+
+        let start = Instant::now();
+        let result = if transfer {
+            run_transfer(&mut conn, payer_id, payee_id, &mut stats.occ_retries).await
+        } else {
+            sqlx::query("SELECT balance FROM accounts WHERE id = $1")
+                .bind(payer_id)
+                .fetch_optional(&mut *conn)
+                .await
+                .map(|_| ())
+        };
+        match result {
+            Ok(()) => stats.latency.record(start.elapsed().as_millis() as u64)?,
+            Err(err) => {
+                stats.errors += 1;
+                tracing::warn!("query failed: {}", err);
+            }
+        }
+        pb.inc(1);
+    }
+    Ok(stats)
+}
+
+/// Move 1 from `payer_id` to `payee_id` the way the Lambda does, retrying
+/// OCC conflicts until it commits
+async fn run_transfer(
+    conn: &mut sqlx::PgConnection,
+    payer_id: i32,
+    payee_id: i32,
+    occ_retries: &mut u64,
+) -> sqlx::Result<()> {
+    loop {
+        let result = async {
+            let mut tx = sqlx::Connection::begin(&mut *conn).await?;
+            sqlx::query("UPDATE accounts SET balance = balance - 1 WHERE id = $1")
+                .bind(payer_id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("UPDATE accounts SET balance = balance + 1 WHERE id = $1")
+                .bind(payee_id)
+                .execute(&mut *tx)
+                .await?;
+            tx.commit().await
+        }
+        .await;
+        match result {
+            Err(sqlx::Error::Database(err))
+                if err.code().as_deref() == Some(db::OCC_ERROR_CODE) =>
+            {
+                *occ_retries += 1;
+            }
+            result => return result,
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use tokio::sync::Notify;
+
+/// What a producer does with an item when the queue is already full
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum WhenFull {
+    /// Drop the oldest waiting item, so the run never waits on whatever is
+    /// falling behind, which misses what was dropped
+    DropOldest,
+    /// Hold up the run until there's room, so nothing is lost but a slow
+    /// consumer slows the load
+    Block,
+}
+
+/// A queue of at most `capacity` items between producers and a single
+/// consumer, counting what it drops, so a consumer that falls behind on a
+/// long run can't grow memory without bound
+pub struct BoundedQueue<T> {
+    items: Mutex<VecDeque<T>>,
+    capacity: usize,
+    when_full: WhenFull,
+    /// Wakes the consumer when an item is queued or producing finishes
+    queued: Notify,
+    /// Wakes blocked producers when the consumer takes an item or gives up
+    taken: Notify,
+    /// Set when no more items will be pushed
+    finished: AtomicBool,
+    /// Set when the consumer has given up
+    closed: AtomicBool,
+    dropped: AtomicUsize,
+}
+
+impl<T> BoundedQueue<T> {
+    pub fn new(capacity: usize, when_full: WhenFull) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            items: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+            when_full,
+            queued: Notify::new(),
+            taken: Notify::new(),
+            finished: AtomicBool::new(false),
+            closed: AtomicBool::new(false),
+            dropped: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn when_full(&self) -> WhenFull {
+        self.when_full
+    }
+
+    /// Queue `item`, making room as `when_full` says
+    pub async fn push(&self, item: T) {
+        loop {
+            let taken = self.taken.notified();
+            {
+                let mut items = self.items.lock().unwrap();
+                if self.closed.load(Ordering::SeqCst) {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                    return;
+                }
+                if items.len() >= self.capacity && self.when_full == WhenFull::DropOldest {
+                    items.pop_front();
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+                if items.len() < self.capacity {
+                    items.push_back(item);
+                    self.queued.notify_one();
+                    return;
+                }
+            }
+            taken.await;
+        }
+    }
+
+    /// Whether a push would go straight in, without dropping or waiting
+    pub fn has_room(&self) -> bool {
+        self.closed.load(Ordering::SeqCst) || self.items.lock().unwrap().len() < self.capacity
+    }
+
+    /// Wait until a push would go straight in
+    pub async fn room(&self) {
+        loop {
+            let taken = self.taken.notified();
+            if self.has_room() {
+                return;
+            }
+            taken.await;
+        }
+    }
+
+    /// The next item, or None once producing has finished and everything
+    /// queued has been taken. Nothing is lost if this is cancelled.
+    pub async fn pop(&self) -> Option<T> {
+        loop {
+            let queued = self.queued.notified();
+            if let Some(item) = self.items.lock().unwrap().pop_front() {
+                self.taken.notify_waiters();
+                return Some(item);
+            }
+            if self.finished.load(Ordering::SeqCst) {
+                return None;
+            }
+            queued.await;
+        }
+    }
+
+    /// No more items will be pushed; the consumer gets what's queued, then
+    /// None
+    pub fn finish(&self) {
+        self.finished.store(true, Ordering::SeqCst);
+        self.queued.notify_one();
+    }
+
+    /// The consumer has given up: drop what's queued and anything pushed
+    /// from now on, releasing blocked producers
+    pub fn close(&self) {
+        let mut items = self.items.lock().unwrap();
+        self.closed.store(true, Ordering::SeqCst);
+        self.dropped.fetch_add(items.drain(..).count(), Ordering::Relaxed);
+        self.taken.notify_waiters();
+    }
+
+    /// Items dropped so far for want of room
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn drop_oldest_keeps_the_newest() {
+        let queue = BoundedQueue::new(2, WhenFull::DropOldest);
+        for i in 0..5 {
+            queue.push(i).await;
+        }
+        queue.finish();
+        assert_eq!(queue.pop().await, Some(3));
+        assert_eq!(queue.pop().await, Some(4));
+        assert_eq!(queue.pop().await, None);
+        assert_eq!(queue.dropped(), 3);
+    }
+
+    #[tokio::test]
+    async fn close_releases_blocked_producers() {
+        let queue = std::sync::Arc::new(BoundedQueue::new(1, WhenFull::Block));
+        queue.push(1).await;
+        assert!(!queue.has_room());
+        let producer = tokio::spawn({
+            let queue = queue.clone();
+            async move { queue.push(2).await }
+        });
+        tokio::task::yield_now().await;
+        queue.close();
+        producer.await.unwrap();
+        assert_eq!(queue.dropped(), 2);
+    }
+}
@@ -1,11 +1,259 @@
+use crate::bounded::WhenFull;
+use crate::stress::{AmountDist, LoadModel, Pairing, PayeeStrategy, Workload};
 use clap::{Parser, Subcommand};
+use std::path::PathBuf;
 
 #[derive(Parser)]
 #[command(name = "helper")]
 #[command(about = "Test helper for Aurora DSQL demo")]
+#[command(after_help = "Exit status: 0 on success, 2 for bad arguments or configuration, \
+3 if the cluster or Lambda couldn't be reached, 4 if a run failed a gate (--max-p99-ms, \
+--max-error-rate, a compare limit), 5 if balances or the ledger didn't add up, 1 otherwise")]
 pub struct Args {
     #[command(subcommand)]
     pub command: Command,
+    #[command(flatten)]
+    pub db: DbArgs,
+    #[command(flatten)]
+    pub lambda: LambdaArgs,
+    #[command(flatten)]
+    pub mock: MockArgs,
+    /// Read the cluster endpoint, region, function name and pool settings
+    /// from this TOML file (default ~/.riv25-codetalk.toml, if it exists).
+    /// Flags take precedence over the file, and the file over
+    /// CLUSTER_ENDPOINT and AWS_REGION
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+    /// Keep temporary AWS credentials in this file between runs (default
+    /// $XDG_CACHE_HOME/riv25-codetalk/credentials.json), readable only by you
+    #[arg(long, global = true, num_args = 0..=1, require_equals = true, value_name = "PATH")]
+    pub credential_cache_file: Option<Option<PathBuf>>,
+    /// Don't draw progress bars, only the final results (implied when stderr
+    /// isn't a terminal)
+    #[arg(long, short, global = true)]
+    pub quiet: bool,
+    /// How log lines are written
+    #[arg(long, global = true, value_enum, default_value = "pretty")]
+    pub log_format: LogFormat,
+    /// Also write log lines to this file, in --log-format, without colours
+    #[arg(long, global = true)]
+    pub log_file: Option<PathBuf>,
+    /// Start a new --log-file every hour or day, suffixed with the date, for
+    /// runs too long for one file
+    #[arg(long, global = true, value_enum, default_value = "never", requires = "log_file")]
+    pub log_rotation: LogRotation,
+    /// Tokio worker threads. The default of 64 is what earlier versions
+    /// hardcoded, so results stay comparable; size it to the machine
+    /// instead, as too few threads delay replies and too many contend, and
+    /// either skews client-side latency
+    #[arg(long, global = true, default_value = "64", value_parser = clap::value_parser!(u32).range(1..))]
+    pub worker_threads: u32,
+    /// Give up on any command after this many seconds. Load runs stop
+    /// launching and drain as on Ctrl-C, printing what they measured; if the
+    /// command still hasn't finished a little later, it's cut off
+    #[arg(long, global = true, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub max_runtime: Option<u64>,
+    /// Label the run KEY=VALUE in its summary, CSVs and uploaded results,
+    /// to sort archived runs by later; repeat for more. Letters, digits and
+    /// - _ . only, plus / and : in values
+    #[arg(long = "tag", global = true, value_name = "KEY=VALUE", value_parser = parse_tag)]
+    pub tags: Vec<(String, String)>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogRotation {
+    Never,
+    Hourly,
+    Daily,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogFormat {
+    /// Human-readable lines
+    Pretty,
+    /// One JSON object per line, with fields such as `error` and `code` kept
+    /// separate for log pipelines
+    Json,
+}
+
+/// Simulated Lambda for working offline, accepted by every command
+#[derive(Parser, Clone, Debug)]
+pub struct MockArgs {
+    /// Answer invocations from a local simulator instead of calling Lambda
+    #[arg(long, global = true)]
+    pub mock: bool,
+    /// Simulated latency of every transaction attempt, before the tail
+    #[arg(long, global = true, default_value = "5")]
+    pub mock_latency_ms: u64,
+    /// Mean of the exponentially distributed latency added to each attempt
+    #[arg(long, global = true, default_value = "10")]
+    pub mock_latency_tail_ms: u64,
+    /// Chance that a simulated transaction attempt hits an OCC conflict and
+    /// retries
+    #[arg(long, global = true, default_value = "0.05", value_parser = parse_ratio)]
+    pub mock_occ_probability: f64,
+}
+
+/// Lambda SDK client settings, accepted by every command
+#[derive(Parser, Clone, Debug)]
+pub struct LambdaArgs {
+    /// How long the SDK waits to establish a connection to Lambda
+    #[arg(long, global = true, default_value = "30000")]
+    pub connect_timeout_ms: u64,
+    /// Give up on an invocation after this long, including SDK retries
+    #[arg(long, global = true)]
+    pub operation_timeout_ms: Option<u64>,
+    /// Send each transfer a `deadline_ms` a little under
+    /// --operation-timeout-ms, so a Lambda that honours it stops retrying
+    /// OCC conflicts once the client would have given up anyway
+    #[arg(long, global = true, requires = "operation_timeout_ms")]
+    pub propagate_deadline: bool,
+    /// After a run, print the average time each invocation spent
+    /// serializing, signing, waiting on the Lambda and parsing the reply,
+    /// next to the Lambda's own execution time, to see whether the client
+    /// or the server dominates
+    #[arg(long, global = true)]
+    pub phase_breakdown: bool,
+    /// Give up on a single attempt of an invocation after this long
+    #[arg(long, global = true)]
+    pub operation_attempt_timeout_ms: Option<u64>,
+    /// Attempts the SDK makes per invocation, retrying failures it considers
+    /// transient. Set to 1 for cleaner load-test numbers: every dispatch
+    /// failure is then one failed request, rather than several hidden ones
+    /// that also inflate latency
+    #[arg(long, global = true, default_value = "3", value_parser = clap::value_parser!(u32).range(1..))]
+    pub max_sdk_attempts: u32,
+    /// Send Lambda invocations here instead of the regional AWS endpoint,
+    /// e.g. http://localhost:4566 for LocalStack (the SDK also honours
+    /// AWS_ENDPOINT_URL)
+    #[arg(long, global = true)]
+    pub endpoint_url: Option<String>,
+    /// Write the request and raw response of each failed invocation to this
+    /// directory, one pair of timestamped files per invocation
+    #[arg(long, global = true)]
+    pub dump_payloads: Option<PathBuf>,
+    /// With --dump-payloads, dump every invocation rather than just failed
+    /// ones. This writes two files per call, so beware of long runs
+    #[arg(long, global = true, requires = "dump_payloads")]
+    pub dump_all: bool,
+    /// Gzip request payloads larger than this many bytes, sending them
+    /// base64-encoded in a `{"content_encoding": "gzip", "payload": ...}`
+    /// envelope. Only for Lambdas (and --mock) that unwrap it
+    #[arg(long, global = true)]
+    pub compress_over: Option<usize>,
+    /// Skip the Lambda and run its transactions against DSQL from here,
+    /// retrying OCC conflicts up to 100 times, to compare the two
+    /// architectures
+    #[arg(long, global = true, conflicts_with = "mock")]
+    pub direct: bool,
+    /// Function to invoke (default reinvent-dat401)
+    #[arg(long, global = true)]
+    pub function_name: Option<String>,
+    /// Split invocations between these functions, to compare two
+    /// implementations under the same load. Each takes turns in proportion
+    /// to an optional weight, e.g. old=3,new=1, and stats are broken down
+    /// by function
+    #[arg(
+        long,
+        global = true,
+        value_delimiter = ',',
+        value_parser = parse_function,
+        conflicts_with_all = ["mock", "direct", "function_name"]
+    )]
+    pub functions: Vec<FunctionWeight>,
+    /// Send the run id and workload with each invocation as the Lambda
+    /// client context (`context.clientContext.custom`), so the function's
+    /// logs can be matched to a run without changing the request payload
+    #[arg(long, global = true, conflicts_with_all = ["mock", "direct"])]
+    pub client_context: bool,
+}
+
+/// A function to invoke and its share of the invocations
+#[derive(Clone, Debug)]
+pub struct FunctionWeight {
+    pub name: String,
+    pub weight: u32,
+}
+
+/// DSQL connection settings, accepted by every command
+#[derive(Parser, Clone, Debug)]
+pub struct DbArgs {
+    /// Times to retry connecting, backing off exponentially from 200ms,
+    /// while the cluster is unavailable (e.g. still starting up)
+    #[arg(long, global = true, default_value = "5")]
+    pub db_connect_retries: u32,
+    /// DSQL cluster endpoint to connect to
+    #[arg(long, global = true)]
+    pub cluster_endpoint: Option<String>,
+    /// AWS region of the cluster and function (default us-west-2 for the
+    /// cluster, the AWS profile's for the function)
+    #[arg(long, global = true)]
+    pub region: Option<String>,
+    /// Most connections each DSQL pool opens
+    #[arg(long, global = true, value_parser = clap::value_parser!(u32).range(1..))]
+    pub db_max_connections: Option<u32>,
+    /// Fail a query that waits this long for a free pool connection, so an
+    /// exhausted pool shows up as errors rather than latency (with
+    /// --direct, as SQLSTATE 53300)
+    #[arg(long, global = true)]
+    pub acquire_timeout_ms: Option<u64>,
+    /// Abort any query running longer than this, via each connection's
+    /// statement_timeout
+    #[arg(long, global = true)]
+    pub statement_timeout_ms: Option<u64>,
+    /// Schema to find the tables in instead of public, set by self-test to
+    /// keep its tables apart
+    #[arg(skip)]
+    pub schema: Option<String>,
+    /// Regional endpoints of a multi-region cluster as HOST=REGION pairs,
+    /// e.g. host1=us-west-2,host2=us-east-1. Load runs spread requests
+    /// round-robin over the function (or --direct pool) in each region and
+    /// break stats down by region; other commands use the first endpoint.
+    /// Defaults to --cluster-endpoint in --region
+    #[arg(long, global = true, value_delimiter = ',', value_parser = parse_endpoint)]
+    pub endpoints: Vec<Endpoint>,
+    /// Other endpoints of the cluster in the first endpoint's region, tried
+    /// in order when connecting to it fails, so killing one mid-demo
+    /// doesn't abort the command
+    #[arg(long, global = true, value_delimiter = ',', value_name = "HOSTS")]
+    pub failover_endpoints: Vec<String>,
+    /// How strictly to check the database's TLS: require only encrypts,
+    /// the verify modes also check its certificate
+    #[arg(long, global = true, value_enum, default_value = "require")]
+    pub ssl_mode: SslMode,
+    /// CA certificate (PEM) to verify the database against, instead of the
+    /// system's, for --ssl-mode verify-ca or verify-full
+    #[arg(long, global = true)]
+    pub ssl_root_cert: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum SslMode {
+    /// Connect without TLS
+    Disable,
+    /// Use TLS if the server offers it
+    Prefer,
+    /// Use TLS without checking the certificate
+    Require,
+    /// Use TLS and check the certificate is signed by a trusted CA
+    VerifyCa,
+    /// As verify-ca, and check the certificate names the host
+    VerifyFull,
+}
+
+/// A DSQL cluster endpoint and the region it's in
+#[derive(Clone, Debug)]
+pub struct Endpoint {
+    pub host: String,
+    pub region: String,
+}
+
+/// Where `--s3-results` uploads to
+#[derive(Clone, Debug)]
+pub struct S3Location {
+    pub bucket: String,
+    /// Key prefix, empty or ending in `/`
+    pub prefix: String,
 }
 
 #[derive(Subcommand)]
@@ -14,15 +262,245 @@ pub enum Command {
     TestChapter {
         #[arg(short, long)]
         chapter: u32,
+        /// Save the stress summary of chapters 2 and 4 as JSON
+        #[arg(long)]
+        summary_json: Option<PathBuf>,
+        /// Set up the schema with 1000 accounts first if it doesn't exist
+        #[arg(long)]
+        ensure_schema: bool,
+    },
+    /// List the chapters test-chapter can run, and what each exercises
+    Chapters,
+    /// Send one chosen transfer through the Lambda, optionally several
+    /// times, printing each reply in full
+    Transfer {
+        /// Account to debit
+        #[arg(long, default_value = "1")]
+        payer: u32,
+        /// Account to credit
+        #[arg(long, default_value = "2")]
+        payee: u32,
+        /// Amount to move
+        #[arg(long, default_value = "10")]
+        amount: u32,
+        /// Times to send it, one after another
+        #[arg(long, default_value = "1", value_parser = clap::value_parser!(u32).range(1..))]
+        repeat: u32,
+    },
+    /// Check that the Lambda and DSQL are both reachable
+    HealthCheck,
+    /// Smoke-test the whole pipeline in a throwaway schema: set it up, run
+    /// a short burst of transfers, check conservation and the history
+    /// query, then drop it, printing whether each stage passed. Transfers
+    /// run from here, as with --direct, since the Lambdas only use the
+    /// public tables
+    SelfTest {
+        /// Accounts to create
+        #[arg(long, default_value = "10", value_parser = clap::value_parser!(u32).range(2..))]
+        accounts: u32,
+        /// Transfers in the burst
+        #[arg(long, default_value = "100")]
+        transfers: usize,
+        /// Transfers in flight at once
+        #[arg(short, long, default_value = "10")]
+        parallel: usize,
+    },
+    /// Estimate what a run of `--total` invocations would cost, scaling up
+    /// the durations and retries of a few real probe transfers between
+    /// accounts 1 and 2
+    EstimateCost {
+        /// Invocations the run would make
+        #[arg(long)]
+        total: u64,
+        /// Amount each probe transfer moves
+        #[arg(long, default_value = "1")]
+        amount: u32,
+        /// Probe transfers to send, one at a time
+        #[arg(long, default_value = "5")]
+        probes: u32,
+        /// The function's configured memory, as the CDK stacks deploy it
+        #[arg(long, default_value = "512")]
+        memory_mb: u32,
+        /// DPUs one attempt at a transfer is assumed to use; check the
+        /// cluster's TotalDPU metric after a run to refine it
+        #[arg(long, default_value = "1.0")]
+        dpu_per_transfer: f64,
+    },
+    /// Time opening fresh DSQL connections, including auth token generation
+    ConnLatency {
+        /// Connections to open, one at a time
+        #[arg(long, default_value = "20")]
+        count: usize,
+    },
+    /// Time the AWS credential provider chain, bypassing the credential
+    /// cache, to see what a refresh costs the request that triggers it
+    CredBench {
+        /// Fetches to time, one at a time
+        #[arg(long, default_value = "20")]
+        iterations: usize,
+    },
+    /// Time queries sent straight to DSQL, to compare with the Lambda runs
+    /// and see how much latency the Lambda adds
+    DbBench {
+        /// Queries to run
+        #[arg(long, default_value = "1000")]
+        queries: usize,
+        /// Connections running queries at once
+        #[arg(short, long, default_value = "10")]
+        parallel: usize,
+        /// Number of accounts to pick from
+        #[arg(long, default_value = "1000")]
+        accounts: u32,
+        /// Run the Lambda's transfer transaction (two UPDATEs, moving 1)
+        /// instead of a balance lookup
+        #[arg(long)]
+        transfer: bool,
+    },
+    /// Time the chapter 3 history query without the payer index, then
+    /// create the index and time it again. Drops the index first if it
+    /// exists
+    IndexBench {
+        /// History lookups to time on each side
+        #[arg(long, default_value = "100")]
+        queries: usize,
+        /// Number of accounts to pick payers from
+        #[arg(long, default_value = "1000")]
+        accounts: u32,
     },
     /// Setup database schema
     Setup {
         #[arg(long, default_value = "1000")]
         accounts: u32,
+        /// Also index transactions by payer, for the chapter 3 history query
+        #[arg(long)]
+        with_indexes: bool,
+        /// Fail rather than delete any accounts or transactions already
+        /// there, to guard a shared cluster
+        #[arg(long)]
+        if_empty: bool,
+        /// Print the SQL setup would run, without connecting
+        #[arg(long)]
+        dry_run: bool,
     },
+    /// Apply any schema migrations not yet applied, without touching data
+    Migrate,
     /// Setup Chapter 4 (1M accounts)
-    SetupCh04,
-    /// Run sustained load until Ctrl-C
+    SetupCh04 {
+        /// Print the SQL setup would run, without connecting
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Replace the accounts table's rows with those of an `id,balance` CSV
+    ImportAccounts {
+        /// CSV file to read, optionally starting with an `id,balance` header
+        path: PathBuf,
+    },
+    /// Delete old rows from the transactions table, in batches
+    PruneTransactions {
+        /// Delete transactions created more than this many hours ago
+        #[arg(long)]
+        older_than_hours: u32,
+    },
+    /// Check every balance against the starting balance and, with --apply,
+    /// reset those that drifted, keeping the transactions table
+    Repair {
+        /// Balance every account should hold (as created by `setup`)
+        #[arg(long, default_value = "100")]
+        initial_balance: i64,
+        /// Reset the drifted balances; without this, only report them
+        #[arg(long)]
+        apply: bool,
+    },
+    /// Set every account's balance back to the starting balance, keeping
+    /// the schema and the transactions table
+    ResetBalances {
+        /// Balance every account should hold (as created by `setup`)
+        #[arg(long, default_value = "100")]
+        initial_balance: i64,
+    },
+    /// Drop the accounts, transactions and schema_migrations tables
+    Teardown {
+        /// Confirm that the tables should be dropped
+        #[arg(long)]
+        yes: bool,
+        /// Print the tables teardown would drop, without connecting
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Run a fixed number of invocations as fast as possible
+    Stress {
+        /// Total invocations to send
+        #[arg(short, long, default_value = "10000")]
+        total: usize,
+        /// Maximum requests in flight at once
+        #[arg(
+            short,
+            long,
+            default_value = "1000",
+            value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+        )]
+        parallel: usize,
+        /// Start at most this many invocations per second, limited the way
+        /// sustained-load limits them
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+        rate: Option<u32>,
+        /// Spread the first --parallel launches evenly over this many
+        /// milliseconds instead of starting them all at once
+        #[arg(long)]
+        ramp_in: Option<u64>,
+        /// Number of accounts to pick from
+        #[arg(short, long, default_value = "1000")]
+        accounts: u32,
+        #[command(flatten)]
+        workload: WorkloadArgs,
+        #[command(flatten)]
+        run: RunArgs,
+        /// Lambda clients (each with its own HTTP connection pool) to spread
+        /// requests across
+        #[arg(long, default_value = "16")]
+        clients: usize,
+        /// Save the final stats as JSON (with --repeat, one file per run,
+        /// numbered like summary-1.json)
+        #[arg(long)]
+        summary_json: Option<PathBuf>,
+        /// Run the same test this many times back to back, then print each
+        /// run's results and their mean and standard deviation
+        #[arg(long, default_value = "1", value_parser = clap::value_parser!(u32).range(1..))]
+        repeat: u32,
+        /// Print the function, region, accounts and load the run would use,
+        /// without invoking anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Send the transfers recorded in a CSV, such as one written by
+    /// export-transactions, at their recorded times or in order
+    Replay {
+        /// CSV with payer_id, payee_id and amount columns, and optionally
+        /// created_at to replay at the recorded inter-arrival times
+        path: PathBuf,
+        /// Send at most this many transfers per second, ignoring any
+        /// recorded times
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+        rate: Option<u32>,
+        /// Maximum requests in flight at once
+        #[arg(
+            short,
+            long,
+            default_value = "100",
+            value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+        )]
+        parallel: usize,
+        #[command(flatten)]
+        run: RunArgs,
+        /// Lambda clients (each with its own HTTP connection pool) to spread
+        /// requests across
+        #[arg(long, default_value = "16")]
+        clients: usize,
+        /// Save the final stats as JSON
+        #[arg(long)]
+        summary_json: Option<PathBuf>,
+    },
+    /// Run sustained load until Ctrl-C, or through the phases of a scenario
     SustainedLoad {
         /// Target invocations per second
         #[arg(short = 'i', long, default_value = "100")]
@@ -30,5 +508,653 @@ pub enum Command {
         /// Number of accounts to use for random transfers
         #[arg(short, long, default_value = "1000")]
         accounts: u32,
+        #[command(flatten)]
+        workload: WorkloadArgs,
+        /// Take the rate phases, accounts and workload from a TOML scenario file
+        #[arg(long, conflicts_with_all = ["invocations_per_sec", "accounts", "workload", "read_ratio", "amount", "max_amount", "amount_dist", "seed", "duplicate_ratio", "hot_pairs", "hops", "shards", "cross_shard_ratio", "batch_size"])]
+        scenario: Option<PathBuf>,
+        /// Ramp the rate linearly from --invocations-per-sec up to this rate,
+        /// then stop
+        #[arg(long, requires = "ramp_secs", conflicts_with = "scenario")]
+        ramp: Option<u32>,
+        /// How long the ramp takes to reach its final rate
+        #[arg(long, requires = "ramp")]
+        ramp_secs: Option<u64>,
+        /// Shape of the rate over time
+        #[arg(long, value_enum, default_value = "constant", conflicts_with_all = ["scenario", "ramp"])]
+        pattern: LoadPattern,
+        /// Rate outside the spike of a step pattern
+        #[arg(long, required_if_eq("pattern", "step"))]
+        baseline: Option<u32>,
+        /// Rate during the spike of a step pattern
+        #[arg(long, required_if_eq("pattern", "step"))]
+        spike: Option<u32>,
+        /// Seconds into the run at which the spike starts
+        #[arg(long, required_if_eq("pattern", "step"))]
+        spike_at: Option<u64>,
+        /// How many seconds the spike lasts
+        #[arg(long, required_if_eq("pattern", "step"))]
+        spike_for: Option<u64>,
+        // Boxed so the largest variant doesn't set the size of every command
+        #[command(flatten)]
+        run: Box<RunArgs>,
+        #[command(flatten)]
+        options: Box<SustainedArgs>,
+        /// Lambda clients (each with its own HTTP connection pool) to spread
+        /// requests across
+        #[arg(long, default_value = "16")]
+        clients: usize,
+        /// Save the final stats as JSON
+        #[arg(long)]
+        summary_json: Option<PathBuf>,
+        /// Print the function, region, accounts and rate the run would use,
+        /// without invoking anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Find the parallelism with the best throughput by running short bursts
+    /// at doubling parallelism
+    Sweep {
+        /// Number of accounts to pick from
+        #[arg(short, long, default_value = "1000")]
+        accounts: u32,
+        #[command(flatten)]
+        workload: WorkloadArgs,
+        /// Parallelism of the first burst
+        #[arg(long, default_value = "100")]
+        start: usize,
+        /// Highest parallelism to try
+        #[arg(long, default_value = "6400")]
+        max_parallel: usize,
+        /// How long each burst runs
+        #[arg(long, default_value = "10")]
+        step_secs: u64,
+        /// Stop once a burst improves on the best throughput so far by less
+        /// than this percentage
+        #[arg(long, default_value = "5")]
+        plateau_pct: f64,
+        /// Stop once a burst's p99 exceeds this
+        #[arg(long)]
+        p99_limit_ms: Option<u64>,
+        /// Lambda clients (each with its own HTTP connection pool) to spread
+        /// requests across
+        #[arg(long, default_value = "16")]
+        clients: usize,
+        /// Write each burst's throughput, p50, p99 and error rate to this
+        /// CSV, in ascending order of parallelism, to plot the latency knee
+        #[arg(long)]
+        curve_csv: Option<PathBuf>,
+    },
+    /// Binary-search for the highest sustained rate that meets latency and
+    /// error SLOs
+    FindMaxRate {
+        /// Highest p99 a passing rate may have
+        #[arg(long)]
+        max_p99_ms: u64,
+        /// Highest fraction of failed requests a passing rate may have
+        #[arg(long, default_value = "0.01", value_parser = parse_ratio)]
+        max_error_rate: f64,
+        /// Lowest rate to try
+        #[arg(long, default_value = "50", value_parser = clap::value_parser!(u32).range(1..))]
+        min_rate: u32,
+        /// Highest rate to try, no lower than --min-rate
+        #[arg(long, default_value = "10000", value_parser = clap::value_parser!(u32).range(1..))]
+        max_rate: u32,
+        /// Stop once the search has narrowed to this many invocations/sec
+        #[arg(long, default_value = "50")]
+        resolution: u32,
+        /// How long to hold each candidate rate
+        #[arg(long, default_value = "20")]
+        probe_secs: u64,
+        /// Number of accounts to use for random transfers
+        #[arg(short, long, default_value = "1000")]
+        accounts: u32,
+        #[command(flatten)]
+        workload: WorkloadArgs,
+        /// Lambda clients (each with its own HTTP connection pool) to spread
+        /// requests across
+        #[arg(long, default_value = "16")]
+        clients: usize,
+        /// Save the stats of the highest passing rate as JSON
+        #[arg(long)]
+        summary_json: Option<PathBuf>,
+        /// Write each probed rate's throughput, p50, p99 and error rate to
+        /// this CSV, in ascending order of rate, to plot the latency knee
+        #[arg(long)]
+        curve_csv: Option<PathBuf>,
+    },
+    /// Run the same rate twice, first with AIMD finding the concurrency and
+    /// then fixed at where AIMD settled, and compare the two
+    AimdCompare {
+        /// Target invocations per second for both runs
+        #[arg(short = 'i', long, default_value = "100")]
+        invocations_per_sec: u32,
+        /// How long each run lasts
+        #[arg(long, default_value = "60")]
+        duration_secs: u64,
+        /// Number of accounts to use for random transfers
+        #[arg(short, long, default_value = "1000")]
+        accounts: u32,
+        #[command(flatten)]
+        workload: WorkloadArgs,
+        /// Lambda clients (each with its own HTTP connection pool) to spread
+        /// requests across
+        #[arg(long, default_value = "16")]
+        clients: usize,
+    },
+    /// Print the balance of one or more accounts
+    Balance {
+        /// Accounts to look up
+        #[arg(required = true)]
+        account_ids: Vec<i32>,
+    },
+    /// List an account's most recent transactions
+    History {
+        /// Account to list
+        account_id: i32,
+        /// How many transactions to list
+        #[arg(long, default_value = "10")]
+        limit: i64,
+        /// Whether to list the account's payments, receipts or both
+        #[arg(long, value_enum, default_value = "either")]
+        role: HistoryRole,
+        /// Only list transactions created at or after this time
+        /// (e.g. 2025-12-01T09:00:00)
+        #[arg(long)]
+        since: Option<chrono::NaiveDateTime>,
+    },
+    /// Show which accounts paid most often
+    AccountStats {
+        /// How many of the hottest payers to list
+        #[arg(long, default_value = "10")]
+        top: usize,
+    },
+    /// Print the accounts and transactions row counts, and how fast
+    /// transactions are being added, every interval until stopped, to watch
+    /// a load run from the database's side
+    Watch {
+        /// Seconds between samples
+        #[arg(long, default_value = "5", value_parser = clap::value_parser!(u64).range(1..))]
+        interval: u64,
+    },
+    /// Collect the per-second counters of sustained-load runs started with
+    /// --coordinator on other machines, printing their combined throughput
+    /// and merged latency, then a summary once stopped
+    ServeCoordinator {
+        /// Address to accept workers on
+        #[arg(long, default_value = "0.0.0.0:7878")]
+        listen: String,
+    },
+    /// Export the transactions table to CSV
+    ExportTransactions {
+        /// CSV file to write
+        path: PathBuf,
+        /// Export at most this many rows
+        #[arg(long)]
+        limit: Option<usize>,
+        /// Only export transactions created at or after this time
+        /// (e.g. 2025-12-01T09:00:00)
+        #[arg(long)]
+        since: Option<chrono::NaiveDateTime>,
     },
+    /// Export every account's balance to an `id,balance` CSV, which
+    /// import-accounts reads back
+    ExportAccounts {
+        /// CSV file to write
+        path: PathBuf,
+    },
+    /// Compare two saved stress summaries
+    Compare {
+        /// Summary JSON to compare against
+        baseline: PathBuf,
+        /// Summary JSON being evaluated
+        candidate: PathBuf,
+        /// Fail if p99 regresses by more than this percentage
+        #[arg(long)]
+        max_p99_regression: Option<f64>,
+    },
+}
+
+/// Which side of a transaction `history` matches the account on
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum HistoryRole {
+    Payer,
+    Payee,
+    Either,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum LoadPattern {
+    /// Hold --invocations-per-sec
+    Constant,
+    /// Step from --baseline to --spike and back
+    Step,
+}
+
+/// Options shared by stress and sustained-load runs
+#[derive(Parser, Clone, Debug)]
+pub struct RunArgs {
+    /// Log requests slower than this and list the slowest at the end
+    #[arg(long)]
+    pub slow_threshold_ms: Option<u64>,
+    /// Stop the run and exit non-zero once more than this fraction of
+    /// requests have failed
+    #[arg(long, value_parser = parse_ratio)]
+    pub max_error_rate: Option<f64>,
+    /// Requests to complete before --max-error-rate is enforced, so a few
+    /// early failures don't abort the run
+    #[arg(long, default_value = "100")]
+    pub error_rate_min_calls: usize,
+    /// Exit non-zero if the run's overall p99 exceeds this
+    #[arg(long)]
+    pub max_p99_ms: Option<u64>,
+    /// Set up the schema with --accounts accounts first if it doesn't exist
+    #[arg(long)]
+    pub ensure_schema: bool,
+    /// What to do if --accounts is more than the accounts table holds
+    /// (not checked with --mock)
+    #[arg(long, value_enum, default_value = "error")]
+    pub accounts_check: AccountsCheck,
+    /// Write summary.json, latency.hdr and (for sustained runs)
+    /// throughput.csv to a directory named after the run id in here, even if
+    /// the run is interrupted or aborted
+    #[arg(long)]
+    pub results_dir: Option<PathBuf>,
+    /// Once the run finishes, upload its --results-dir artifacts to
+    /// s3://BUCKET/PREFIX/<run id>/, using the same credentials as the rest
+    /// of the run
+    #[arg(long, requires = "results_dir", value_parser = parse_s3_location)]
+    pub s3_results: Option<S3Location>,
+    /// Check afterwards that the run added exactly one transactions row per
+    /// successful transfer (assumes nothing else writes to the table; not
+    /// checked with --mock)
+    #[arg(long)]
+    pub verify_ledger: bool,
+    /// Check afterwards that accounts 1 to --accounts still hold
+    /// --initial-balance each between them, printing PASS or FAIL and
+    /// exiting non-zero on FAIL (assumes the accounts were freshly set up;
+    /// not checked with --mock)
+    #[arg(long)]
+    pub assert_conservation: bool,
+    /// Significant figures kept by the latency histograms; fewer saves
+    /// memory on long runs, more gives finer percentiles
+    #[arg(long, default_value = "3", value_parser = clap::value_parser!(u8).range(1..=5))]
+    pub hdr_sigfigs: u8,
+    /// Resend a request up to this many times, after a short jittered
+    /// backoff, when it fails to dispatch or times out, before counting it
+    /// as an error. On top of the SDK's own retries (--max-sdk-attempts);
+    /// transfers keep their idempotency key, so a resend can't apply twice
+    #[arg(long, default_value = "0")]
+    pub dispatch_retries: u32,
+    /// Stop sending new requests, and drain those in flight, once the
+    /// Lambda has reported more than this many OCC retries in total
+    #[arg(long)]
+    pub retry_budget: Option<u64>,
+    /// Every this many seconds during the run, check the accounts still
+    /// hold the balance total they started with, warning at once if money
+    /// appeared or vanished. Each check is a full-table SUM competing with
+    /// the workload (not checked with --mock)
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub invariant_check_interval: Option<u64>,
+    /// Once the run stops sending, wait at most this many seconds for
+    /// requests still in flight, then abandon them and report them as
+    /// incomplete (waits indefinitely by default)
+    #[arg(long)]
+    pub drain_timeout: Option<u64>,
+    /// Before the run, open this many connections in each --direct
+    /// database pool, so the first requests don't pay to connect
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub prewarm: Option<u32>,
+    /// Leave the first this many completed invocations out of the stats,
+    /// so cold starts don't skew them; time is measured from the last one
+    #[arg(long, default_value = "0")]
+    pub warmup_invocations: usize,
+    /// Skip the few transfers sent before the run to check the Lambda's
+    /// replies have the expected shape
+    #[arg(long)]
+    pub no_probe: bool,
+    /// Redraw the progress bars at most every 50ms (or every 1000 requests)
+    /// rather than on every completion, so at very high rates drawing them
+    /// doesn't take time from sending requests
+    #[arg(long)]
+    pub coalesce_progress: bool,
+    /// Add the latencies saved in these latency.hdr files, as --results-dir
+    /// writes them, to this run's before working out its percentiles, so
+    /// interrupted or split runs add up to one distribution
+    #[arg(long, num_args = 1.., value_name = "PATH")]
+    pub merge_hdr: Vec<PathBuf>,
+    /// Count OCC conflicts (SQLSTATE 40001) as successes in the success
+    /// rate and --max-error-rate, as they're expected under contention;
+    /// they're still counted and reported as OCC conflicts
+    #[arg(long)]
+    pub occ_as_success: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum AccountsCheck {
+    /// Refuse to start
+    Error,
+    /// Warn and use only the accounts that exist
+    Clamp,
+    /// Don't query the database
+    Skip,
+}
+
+impl Default for RunArgs {
+    /// The same defaults the CLI uses, for callers that don't parse arguments
+    fn default() -> Self {
+        Self::parse_from(["run"])
+    }
+}
+
+/// Options specific to sustained-load runs
+#[derive(Parser, Clone, Debug)]
+pub struct SustainedArgs {
+    /// Write per-second throughput and latency to this CSV file
+    #[arg(long)]
+    pub throughput_csv: Option<PathBuf>,
+    /// Report the rate at which one second's p99 first exceeds this
+    #[arg(long)]
+    pub p99_threshold_ms: Option<u64>,
+    /// Whether requests wait for a free slot (closed) or are scheduled at
+    /// the target rate with latency measured from their intended send time
+    /// (open)
+    #[arg(long, value_enum, default_value = "closed")]
+    pub model: LoadModel,
+    /// Also publish each second's throughput, errors, OCC errors and p99
+    /// as CloudWatch metrics in this namespace, in the cluster's region, to
+    /// chart next to DSQL's own
+    #[arg(long, value_name = "NAMESPACE")]
+    pub cloudwatch_namespace: Option<String>,
+    /// Print each second's throughput and latency to stdout as it happens,
+    /// instead of the spinner
+    #[arg(long, value_enum)]
+    pub stream: Option<StreamFormat>,
+    /// Stop after sending this many invocations, draining those in flight
+    #[arg(long)]
+    pub max_calls: Option<usize>,
+    /// Hold this many requests in flight instead of letting AIMD find the
+    /// concurrency, still sending no faster than the target rate
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub fixed_concurrency: Option<u32>,
+    /// Concurrency AIMD starts from, and falls back to until it has found
+    /// a better one, instead of 10; capped at the most the rate could need
+    #[arg(
+        long,
+        conflicts_with = "fixed_concurrency",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    pub initial_concurrency: Option<u32>,
+    /// Let OCC errors back AIMD off too, not just dispatch failures, for
+    /// workloads where conflicts mean the run is past useful capacity
+    #[arg(long, conflicts_with = "fixed_concurrency")]
+    pub occ_aware_aimd: bool,
+    /// With --occ-aware-aimd, the fraction of the OCC error rate to cut the
+    /// concurrency target by each second: 0.5 cuts it by 10% when 20% of
+    /// replies conflict
+    #[arg(long, default_value = "0.5", value_parser = parse_ratio, requires = "occ_aware_aimd")]
+    pub occ_weight: f64,
+    /// Instead of the spinner, print a plain one-line summary every this
+    /// many seconds, for log viewers that garble carriage returns
+    #[arg(long, conflicts_with = "stream", value_parser = clap::value_parser!(u64).range(1..))]
+    pub progress_interval: Option<u64>,
+    /// Chart the last minute of throughput in the spinner line, for demos
+    #[arg(long, conflicts_with_all = ["stream", "progress_interval"])]
+    pub sparkline: bool,
+    /// Also stream each second's counters and latency to a
+    /// serve-coordinator at HOST:PORT, which combines several machines'
+    /// runs into one
+    #[arg(long, value_name = "HOST:PORT")]
+    pub coordinator: Option<String>,
+    /// Seconds of reports to hold for a --coordinator that's falling
+    /// behind, bounding the memory a slow one can take
+    #[arg(long, default_value = "60", value_parser = clap::value_parser!(u32).range(1..))]
+    pub coordinator_buffer: u32,
+    /// What to do with a report when --coordinator-buffer is full: drop
+    /// the oldest waiting one, or stop recording results until there's
+    /// room, which with --completion-when-full block holds up the run
+    #[arg(long, value_enum, default_value = "drop-oldest")]
+    pub coordinator_when_full: WhenFull,
+    /// Finished requests to hold for the stats task when it falls behind,
+    /// bounding the memory a long run can take
+    #[arg(long, default_value = "100000", value_parser = clap::value_parser!(u32).range(1..))]
+    pub completion_buffer: u32,
+    /// What to do with a finished request when --completion-buffer is
+    /// full: drop the oldest waiting one, leaving it out of the stats, or
+    /// stop sending requests until there's room
+    #[arg(long, value_enum, default_value = "block")]
+    pub completion_when_full: WhenFull,
+    /// Seconds of latency the spinner and --progress-interval lines show
+    /// p50 and p99 over, so they read as "right now" rather than the whole
+    /// run. The final stats, --stream and --throughput-csv are unaffected
+    #[arg(long, default_value = "10", value_parser = clap::value_parser!(u32).range(1..))]
+    pub latency_window: u32,
+    /// Write the throughput, p50, p99 and error rate at each rate the
+    /// schedule offered to this CSV, one row per rate (each second of a
+    /// --ramp, each phase of a scenario), to plot the latency knee
+    #[arg(long)]
+    pub curve_csv: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum StreamFormat {
+    /// One JSON object per line, with the same fields as --throughput-csv
+    Jsonl,
+}
+
+impl Default for SustainedArgs {
+    /// The same defaults the CLI uses, for callers that don't parse arguments
+    fn default() -> Self {
+        Self::parse_from(["sustained"])
+    }
+}
+
+/// Options controlling which requests a stress run generates
+#[derive(Parser, Clone, Debug)]
+pub struct WorkloadArgs {
+    /// Kind of request to send
+    #[arg(short, long, value_enum, default_value = "transfer")]
+    pub workload: Workload,
+    /// Send a mix of balance lookups and transfers, with this fraction of reads
+    #[arg(long, value_parser = parse_ratio, conflicts_with = "workload")]
+    pub read_ratio: Option<f64>,
+    /// Amount moved by each transfer
+    #[arg(long, default_value = "1")]
+    pub amount: u32,
+    /// Pick each transfer's amount uniformly from `--amount` up to this, or
+    /// cap the other `--amount-dist` distributions at it
+    #[arg(long)]
+    pub max_amount: Option<u32>,
+    /// How transfer amounts are distributed (uniform if `--max-amount` is
+    /// given, otherwise fixed)
+    #[arg(long, value_enum)]
+    pub amount_dist: Option<AmountDist>,
+    /// Send amounts with cents, as decimal strings such as "12.34": each
+    /// amount drawn becomes one from just over a unit less up to it (1
+    /// anywhere from 0.01 to 1.00). These move the NUMERIC exact_balance
+    /// instead of balance, to catch rounding whole amounts can't. Needs the
+    /// chapter 4 Lambda, --direct or --mock
+    #[arg(long)]
+    pub amount_decimal: bool,
+    /// How transfer payers and payees are paired up
+    #[arg(long, value_enum, default_value = "random")]
+    pub pairing: Pairing,
+    /// Where each transfer's payee is relative to its payer. Adjacent and
+    /// fixed-offset payees ignore `--shards`, and concentrate contention
+    /// on predictable ranges of accounts
+    #[arg(long, value_enum, default_value = "random")]
+    pub payee_strategy: PayeeStrategy,
+    /// How far above the payer `--payee-strategy fixed-offset` pays,
+    /// wrapping past the last account
+    #[arg(long, default_value = "1", value_parser = clap::value_parser!(u32).range(1..))]
+    pub payee_offset: u32,
+    /// Avoid picking payers whose balance would go negative, using an
+    /// approximate local view of balances
+    #[arg(long)]
+    pub no_overdraw: bool,
+    /// Balance every account starts with (as created by `setup`)
+    #[arg(long, default_value = "100")]
+    pub initial_balance: i64,
+    /// Seed for account and amount selection, to replay the same requests
+    /// (random if not given; the seed used is printed at the start)
+    #[arg(long)]
+    pub seed: Option<u64>,
+    /// Fraction of `--workload duplicate` transfers that resend an earlier
+    /// transfer's idempotency key
+    #[arg(long, default_value = "0.1", value_parser = parse_ratio)]
+    pub duplicate_ratio: f64,
+    /// Pairs of accounts `--workload bidirectional` transfers within
+    #[arg(long, default_value = "1", value_parser = clap::value_parser!(u32).range(1..))]
+    pub hot_pairs: u32,
+    /// Transfers in each `--workload chain` chain
+    #[arg(long, default_value = "3", value_parser = clap::value_parser!(u32).range(1..))]
+    pub hops: u32,
+    /// Split the accounts into this many contiguous shards and take turns
+    /// between them, keeping each request within one shard, to model a
+    /// partitioned workload (random pairing only)
+    #[arg(long, default_value = "1", value_parser = clap::value_parser!(u32).range(1..))]
+    pub shards: u32,
+    /// Fraction of sharded transfers whose payee is in a different shard
+    #[arg(long, default_value = "0", value_parser = parse_ratio)]
+    pub cross_shard_ratio: f64,
+    /// Pack this many transfers into each invocation as a `{"transfers":
+    /// [...]}` batch, for Lambdas that accept one, to amortise the invoke
+    /// overhead. Reads still go alone; stats count every transfer
+    #[arg(long, default_value = "1", value_parser = clap::value_parser!(u32).range(1..))]
+    pub batch_size: u32,
+}
+
+impl WorkloadArgs {
+    /// `--amount-dist`, or what `--amount` and `--max-amount` imply
+    pub fn amount_dist(&self) -> AmountDist {
+        match (self.amount_dist, self.max_amount) {
+            (Some(dist), _) => dist,
+            (None, Some(_)) => AmountDist::Uniform,
+            (None, None) => AmountDist::Fixed,
+        }
+    }
+
+    /// Whether any of the requests are transfers
+    pub fn sends_transfers(&self) -> bool {
+        self.read_ratio.is_some() || !matches!(self.workload, Workload::Read | Workload::Greeting)
+    }
+
+    pub fn describe(&self) -> String {
+        let kind = match self.read_ratio {
+            Some(ratio) => format!("Mixed ({:.0}% reads)", ratio * 100.0),
+            None if self.workload == Workload::Duplicate => format!(
+                "Duplicate ({:.0}% resent)",
+                self.duplicate_ratio * 100.0
+            ),
+            None if self.workload == Workload::Bidirectional => {
+                format!("Bidirectional ({} hot pairs)", self.hot_pairs)
+            }
+            None if self.workload == Workload::Chain => format!("Chain ({} hops)", self.hops),
+            None => format!("{:?}", self.workload),
+        };
+        let kind = match self.amount_dist {
+            Some(dist @ (AmountDist::Exponential | AmountDist::Pareto)) => {
+                format!("{}, {:?} amounts", kind, dist)
+            }
+            _ => kind,
+        };
+        let kind = if self.amount_decimal {
+            format!("{}, decimal amounts", kind)
+        } else {
+            kind
+        };
+        let kind = match self.pairing {
+            Pairing::Random if self.shards > 1 => format!(
+                "{}, {} shards ({:.0}% cross-shard)",
+                kind,
+                self.shards,
+                self.cross_shard_ratio * 100.0
+            ),
+            Pairing::Random => kind,
+            Pairing::Sequential => format!("{}, sequential pairing", kind),
+        };
+        let kind = match self.payee_strategy {
+            PayeeStrategy::Random => kind,
+            PayeeStrategy::Adjacent => format!("{}, adjacent payees", kind),
+            PayeeStrategy::FixedOffset => {
+                format!("{}, payees {} accounts up", kind, self.payee_offset)
+            }
+        };
+        match self.batch_size {
+            1 => kind,
+            n => format!("{}, {} transfers per invocation", kind, n),
+        }
+    }
+}
+
+impl Default for WorkloadArgs {
+    /// The same defaults the CLI uses, for callers that don't parse arguments
+    fn default() -> Self {
+        Self::parse_from(["workload"])
+    }
+}
+
+fn parse_function(s: &str) -> Result<FunctionWeight, String> {
+    let (name, weight) = match s.split_once('=') {
+        Some((name, weight)) => (name, weight.parse().map_err(|e| format!("{e}"))?),
+        None => (s, 1),
+    };
+    if name.is_empty() || weight == 0 {
+        return Err(format!("{s} is not NAME or NAME=WEIGHT with a positive weight"));
+    }
+    Ok(FunctionWeight {
+        name: name.to_string(),
+        weight,
+    })
+}
+
+fn parse_endpoint(s: &str) -> Result<Endpoint, String> {
+    match s.split_once('=') {
+        Some((host, region)) if !host.is_empty() && !region.is_empty() => Ok(Endpoint {
+            host: host.to_string(),
+            region: region.to_string(),
+        }),
+        _ => Err(format!("{s} is not HOST=REGION")),
+    }
+}
+
+fn parse_tag(s: &str) -> Result<(String, String), String> {
+    let Some((key, value)) = s.split_once('=') else {
+        return Err(format!("{s} is not KEY=VALUE"));
+    };
+    let plain = |c: char| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.');
+    if key.is_empty() || !key.chars().all(plain) {
+        return Err(format!("tag key {key:?} must be letters, digits, - _ or ."));
+    }
+    if value.is_empty() || !value.chars().all(|c| plain(c) || matches!(c, '/' | ':')) {
+        return Err(format!("tag value {value:?} must be letters, digits, - _ . / or :"));
+    }
+    Ok((key.to_string(), value.to_string()))
+}
+
+fn parse_s3_location(s: &str) -> Result<S3Location, String> {
+    let Some(path) = s.strip_prefix("s3://") else {
+        return Err(format!("{s} is not an s3:// URI"));
+    };
+    let (bucket, prefix) = path.split_once('/').unwrap_or((path, ""));
+    if bucket.is_empty() {
+        return Err(format!("{s} has no bucket"));
+    }
+    let prefix = match prefix.trim_end_matches('/') {
+        "" => String::new(),
+        prefix => format!("{prefix}/"),
+    };
+    Ok(S3Location {
+        bucket: bucket.to_string(),
+        prefix,
+    })
+}
+
+fn parse_ratio(s: &str) -> Result<f64, String> {
+    let ratio: f64 = s.parse().map_err(|e| format!("{e}"))?;
+    if (0.0..=1.0).contains(&ratio) {
+        Ok(ratio)
+    } else {
+        Err(format!("{ratio} is not within [0, 1]"))
+    }
 }
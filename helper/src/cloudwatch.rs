@@ -0,0 +1,159 @@
+use crate::bounded::{BoundedQueue, WhenFull};
+use crate::credentials::CredentialCache;
+use anyhow::Result;
+use aws_config::BehaviorVersion;
+use aws_sdk_cloudwatch::primitives::DateTime;
+use aws_sdk_cloudwatch::types::{MetricDatum, StandardUnit};
+use hdrhistogram::Histogram;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::task::JoinHandle;
+
+/// Datapoints one PutMetricData call takes at most
+const MAX_BATCH: usize = 1000;
+
+/// How long datapoints are gathered before they're sent, so a run makes a
+/// few calls a minute rather than one a second
+const FLUSH_INTERVAL: Duration = Duration::from_secs(20);
+
+/// Datapoints to hold while CloudWatch is throttling or unreachable, ten
+/// minutes' worth, before the oldest are dropped
+const BUFFER: usize = 4 * 600;
+
+/// How long the last batch may take to send once the run is over
+const FINAL_FLUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Publishes a run's per-second throughput, errors, OCC errors and p99 to
+/// CloudWatch under `--cloudwatch-namespace`, to chart next to the
+/// cluster's own metrics. Sending happens in the background and failures,
+/// throttling included, are logged and dropped, so CloudWatch can never
+/// hold up the load.
+#[derive(Clone)]
+pub struct Publisher {
+    inner: Arc<PublisherInner>,
+}
+
+struct PublisherInner {
+    queue: Arc<BoundedQueue<MetricDatum>>,
+    /// Datapoints CloudWatch didn't accept
+    rejected: Arc<AtomicUsize>,
+    /// Taken by whoever finishes the publisher
+    worker: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Publisher {
+    /// Publish to `namespace` in `region` with the cached credentials
+    pub async fn connect(creds: &CredentialCache, region: &str, namespace: &str) -> Result<Self> {
+        let credentials = creds.get_credentials().await?;
+        let config = aws_config::defaults(BehaviorVersion::latest())
+            .credentials_provider(credentials)
+            .region(aws_config::Region::new(region.to_string()))
+            .load()
+            .await;
+        let client = aws_sdk_cloudwatch::Client::new(&config);
+        let queue = Arc::new(BoundedQueue::new(BUFFER, WhenFull::DropOldest));
+        let rejected = Arc::new(AtomicUsize::new(0));
+
+        let pending = queue.clone();
+        let failed = rejected.clone();
+        let namespace = namespace.to_string();
+        let worker = tokio::spawn(async move {
+            let mut batch = Vec::new();
+            let mut flush = tokio::time::interval(FLUSH_INTERVAL);
+            flush.tick().await;
+            loop {
+                let done = tokio::select! {
+                    datum = pending.pop() => match datum {
+                        Some(datum) => {
+                            batch.push(datum);
+                            if batch.len() < MAX_BATCH {
+                                continue;
+                            }
+                            false
+                        }
+                        None => true,
+                    },
+                    _ = flush.tick() => false,
+                };
+                if !batch.is_empty() {
+                    let count = batch.len();
+                    let sent = client
+                        .put_metric_data()
+                        .namespace(&namespace)
+                        .set_metric_data(Some(std::mem::take(&mut batch)))
+                        .send()
+                        .await;
+                    if let Err(err) = sent {
+                        tracing::warn!(?err, count, "CloudWatch rejected a batch of datapoints");
+                        failed.fetch_add(count, Ordering::Relaxed);
+                    }
+                }
+                if done {
+                    return;
+                }
+            }
+        });
+        Ok(Self {
+            inner: Arc::new(PublisherInner {
+                queue,
+                rejected,
+                worker: Mutex::new(Some(worker)),
+            }),
+        })
+    }
+
+    /// Queue one second's counts and p99. Never waits: if CloudWatch is
+    /// that far behind, the oldest datapoints go.
+    pub async fn send(
+        &self,
+        success: usize,
+        errors: usize,
+        occ_errors: usize,
+        latency: &Histogram<u64>,
+    ) {
+        let at = DateTime::from(SystemTime::now());
+        let datum = |name: &str, value: f64, unit: StandardUnit| {
+            MetricDatum::builder()
+                .metric_name(name)
+                .value(value)
+                .unit(unit)
+                .timestamp(at)
+                .storage_resolution(1)
+                .build()
+        };
+        let mut data = vec![
+            datum("Throughput", success as f64, StandardUnit::CountSecond),
+            datum("Errors", errors as f64, StandardUnit::Count),
+            datum("OccErrors", occ_errors as f64, StandardUnit::Count),
+        ];
+        // A second with nothing recorded has no p99 to speak of
+        if !latency.is_empty() {
+            let p99 = latency.value_at_quantile(0.99) as f64;
+            data.push(datum("P99Latency", p99, StandardUnit::Milliseconds));
+        }
+        for datum in data {
+            self.inner.queue.push(datum).await;
+        }
+    }
+
+    /// Send what's still queued, waiting a little for it, then print how
+    /// many datapoints never made it. Nothing sent afterwards is published.
+    pub async fn finish(&self) {
+        self.inner.queue.finish();
+        let worker = self.inner.worker.lock().unwrap().take();
+        if let Some(worker) = worker
+            && tokio::time::timeout(FINAL_FLUSH_TIMEOUT, worker)
+                .await
+                .is_err()
+        {
+            tracing::warn!("gave up waiting for the last CloudWatch datapoints to send");
+        }
+        let inner = &self.inner;
+        let lost = inner.queue.dropped() + inner.rejected.load(Ordering::Relaxed);
+        if lost > 0 {
+            println!("CloudWatch: {} datapoints not published", lost);
+            println!();
+        }
+    }
+}
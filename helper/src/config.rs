@@ -0,0 +1,76 @@
+use crate::cli::Args;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// Where the cluster and function live, kept in a TOML file so they don't
+/// have to be exported in every shell.
+///
+/// ```toml
+/// cluster_endpoint = "abcdefghijklmnopqrstuvwxyz.dsql.us-west-2.on.aws"
+/// region = "us-west-2"
+/// function_name = "reinvent-dat401"
+///
+/// [pool]
+/// max_connections = 100
+/// ```
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub cluster_endpoint: Option<String>,
+    pub region: Option<String>,
+    pub function_name: Option<String>,
+    #[serde(default)]
+    pub pool: PoolSettings,
+}
+
+/// Defaults for the DSQL connection pool
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct PoolSettings {
+    pub max_connections: Option<u32>,
+}
+
+/// The config file read when `--config` isn't given, if it exists
+pub fn default_config_file() -> Option<PathBuf> {
+    let home = std::env::var_os("HOME")?;
+    Some(PathBuf::from(home).join(".riv25-codetalk.toml"))
+}
+
+impl Config {
+    /// Read `path`, which must exist, or the default file if there is one
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match default_config_file() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Self::default()),
+            },
+        };
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read config {}", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("Failed to parse config {}", path.display()))
+    }
+}
+
+/// Fill in whatever the command line left unset from the config file, then
+/// from `CLUSTER_ENDPOINT` and `AWS_REGION`, so flags override the file and
+/// the environment is the last resort
+pub fn resolve(args: &mut Args) -> Result<()> {
+    let config = Config::load(args.config.as_deref())?;
+    let db = &mut args.db;
+    db.cluster_endpoint = db
+        .cluster_endpoint
+        .take()
+        .or(config.cluster_endpoint)
+        .or_else(|| std::env::var("CLUSTER_ENDPOINT").ok());
+    db.region = db
+        .region
+        .take()
+        .or(config.region)
+        .or_else(|| std::env::var("AWS_REGION").ok());
+    db.db_max_connections = db.db_max_connections.or(config.pool.max_connections);
+    let lambda = &mut args.lambda;
+    lambda.function_name = lambda.function_name.take().or(config.function_name);
+    Ok(())
+}
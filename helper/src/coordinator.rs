@@ -0,0 +1,341 @@
+use crate::bounded::{BoundedQueue, WhenFull};
+use crate::stress;
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use hdrhistogram::serialization::{Deserializer, Serializer, V2Serializer};
+use hdrhistogram::Histogram;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use uuid::Uuid;
+
+/// One second of a worker's sustained run, sent to the coordinator as a
+/// line of JSON
+#[derive(Serialize, Deserialize, Debug)]
+struct Report {
+    /// Host and run id, telling apart several workers on one machine
+    worker: String,
+    success: usize,
+    errors: usize,
+    occ_errors: usize,
+    /// The second's latency histogram, V2-serialized and base64-encoded
+    latency: String,
+}
+
+/// How long writing one report may take before the coordinator is taken
+/// as gone, so one that stops reading can't hold up the run forever
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Streams a worker's per-second counters to `--coordinator` in the
+/// background, holding at most `--coordinator-buffer` reports for it so a
+/// slow or vanished coordinator can't grow memory without bound
+pub struct Reporter {
+    worker: String,
+    queue: Arc<BoundedQueue<Report>>,
+}
+
+impl Reporter {
+    /// Connect to the coordinator at `addr` for the run `run_id`
+    pub async fn connect(
+        addr: &str,
+        run_id: Uuid,
+        capacity: usize,
+        when_full: WhenFull,
+    ) -> Result<Self> {
+        let mut stream = TcpStream::connect(addr)
+            .await
+            .with_context(|| format!("Failed to connect to coordinator at {}", addr))?;
+        let host = hostname::get()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|_| "unknown".to_string());
+        let worker = format!("{}/{}", host, &run_id.to_string()[..8]);
+        let queue = Arc::new(BoundedQueue::new(capacity, when_full));
+        let writer = queue.clone();
+        let addr = addr.to_string();
+        tokio::spawn(async move {
+            while let Some(report) = writer.pop().await {
+                let mut line = match serde_json::to_vec(&report) {
+                    Ok(line) => line,
+                    Err(err) => {
+                        tracing::warn!(%err, "failed to serialize coordinator report");
+                        continue;
+                    }
+                };
+                line.push(b'\n');
+                let written = tokio::time::timeout(WRITE_TIMEOUT, stream.write_all(&line)).await;
+                let err = match written {
+                    Ok(Ok(())) => continue,
+                    Ok(Err(err)) => err.to_string(),
+                    Err(_) => format!("a write took over {}s", WRITE_TIMEOUT.as_secs()),
+                };
+                tracing::warn!(
+                    %err,
+                    addr = %addr,
+                    "lost the coordinator, no longer reporting to it"
+                );
+                writer.close();
+                return;
+            }
+            let _ = stream.shutdown().await;
+        });
+        Ok(Self { worker, queue })
+    }
+
+    /// Queue one second's counts and latency for the coordinator, waiting
+    /// for room if it's behind and `--coordinator-when-full block` is set
+    pub async fn send(
+        &self,
+        success: usize,
+        errors: usize,
+        occ_errors: usize,
+        latency: &Histogram<u64>,
+    ) {
+        let mut bytes = Vec::new();
+        if let Err(err) = V2Serializer::new().serialize(latency, &mut bytes) {
+            tracing::warn!(?err, "failed to serialize latency for the coordinator");
+            return;
+        }
+        self.queue
+            .push(Report {
+                worker: self.worker.clone(),
+                success,
+                errors,
+                occ_errors,
+                latency: BASE64.encode(bytes),
+            })
+            .await;
+    }
+
+    /// A handle on how many reports never reached the coordinator, which
+    /// outlives the reporter
+    pub fn delivery(&self) -> Delivery {
+        Delivery(self.queue.clone())
+    }
+}
+
+impl Drop for Reporter {
+    /// Let the writer send what's queued, then disconnect
+    fn drop(&mut self) {
+        self.queue.finish();
+    }
+}
+
+/// How a run's reports to the coordinator fared
+pub struct Delivery(Arc<BoundedQueue<Report>>);
+
+impl Delivery {
+    pub fn print(&self) {
+        println!("Coordinator: {} reports dropped due to backpressure", self.0.dropped());
+        println!();
+    }
+}
+
+/// Everything one worker has reported
+struct Worker {
+    success: usize,
+    errors: usize,
+    occ_errors: usize,
+    latency: Histogram<u64>,
+    connected: bool,
+}
+
+/// The workers' reports merged, in total and for the current second
+struct Aggregate {
+    workers: BTreeMap<String, Worker>,
+    /// When the first report arrived, which is when the load started
+    first_report: Option<Instant>,
+    second_success: usize,
+    second_errors: usize,
+    second_latency: Histogram<u64>,
+}
+
+impl Aggregate {
+    fn new() -> Self {
+        Self {
+            workers: BTreeMap::new(),
+            first_report: None,
+            second_success: 0,
+            second_errors: 0,
+            second_latency: Histogram::new(3).unwrap(),
+        }
+    }
+
+    fn record(&mut self, report: Report) -> Result<()> {
+        let bytes = BASE64.decode(&report.latency)?;
+        let latency: Histogram<u64> = Deserializer::new()
+            .deserialize(&mut &bytes[..])
+            .map_err(|e| anyhow::anyhow!("bad latency histogram: {:?}", e))?;
+        self.first_report.get_or_insert_with(Instant::now);
+        self.second_success += report.success;
+        self.second_errors += report.errors;
+        let _ = self.second_latency.add(&latency);
+        let worker = self.workers.entry(report.worker).or_insert_with(|| Worker {
+            success: 0,
+            errors: 0,
+            occ_errors: 0,
+            latency: Histogram::new(3).unwrap(),
+            connected: true,
+        });
+        worker.success += report.success;
+        worker.errors += report.errors;
+        worker.occ_errors += report.occ_errors;
+        let _ = worker.latency.add(&latency);
+        Ok(())
+    }
+
+    fn disconnected(&mut self, worker: &str) {
+        if let Some(worker) = self.workers.get_mut(worker) {
+            worker.connected = false;
+        }
+    }
+
+    /// The line printed each second, then forget the second
+    fn take_second(&mut self, elapsed: Duration) -> String {
+        let connected = self.workers.values().filter(|w| w.connected).count();
+        let line = format!(
+            "[{}s] workers: {} | {}/s | p50: {}ms p99: {}ms | errors: {}",
+            elapsed.as_secs(),
+            connected,
+            self.second_success,
+            self.second_latency.value_at_quantile(0.5),
+            self.second_latency.value_at_quantile(0.99),
+            self.second_errors
+        );
+        self.second_success = 0;
+        self.second_errors = 0;
+        self.second_latency.reset();
+        line
+    }
+
+    fn print_summary(&self) {
+        println!();
+        println!("{}", "=".repeat(60));
+        println!("ACROSS {} WORKERS", self.workers.len());
+        println!("{}", "=".repeat(60));
+        if self.workers.is_empty() {
+            println!("No worker reported");
+            return;
+        }
+        let elapsed = self
+            .first_report
+            .map_or(0.0, |first| first.elapsed().as_secs_f64());
+        let per_sec = |success: usize| {
+            if elapsed > 0.0 {
+                success as f64 / elapsed
+            } else {
+                0.0
+            }
+        };
+        println!(
+            "{:<24} {:>12} {:>8} {:>8} {:>8} {:>8}",
+            "Worker", "Throughput/s", "Errors", "OCC", "p50 ms", "p99 ms"
+        );
+        let mut total = Histogram::<u64>::new(3).unwrap();
+        let (mut success, mut errors, mut occ_errors) = (0, 0, 0);
+        for (name, worker) in &self.workers {
+            println!(
+                "{:<24} {:>12.0} {:>8} {:>8} {:>8} {:>8}",
+                name,
+                per_sec(worker.success),
+                worker.errors,
+                worker.occ_errors,
+                worker.latency.value_at_quantile(0.5),
+                worker.latency.value_at_quantile(0.99)
+            );
+            let _ = total.add(&worker.latency);
+            success += worker.success;
+            errors += worker.errors;
+            occ_errors += worker.occ_errors;
+        }
+        println!(
+            "{:<24} {:>12.0} {:>8} {:>8} {:>8} {:>8}",
+            "Total",
+            per_sec(success),
+            errors,
+            occ_errors,
+            total.value_at_quantile(0.5),
+            total.value_at_quantile(0.99)
+        );
+        println!();
+        println!("Successful calls:   {}", success);
+        println!("Duration:           {:.1}s", elapsed);
+        println!("Latency (merged):");
+        for (label, quantile) in [("p50", 0.5), ("p90", 0.9), ("p99", 0.99), ("p99.9", 0.999)] {
+            println!("  {:<6} {}ms", label, total.value_at_quantile(quantile));
+        }
+        println!("  max    {}ms", total.max());
+    }
+}
+
+/// Accept workers on `listen` and print their merged throughput and
+/// latency every second, then a summary per worker and overall once
+/// stopped with Ctrl-C or `--max-runtime`
+pub async fn serve(listen: &str) -> Result<()> {
+    let listener = TcpListener::bind(listen)
+        .await
+        .with_context(|| format!("Failed to listen on {}", listen))?;
+    println!("Coordinator listening on {}", listener.local_addr()?);
+    println!("Start workers with sustained-load --coordinator <this address>");
+    println!("Press Ctrl-C to stop and print the summary...");
+    println!();
+
+    let aggregate = Arc::new(Mutex::new(Aggregate::new()));
+    let mut ticks = tokio::time::interval(Duration::from_secs(1));
+    let stop = stress::stop_requested();
+    tokio::pin!(stop);
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let (stream, peer) = accepted?;
+                tracing::info!(%peer, "worker connected");
+                tokio::spawn(read_reports(stream, aggregate.clone()));
+            }
+            _ = ticks.tick() => {
+                let mut aggregate = aggregate.lock().unwrap();
+                if let Some(first) = aggregate.first_report {
+                    println!("{}", aggregate.take_second(first.elapsed()));
+                }
+            }
+            why = &mut stop => {
+                println!("\n{}, shutting down...", why);
+                break;
+            }
+        }
+    }
+    aggregate.lock().unwrap().print_summary();
+    Ok(())
+}
+
+/// Merge one worker's reports until it disconnects
+async fn read_reports(stream: TcpStream, aggregate: Arc<Mutex<Aggregate>>) {
+    let mut lines = BufReader::new(stream).lines();
+    let mut worker = None;
+    loop {
+        let line = match lines.next_line().await {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(err) => {
+                tracing::warn!(%err, "failed to read from worker");
+                break;
+            }
+        };
+        let report: Report = match serde_json::from_str(&line) {
+            Ok(report) => report,
+            Err(err) => {
+                tracing::warn!(%err, "ignoring malformed worker report");
+                continue;
+            }
+        };
+        worker.get_or_insert_with(|| report.worker.clone());
+        if let Err(err) = aggregate.lock().unwrap().record(report) {
+            tracing::warn!(%err, "ignoring malformed worker report");
+        }
+    }
+    if let Some(worker) = worker {
+        println!("Worker {} finished", worker);
+        aggregate.lock().unwrap().disconnected(&worker);
+    }
+}
@@ -0,0 +1,126 @@
+use crate::cli::{DbArgs, LambdaArgs, MockArgs};
+use crate::credentials::CredentialCache;
+use crate::lambda::{self, tpcb};
+use anyhow::Result;
+use std::time::Instant;
+
+/// Lambda's on-demand price per GB-second (x86, us-east-1)
+const GB_SECOND_PRICE: f64 = 0.000_016_666_7;
+
+/// Lambda's price per million requests
+const REQUEST_PRICE_PER_MILLION: f64 = 0.20;
+
+/// DSQL's price per million DPUs (us-east-1)
+const DPU_PRICE_PER_MILLION: f64 = 8.0;
+
+/// What the estimate takes as given rather than measuring
+#[derive(Clone, Copy, Debug)]
+pub struct Assumptions {
+    /// Probe transfers to average over
+    pub probes: u32,
+    /// The function's configured memory
+    pub memory_mb: u32,
+    /// DPUs one attempt at a transfer uses
+    pub dpu_per_transfer: f64,
+}
+
+/// What the probe transfers measured, per invocation
+struct Probed {
+    /// Mean duration the Lambda reported, rounded up to whole milliseconds
+    /// as Lambda bills, or the round trip where it reported none
+    billed_ms: f64,
+    /// Mean round trip seen from here
+    round_trip_ms: f64,
+    /// Mean OCC retries, each of which repeats the transaction's work
+    retries: f64,
+    request_bytes: f64,
+    response_bytes: f64,
+}
+
+/// Send a few probe transfers of `amount` back and forth between accounts
+/// 1 and 2 and scale what they took up to `total` invocations, printing the
+/// Lambda GB-seconds, the Lambda and DSQL costs and the DSQL DPUs the run
+/// would use. Prices are list prices, so treat the result as an order of
+/// magnitude, not a bill.
+pub async fn estimate_cost(
+    creds: &CredentialCache,
+    db_args: &DbArgs,
+    lambda_args: &LambdaArgs,
+    mock: &MockArgs,
+    total: u64,
+    amount: u32,
+    assumptions: &Assumptions,
+) -> Result<()> {
+    let Assumptions {
+        probes,
+        memory_mb,
+        dpu_per_transfer,
+    } = *assumptions;
+    anyhow::ensure!(probes > 0, "--probes must be at least 1");
+    let client_pool = lambda::client_pool(creds, 1, lambda_args, db_args, mock).await?;
+    println!("Sending {} probe transfers of {}\n", probes, amount);
+    let probed = probe(&client_pool, amount, probes).await?;
+
+    let gb_seconds = total as f64 * probed.billed_ms / 1000.0 * memory_mb as f64 / 1024.0;
+    let compute_cost = gb_seconds * GB_SECOND_PRICE;
+    let request_cost = total as f64 / 1e6 * REQUEST_PRICE_PER_MILLION;
+    let dpus = total as f64 * dpu_per_transfer * (1.0 + probed.retries);
+    let dpu_cost = dpus / 1e6 * DPU_PRICE_PER_MILLION;
+
+    println!("Per invocation (mean of {} probes):", probes);
+    println!("  Billed duration:  {:.1}ms", probed.billed_ms);
+    println!("  Round trip:       {:.1}ms", probed.round_trip_ms);
+    println!("  OCC retries:      {:.2}", probed.retries);
+    println!("  Request:          {:.0} bytes", probed.request_bytes);
+    println!("  Response:         {:.0} bytes", probed.response_bytes);
+    println!();
+    println!("Estimate for {} invocations at {}MB:", total, memory_mb);
+    println!("  Lambda compute:   {:.1} GB-s  ${:.2}", gb_seconds, compute_cost);
+    println!("  Lambda requests:  ${:.2}", request_cost);
+    println!(
+        "  DSQL:             {:.0} DPUs  ${:.2} (assuming {} DPU per transfer)",
+        dpus, dpu_cost, dpu_per_transfer
+    );
+    println!("  Total:            ${:.2}", compute_cost + request_cost + dpu_cost);
+    println!();
+    println!("List prices for us-east-1; provisioned concurrency and storage are not included");
+    Ok(())
+}
+
+async fn probe(client_pool: &lambda::ClientPool, amount: u32, probes: u32) -> Result<Probed> {
+    let mut billed_ms = 0.0;
+    let mut round_trip_ms = 0.0;
+    let mut retries = 0;
+    for i in 0..probes {
+        let (payer_id, payee_id) = if i % 2 == 0 { (1, 2) } else { (2, 1) };
+        let start = Instant::now();
+        let response: tpcb::Response = lambda::invoke(
+            client_pool.get(),
+            tpcb::Request {
+                payer_id,
+                payee_id,
+                amount: tpcb::Amount::Whole(amount),
+                idempotency_key: None,
+                deadline_ms: client_pool.deadline_ms(),
+            },
+        )
+        .await?;
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+        if let Some(err) = response.error {
+            anyhow::bail!("Probe transfer from account {} failed: {}", payer_id, err);
+        }
+        round_trip_ms += elapsed;
+        billed_ms += response.duration.map_or(elapsed.ceil(), |ms| ms.max(1) as f64);
+        retries += response.retries.unwrap_or(0);
+    }
+
+    let traffic = client_pool.traffic();
+    let n = probes as f64;
+    Ok(Probed {
+        billed_ms: billed_ms / n,
+        round_trip_ms: round_trip_ms / n,
+        retries: retries as f64 / n,
+        request_bytes: traffic.avg_request_bytes(),
+        response_bytes: traffic.avg_response_bytes(),
+    })
+}
@@ -1,10 +1,15 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use aws_config::BehaviorVersion;
 use aws_credential_types::provider::ProvideCredentials;
 use aws_credential_types::Credentials;
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::{Duration, SystemTime};
-use tokio::sync::RwLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{OnceCell, RwLock};
 
 #[derive(Clone, Debug)]
 struct CachedCredentials {
@@ -12,20 +17,44 @@ struct CachedCredentials {
     expires_at: SystemTime,
 }
 
+impl CachedCredentials {
+    /// Still valid for at least another 5 minutes
+    fn is_fresh(&self) -> bool {
+        self.expires_at > SystemTime::now() + Duration::from_secs(300)
+    }
+}
+
+/// On-disk form of `CachedCredentials`
+#[derive(Serialize, Deserialize)]
+struct PersistedCredentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+    /// Seconds since the Unix epoch
+    expires_at: u64,
+}
+
 #[derive(Debug)]
 pub struct CredentialCache {
     cached: Arc<RwLock<Option<CachedCredentials>>>,
-    provider: aws_credential_types::provider::SharedCredentialsProvider,
+    /// The default provider chain, resolved on first use so commands that
+    /// never need credentials (`--dry-run`, `--mock`) don't go looking
+    provider: OnceCell<aws_credential_types::provider::SharedCredentialsProvider>,
+    /// Where credentials are persisted between runs, if at all
+    cache_file: Option<PathBuf>,
 }
 
 impl CredentialCache {
-    pub async fn new() -> Result<Self> {
-        let config = aws_config::load_defaults(BehaviorVersion::latest()).await;
-        let provider = config.credentials_provider().unwrap().clone();
+    pub async fn new(cache_file: Option<PathBuf>) -> Result<Self> {
+        let cached = cache_file
+            .as_deref()
+            .and_then(load_cache_file)
+            .filter(CachedCredentials::is_fresh);
 
         Ok(Self {
-            cached: Arc::new(RwLock::new(None)),
-            provider,
+            cached: Arc::new(RwLock::new(cached)),
+            provider: OnceCell::new(),
+            cache_file,
         })
     }
 
@@ -33,12 +62,10 @@ impl CredentialCache {
         // Check if we have valid cached credentials
         {
             let cached = self.cached.read().await;
-            if let Some(cached_creds) = cached.as_ref() {
-                // Refresh 5 minutes before expiry
-                let refresh_threshold = SystemTime::now() + Duration::from_secs(300);
-                if cached_creds.expires_at > refresh_threshold {
-                    return Ok(cached_creds.credentials.clone());
-                }
+            if let Some(cached_creds) = cached.as_ref()
+                && cached_creds.is_fresh()
+            {
+                return Ok(cached_creds.credentials.clone());
             }
         }
 
@@ -46,30 +73,117 @@ impl CredentialCache {
         let mut cached = self.cached.write().await;
 
         // Double-check in case another task just updated
-        if let Some(cached_creds) = cached.as_ref() {
-            let refresh_threshold = SystemTime::now() + Duration::from_secs(300);
-            if cached_creds.expires_at > refresh_threshold {
-                return Ok(cached_creds.credentials.clone());
-            }
+        if let Some(cached_creds) = cached.as_ref()
+            && cached_creds.is_fresh()
+        {
+            return Ok(cached_creds.credentials.clone());
         }
 
         // Fetch fresh credentials
-        let credentials = self
-            .provider
-            .provide_credentials()
-            .await
-            .map_err(|e| anyhow::anyhow!("Failed to fetch credentials: {}", e))?;
+        let credentials = self.provide_uncached().await?;
 
         // Determine expiry time
         let expires_at = credentials
             .expiry()
             .unwrap_or_else(|| SystemTime::now() + Duration::from_secs(3600));
 
-        *cached = Some(CachedCredentials {
+        let fresh = CachedCredentials {
             credentials: credentials.clone(),
             expires_at,
-        });
+        };
+        // Long-lived keys (no expiry) are already on disk wherever they came
+        // from; only temporary credentials are worth caching
+        if let Some(path) = &self.cache_file
+            && credentials.expiry().is_some()
+            && let Err(err) = save_cache_file(path, &fresh)
+        {
+            tracing::warn!(path = %path.display(), "failed to write credential cache: {:#}", err);
+        }
+        *cached = Some(fresh);
 
         Ok(credentials)
     }
+
+    /// Ask the provider chain for credentials, bypassing the cache
+    pub async fn provide_uncached(&self) -> Result<Credentials> {
+        let provider = self
+            .provider
+            .get_or_init(|| async {
+                let config = aws_config::load_defaults(BehaviorVersion::latest()).await;
+                config.credentials_provider().unwrap()
+            })
+            .await;
+        provider
+            .provide_credentials()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to fetch credentials: {}", e))
+    }
+
+    /// Fetch credentials now, if not already cached, so the first request
+    /// of a run doesn't pay for the provider call
+    pub async fn warm(&self) -> Result<()> {
+        let start = std::time::Instant::now();
+        self.get_credentials().await?;
+        tracing::debug!(elapsed_ms = start.elapsed().as_millis() as u64, "credentials warm");
+        Ok(())
+    }
+}
+
+/// Default location for `--credential-cache-file` given without a path
+pub fn default_cache_file() -> Option<PathBuf> {
+    let cache_home = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+    Some(cache_home.join("riv25-codetalk").join("credentials.json"))
+}
+
+fn load_cache_file(path: &Path) -> Option<CachedCredentials> {
+    let json = std::fs::read_to_string(path).ok()?;
+    let persisted: PersistedCredentials = match serde_json::from_str(&json) {
+        Ok(persisted) => persisted,
+        Err(err) => {
+            tracing::warn!(path = %path.display(), %err, "ignoring unreadable credential cache");
+            return None;
+        }
+    };
+    let expires_at = UNIX_EPOCH + Duration::from_secs(persisted.expires_at);
+    Some(CachedCredentials {
+        credentials: Credentials::new(
+            persisted.access_key_id,
+            persisted.secret_access_key,
+            persisted.session_token,
+            Some(expires_at),
+            "credential-cache-file",
+        ),
+        expires_at,
+    })
+}
+
+/// Write the credentials readable by the current user only, replacing the
+/// file atomically so a concurrent reader never sees a partial write
+fn save_cache_file(path: &Path, cached: &CachedCredentials) -> Result<()> {
+    let persisted = PersistedCredentials {
+        access_key_id: cached.credentials.access_key_id().to_string(),
+        secret_access_key: cached.credentials.secret_access_key().to_string(),
+        session_token: cached.credentials.session_token().map(str::to_string),
+        expires_at: cached.expires_at.duration_since(UNIX_EPOCH)?.as_secs(),
+    };
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let tmp = path.with_extension("tmp");
+    // A leftover from an interrupted write may have other permissions
+    let _ = std::fs::remove_file(&tmp);
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&tmp)
+        .with_context(|| format!("Failed to create {}", tmp.display()))?;
+    file.write_all(serde_json::to_string(&persisted)?.as_bytes())?;
+    file.sync_all()?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
 }
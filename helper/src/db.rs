@@ -1,27 +1,597 @@
+use crate::cli::{AccountsCheck, DbArgs, Endpoint, HistoryRole, SslMode};
 use crate::credentials::CredentialCache;
-use anyhow::Result;
+use crate::failure::Failure;
+use crate::lambda::{tpcb::Amount, ClientPool};
+use crate::setup;
+use anyhow::{Context, Result};
 use aws_config::BehaviorVersion;
 use aws_sdk_dsql::auth_token::{AuthTokenGenerator, Config};
-use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
-use sqlx::{Pool, Postgres};
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions, PgSslMode};
+use sqlx::{Executor, Pool, Postgres};
+use std::time::Duration;
 
-pub async fn get_pool(creds: &CredentialCache) -> Result<Pool<Postgres>> {
-    let cluster_endpoint = std::env::var("CLUSTER_ENDPOINT")?;
-    let region = std::env::var("AWS_REGION").unwrap_or_else(|_| "us-west-2".to_string());
+/// A row of the `transactions` ledger
+#[derive(sqlx::FromRow)]
+pub struct Transaction {
+    pub id: uuid::Uuid,
+    pub payer_id: i32,
+    pub payee_id: i32,
+    /// Whole units, or the exact decimal of an `--amount-decimal` transfer
+    pub amount: String,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+/// Selects a `Transaction`'s columns, whichever of `amount` and
+/// `exact_amount` the transfer wrote
+pub const TRANSACTION_COLUMNS: &str =
+    "id, payer_id, payee_id, COALESCE(exact_amount::text, amount::text) AS amount, created_at";
+
+/// The latest `limit` transactions `account_id` took part in as `role`,
+/// newest first, optionally only those created at or after `since`
+pub async fn account_history(
+    pool: &Pool<Postgres>,
+    account_id: i32,
+    role: HistoryRole,
+    limit: i64,
+    since: Option<chrono::NaiveDateTime>,
+) -> Result<Vec<Transaction>> {
+    let matches = match role {
+        HistoryRole::Payer => "payer_id = $1",
+        HistoryRole::Payee => "payee_id = $1",
+        HistoryRole::Either => "(payer_id = $1 OR payee_id = $1)",
+    };
+    let sql = format!(
+        "SELECT {TRANSACTION_COLUMNS}
+         FROM transactions
+         WHERE {matches} AND ($2::timestamp IS NULL OR created_at >= $2)
+         ORDER BY created_at DESC
+         LIMIT $3"
+    );
+    Ok(sqlx::query_as(&sql)
+        .bind(account_id)
+        .bind(since)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?)
+}
+
+/// Create the demo schema with `num_accounts` accounts if the `accounts`
+/// table doesn't exist yet; does nothing if it does
+pub async fn ensure_schema(
+    creds: &CredentialCache,
+    db_args: &DbArgs,
+    num_accounts: u32,
+) -> Result<()> {
+    let pool = get_pool(creds, db_args).await?;
+    let (exists,): (bool,) = sqlx::query_as("SELECT to_regclass('accounts') IS NOT NULL")
+        .fetch_one(&pool)
+        .await?;
+    if !exists {
+        println!("No accounts table found, creating the schema first");
+        setup::create_schema(&pool, num_accounts, false).await?;
+        println!();
+    }
+    Ok(())
+}
+
+/// Check that accounts `1..=num_accounts` can exist, returning the number of
+/// accounts the run should use. Always fails if the table is empty.
+pub async fn check_account_count(
+    creds: &CredentialCache,
+    db_args: &DbArgs,
+    num_accounts: u32,
+    check: AccountsCheck,
+) -> Result<u32> {
+    if check == AccountsCheck::Skip {
+        return Ok(num_accounts);
+    }
+
+    let pool = get_pool(creds, db_args).await?;
+    let (count,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM accounts")
+        .fetch_one(&pool)
+        .await?;
+    if count == 0 {
+        anyhow::bail!(
+            "The accounts table is empty, so every transfer would fail; \
+             run `setup --accounts {}` first",
+            num_accounts
+        );
+    }
+    if num_accounts as i64 <= count {
+        return Ok(num_accounts);
+    }
+
+    match check {
+        AccountsCheck::Clamp => {
+            tracing::warn!(
+                requested = num_accounts,
+                actual = count,
+                "more accounts requested than exist, using only existing accounts"
+            );
+            Ok(count as u32)
+        }
+        _ => anyhow::bail!(
+            "--accounts is {} but the accounts table only has {} rows; \
+             run setup with more accounts or pass --accounts {}",
+            num_accounts,
+            count,
+            count
+        ),
+    }
+}
+
+/// Rows currently in the `transactions` ledger
+pub async fn count_transactions(creds: &CredentialCache, db_args: &DbArgs) -> Result<i64> {
+    let pool = get_pool(creds, db_args).await?;
+    let (count,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM transactions")
+        .fetch_one(&pool)
+        .await?;
+    Ok(count)
+}
+
+/// Check the ledger grew by exactly `applied_transfers` rows since it held
+/// `before`, catching OCC retries that wrote a transfer twice or transfers
+/// reported successful that never committed
+pub async fn verify_ledger(
+    creds: &CredentialCache,
+    db_args: &DbArgs,
+    before: i64,
+    applied_transfers: usize,
+) -> Result<()> {
+    let after = count_transactions(creds, db_args).await?;
+    let written = after - before;
+    println!("Ledger Check:");
+    println!("  Successful transfers: {}", applied_transfers);
+    println!("  Rows written:         {}", written);
+    println!();
+    if written != applied_transfers as i64 {
+        return Err(anyhow::anyhow!(
+            "The transactions table grew by {} rows but {} transfers succeeded",
+            written,
+            applied_transfers
+        )
+        .context(Failure::Conservation));
+    }
+    Ok(())
+}
+
+/// The sum of every account's balance, which transfers never change
+pub async fn total_balance(pool: &Pool<Postgres>) -> Result<i64> {
+    let (total,): (Option<i64>,) = sqlx::query_as("SELECT SUM(balance)::bigint FROM accounts")
+        .fetch_one(pool)
+        .await?;
+    Ok(total.unwrap_or(0))
+}
+
+/// Check accounts 1 to `num_accounts` hold `initial_balance` each between
+/// them, as they did when set up: transfers among them move money but
+/// never create or destroy it
+pub async fn verify_conservation(
+    creds: &CredentialCache,
+    db_args: &DbArgs,
+    num_accounts: u32,
+    initial_balance: i64,
+) -> Result<()> {
+    let pool = get_pool(creds, db_args).await?;
+    let (total,): (Option<i64>,) =
+        sqlx::query_as("SELECT SUM(balance)::bigint FROM accounts WHERE id BETWEEN 1 AND $1")
+            .bind(num_accounts as i32)
+            .fetch_one(&pool)
+            .await?;
+    let total = total.unwrap_or(0);
+    let expected = num_accounts as i64 * initial_balance;
+    let passed = total == expected;
+    println!("Conservation Check:");
+    println!("  Expected total:       {} ({} x {})", expected, num_accounts, initial_balance);
+    println!("  Actual total:         {}", total);
+    println!("  Result:               {}", if passed { "PASS" } else { "FAIL" });
+    println!();
+    if !passed {
+        return Err(anyhow::anyhow!(
+            "The accounts hold {} between them but started with {} ({:+})",
+            total,
+            expected,
+            total - expected
+        )
+        .context(Failure::Conservation));
+    }
+    Ok(())
+}
+
+/// Check accounts 1 to `num_accounts` hold `initial_balance` each between
+/// them in `exact_balance` too, to the cent, after `--amount-decimal`
+/// transfers
+pub async fn verify_exact_conservation(
+    creds: &CredentialCache,
+    db_args: &DbArgs,
+    num_accounts: u32,
+    initial_balance: i64,
+) -> Result<()> {
+    let pool = get_pool(creds, db_args).await?;
+    // NUMERIC(12, 2) times 100 is a whole number, so nothing is rounded
+    let (total, unset): (Option<i64>, i64) = sqlx::query_as(
+        r#"
+        SELECT (SUM(exact_balance) * 100)::bigint, COUNT(*) FILTER (WHERE exact_balance IS NULL)
+        FROM accounts WHERE id BETWEEN 1 AND $1
+        "#,
+    )
+    .bind(num_accounts as i32)
+    .fetch_one(&pool)
+    .await?;
+    if unset > 0 {
+        return Err(anyhow::anyhow!(
+            "{} accounts have no exact balance, as they were set up before it was added; \
+             run setup or reset-balances again",
+            unset
+        )
+        .context(Failure::Config));
+    }
+    let total = total.unwrap_or(0);
+    let expected = num_accounts as i64 * initial_balance * 100;
+    let passed = total == expected;
+    println!("Exact Conservation Check:");
+    println!(
+        "  Expected total:       {} ({} x {})",
+        format_cents(expected),
+        num_accounts,
+        initial_balance
+    );
+    println!("  Actual total:         {}", format_cents(total));
+    println!("  Result:               {}", if passed { "PASS" } else { "FAIL" });
+    println!();
+    if !passed {
+        return Err(anyhow::anyhow!(
+            "The accounts hold exactly {} between them but started with {} ({}{})",
+            format_cents(total),
+            format_cents(expected),
+            if total > expected { "+" } else { "" },
+            format_cents(total - expected)
+        )
+        .context(Failure::Conservation));
+    }
+    Ok(())
+}
+
+/// Hundredths as a decimal, such as -12.05
+fn format_cents(cents: i64) -> String {
+    let sign = if cents < 0 { "-" } else { "" };
+    format!("{}{}.{:02}", sign, cents.abs() / 100, cents.abs() % 100)
+}
+
+/// Check the balance total against `expected` every `interval` until
+/// aborted, warning with the invocations in flight whenever it has drifted
+pub async fn watch_balances(
+    pool: Pool<Postgres>,
+    expected: i64,
+    interval: Duration,
+    clients: ClientPool,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        match total_balance(&pool).await {
+            Ok(total) if total != expected => tracing::warn!(
+                expected,
+                total,
+                drift = total - expected,
+                in_flight = clients.in_flight(),
+                "balance total drifted mid-run"
+            ),
+            Ok(_) => tracing::debug!(expected, "balance total unchanged"),
+            Err(err) => tracing::warn!("balance check failed: {:#}", err),
+        }
+    }
+}
+
+/// Postgres' serialization failure, which DSQL raises on an OCC conflict
+pub const OCC_ERROR_CODE: &str = "40001";
+
+/// Most OCC retries a direct transfer makes before giving up with the
+/// conflict, where the Lambdas would retry forever
+const MAX_TRANSFER_RETRIES: u32 = 100;
+
+/// Run the chapter 3 TPC-B transfer from here rather than in the Lambda:
+/// debit the payer, credit the payee and record it in the ledger, retrying
+/// the whole transaction on OCC conflicts.
+///
+/// Returns the payer's new balance, and the OCC retries it took either way.
+pub async fn transfer(
+    pool: &Pool<Postgres>,
+    payer_id: i32,
+    payee_id: i32,
+    amount: Amount,
+) -> (Result<i32>, u32) {
+    let mut retries = 0;
+    loop {
+        match try_transfer(pool, payer_id, payee_id, amount).await {
+            Err(err) if is_occ_error(&err) && retries < MAX_TRANSFER_RETRIES => retries += 1,
+            result => return (result, retries),
+        }
+    }
+}
+
+/// The debit, credit and ledger statements of a transfer of whole units
+const WHOLE_TRANSFER: [&str; 3] = [
+    "UPDATE accounts SET balance = balance - $1::integer WHERE id = $2 \
+     RETURNING balance, balance < 0",
+    "UPDATE accounts SET balance = balance + $1::integer WHERE id = $2",
+    "INSERT INTO transactions (payer_id, payee_id, amount) VALUES ($1, $2, $3::integer)",
+];
+
+/// The same for an `--amount-decimal` transfer, which moves the NUMERIC
+/// columns and leaves the whole-unit ones alone
+const EXACT_TRANSFER: [&str; 3] = [
+    "UPDATE accounts SET exact_balance = exact_balance - $1::numeric WHERE id = $2 \
+     RETURNING balance, exact_balance < 0",
+    "UPDATE accounts SET exact_balance = exact_balance + $1::numeric WHERE id = $2",
+    "INSERT INTO transactions (payer_id, payee_id, exact_amount) VALUES ($1, $2, $3::numeric)",
+];
+
+/// One attempt at a transfer; dropping the transaction on error rolls it
+/// back
+async fn try_transfer(
+    pool: &Pool<Postgres>,
+    payer_id: i32,
+    payee_id: i32,
+    amount: Amount,
+) -> Result<i32> {
+    let [debit, credit, record] = match amount {
+        Amount::Whole(_) => WHOLE_TRANSFER,
+        Amount::Cents(_) => EXACT_TRANSFER,
+    };
+    // Bound as text either way, so Postgres parses a decimal itself rather
+    // than receiving a float
+    let amount = amount.to_string();
+    let mut tx = pool.begin().await?;
+
+    let debited: Option<(i32, Option<bool>)> = sqlx::query_as(debit)
+        .bind(&amount)
+        .bind(payer_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+    let Some((balance, overdrawn)) = debited else {
+        anyhow::bail!("Payer account not found");
+    };
+    match overdrawn {
+        Some(false) => {}
+        Some(true) => anyhow::bail!("Insufficient balance"),
+        None => anyhow::bail!("Payer account has no exact balance; run reset-balances"),
+    }
+
+    let credited = sqlx::query(credit)
+        .bind(&amount)
+        .bind(payee_id)
+        .execute(&mut *tx)
+        .await?;
+    if credited.rows_affected() == 0 {
+        anyhow::bail!("Payee account not found");
+    }
+
+    sqlx::query(record)
+        .bind(payer_id)
+        .bind(payee_id)
+        .bind(&amount)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(balance)
+}
+
+/// Reported as the SQLSTATE of a query that timed out waiting for a pool
+/// connection (`--acquire-timeout-ms`): too_many_connections, so it counts
+/// as a server error of its own
+pub const POOL_TIMEOUT_CODE: &str = "53300";
+
+/// The SQLSTATE of a failed query, if `err` came from the database or from
+/// waiting for one of its connections
+pub fn error_code(err: &anyhow::Error) -> Option<String> {
+    let err = err.downcast_ref::<sqlx::Error>()?;
+    if let sqlx::Error::PoolTimedOut = err {
+        return Some(POOL_TIMEOUT_CODE.to_string());
+    }
+    err.as_database_error()?.code().map(|code| code.into_owned())
+}
+
+fn is_occ_error(err: &anyhow::Error) -> bool {
+    error_code(err).as_deref() == Some(OCC_ERROR_CODE)
+}
+
+/// How many of a pool's connections are in use, to tell a slow database
+/// from a pool that has run out of connections
+#[derive(Clone, Copy, Debug)]
+pub struct PoolMetrics {
+    pub active: u32,
+    pub idle: u32,
+}
+
+impl PoolMetrics {
+    pub fn of(pool: &Pool<Postgres>) -> Self {
+        let idle = pool.num_idle() as u32;
+        Self {
+            active: pool.size().saturating_sub(idle),
+            idle,
+        }
+    }
+}
+
+/// Open up to `n` of the pool's connections before they're needed, holding
+/// them all at once so each is a new one, then release them to sit idle.
+/// Returns how many it opened.
+pub async fn pool_prewarm(pool: &Pool<Postgres>, n: u32) -> Result<u32> {
+    let n = n.min(pool.options().get_max_connections());
+    let connections = futures_util::future::try_join_all((0..n).map(|_| pool.acquire())).await?;
+    drop(connections);
+    Ok(n)
+}
+
+/// Delay before the first connection retry; doubles with each attempt
+const INITIAL_CONNECT_BACKOFF: Duration = Duration::from_millis(200);
+
+/// Pool settings independent of what the pool connects to
+#[derive(Clone, Debug)]
+pub struct PoolConfig {
+    pub max_connections: u32,
+    /// Fail a query that waits this long for a free connection, rather
+    /// than leave pool exhaustion to show up as latency
+    pub acquire_timeout: Option<Duration>,
+    /// Set as each connection's `statement_timeout`
+    pub statement_timeout: Option<Duration>,
+    /// Set as each connection's `search_path`, in place of public
+    pub search_path: Option<String>,
+}
 
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_connections: 1_000,
+            acquire_timeout: None,
+            statement_timeout: None,
+            search_path: None,
+        }
+    }
+}
+
+impl PoolConfig {
+    /// The defaults, with `--db-max-connections`, `--acquire-timeout-ms`
+    /// and `--statement-timeout-ms` applied
+    pub fn of(db_args: &DbArgs) -> Self {
+        let defaults = Self::default();
+        Self {
+            max_connections: db_args.db_max_connections.unwrap_or(defaults.max_connections),
+            acquire_timeout: db_args.acquire_timeout_ms.map(Duration::from_millis),
+            statement_timeout: db_args.statement_timeout_ms.map(Duration::from_millis),
+            search_path: db_args.schema.clone(),
+        }
+    }
+}
+
+/// Region of the cluster when neither `--endpoints` nor `--region` says
+pub const DEFAULT_REGION: &str = "us-west-2";
+
+/// Connect to the cluster through its first endpoint, failing over to
+/// `--failover-endpoints` in turn, and retrying them all with exponential
+/// backoff while the cluster is unavailable
+pub async fn get_pool(creds: &CredentialCache, db_args: &DbArgs) -> Result<Pool<Postgres>> {
+    let first = cluster_endpoints(db_args)?.swap_remove(0);
+    let failover = db_args.failover_endpoints.iter().map(|host| Endpoint {
+        host: host.clone(),
+        region: first.region.clone(),
+    });
+    let candidates: Vec<Endpoint> = std::iter::once(first.clone()).chain(failover).collect();
+    connect_any(creds, db_args, &candidates).await
+}
+
+/// Connect to the cluster through `endpoint` only, retrying like `get_pool`
+pub async fn get_pool_at(
+    creds: &CredentialCache,
+    db_args: &DbArgs,
+    endpoint: &Endpoint,
+) -> Result<Pool<Postgres>> {
+    connect_any(creds, db_args, std::slice::from_ref(endpoint)).await
+}
+
+/// Connect through the first of `candidates` that accepts, backing off
+/// between rounds once they've all failed
+async fn connect_any(
+    creds: &CredentialCache,
+    db_args: &DbArgs,
+    candidates: &[Endpoint],
+) -> Result<Pool<Postgres>> {
+    let mut backoff = INITIAL_CONNECT_BACKOFF;
+    let mut attempt = 0;
+    loop {
+        let mut last_err = None;
+        for (i, Endpoint { host, region }) in candidates.iter().enumerate() {
+            // Tokens are short-lived and signed for one endpoint, so each
+            // attempt generates a fresh one
+            let result = match build_dsql_options(creds, host, region).await {
+                Ok(options) => connect(with_ssl(options, db_args), &PoolConfig::of(db_args)).await,
+                Err(err) => Err(err),
+            };
+            match result {
+                Ok(pool) => {
+                    if candidates.len() > 1 {
+                        tracing::info!(endpoint = host, region, "connected to DSQL");
+                    }
+                    return Ok(pool);
+                }
+                Err(err) => {
+                    if i + 1 < candidates.len() {
+                        tracing::warn!(
+                            endpoint = host,
+                            "DSQL connection failed, trying the next endpoint: {:#}",
+                            err
+                        );
+                    }
+                    last_err = Some(err);
+                }
+            }
+        }
+        let err = last_err.expect("at least one endpoint to connect to");
+        if attempt >= db_args.db_connect_retries {
+            return Err(err.context(Failure::Connectivity));
+        }
+        attempt += 1;
+        tracing::warn!(
+            attempt,
+            region = candidates[0].region,
+            backoff_ms = backoff.as_millis() as u64,
+            "DSQL connection failed, retrying: {:#}",
+            err
+        );
+        tokio::time::sleep(backoff).await;
+        backoff *= 2;
+    }
+}
+
+/// The cluster endpoints to connect to: those passed with `--endpoints`,
+/// otherwise `--cluster-endpoint` in `--region`, as filled in by
+/// [`config::resolve`](crate::config::resolve). Never empty.
+pub fn cluster_endpoints(db_args: &DbArgs) -> Result<Vec<Endpoint>> {
+    if !db_args.endpoints.is_empty() {
+        return Ok(db_args.endpoints.clone());
+    }
+    let host = db_args.cluster_endpoint.clone().context(
+        "No cluster endpoint: set cluster_endpoint in ~/.riv25-codetalk.toml (or --config), \
+         or pass --cluster-endpoint, or set CLUSTER_ENDPOINT",
+    )
+    .context(Failure::Config)?;
+    Ok(vec![Endpoint {
+        host,
+        region: cluster_region(db_args),
+    }])
+}
+
+/// The region of the first cluster endpoint
+pub fn cluster_region(db_args: &DbArgs) -> String {
+    match db_args.endpoints.first() {
+        Some(endpoint) => endpoint.region.clone(),
+        None => db_args.region.clone().unwrap_or_else(|| DEFAULT_REGION.to_string()),
+    }
+}
+
+/// Connection options for the cluster's admin user, authenticated with a
+/// newly generated IAM token
+pub async fn build_dsql_options(
+    creds: &CredentialCache,
+    cluster_endpoint: &str,
+    region: &str,
+) -> Result<PgConnectOptions> {
     let credentials = creds.get_credentials().await?;
     let credentials_provider =
         aws_credential_types::provider::SharedCredentialsProvider::new(credentials);
 
     let sdk_config = aws_config::defaults(BehaviorVersion::latest())
         .credentials_provider(credentials_provider)
-        .region(aws_config::Region::new(region.clone()))
+        .region(aws_config::Region::new(region.to_string()))
         .load()
         .await;
 
     let config = Config::builder()
-        .hostname(&cluster_endpoint)
-        .region(aws_config::Region::new(region))
+        .hostname(cluster_endpoint)
+        .region(aws_config::Region::new(region.to_string()))
         .build()
         .map_err(|e| anyhow::anyhow!("Failed to build config: {}", e))?;
 
@@ -31,18 +601,58 @@ pub async fn get_pool(creds: &CredentialCache) -> Result<Pool<Postgres>> {
         .await
         .map_err(|e| anyhow::anyhow!("Failed to generate admin token: {}", e))?;
 
-    let options = PgConnectOptions::new()
-        .host(&cluster_endpoint)
+    Ok(PgConnectOptions::new()
+        .host(cluster_endpoint)
         .port(5432)
         .database("postgres")
         .username("admin")
-        .password(token.as_str())
-        .ssl_mode(sqlx::postgres::PgSslMode::Require);
+        .password(token.as_str()))
+}
 
-    let pool = PgPoolOptions::new()
-        .max_connections(1_000)
-        .connect_with(options)
-        .await?;
+/// Apply `--ssl-mode` and `--ssl-root-cert` to connection options
+pub fn with_ssl(options: PgConnectOptions, db_args: &DbArgs) -> PgConnectOptions {
+    let mode = match db_args.ssl_mode {
+        SslMode::Disable => PgSslMode::Disable,
+        SslMode::Prefer => PgSslMode::Prefer,
+        SslMode::Require => PgSslMode::Require,
+        SslMode::VerifyCa => PgSslMode::VerifyCa,
+        SslMode::VerifyFull => PgSslMode::VerifyFull,
+    };
+    let options = options.ssl_mode(mode);
+    match &db_args.ssl_root_cert {
+        Some(path) => options.ssl_root_cert(path),
+        None => options,
+    }
+}
+
+/// Open a pool against any Postgres, DSQL or otherwise
+pub async fn connect(
+    options: PgConnectOptions,
+    pool_config: &PoolConfig,
+) -> Result<Pool<Postgres>> {
+    let mut pool_options = PgPoolOptions::new().max_connections(pool_config.max_connections);
+    if let Some(timeout) = pool_config.acquire_timeout {
+        pool_options = pool_options.acquire_timeout(timeout);
+    }
+    let mut settings = Vec::new();
+    if let Some(timeout) = pool_config.statement_timeout {
+        settings.push(format!("SET statement_timeout = {}", timeout.as_millis()));
+    }
+    if let Some(schema) = &pool_config.search_path {
+        settings.push(format!("SET search_path = {}", schema));
+    }
+    if !settings.is_empty() {
+        pool_options = pool_options.after_connect(move |conn, _| {
+            let settings = settings.clone();
+            Box::pin(async move {
+                for set in &settings {
+                    conn.execute(set.as_str()).await?;
+                }
+                Ok(())
+            })
+        });
+    }
+    let pool = pool_options.connect_with(options).await?;
 
     Ok(pool)
 }
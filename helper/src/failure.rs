@@ -0,0 +1,54 @@
+//! Exit statuses, so scripts can tell why a command failed.
+//!
+//! | Code | Meaning                                                     |
+//! |------|-------------------------------------------------------------|
+//! | 0    | Success                                                     |
+//! | 1    | Any other failure                                           |
+//! | 2    | Bad arguments or configuration                              |
+//! | 3    | The cluster or the Lambda couldn't be reached               |
+//! | 4    | A run failed a gate: `--max-p99-ms`, `--max-error-rate`, a  |
+//! |      | `compare` regression limit, or no rate met the SLOs         |
+//! | 5    | Balances or the ledger didn't add up after a run            |
+//!
+//! Errors are tagged by adding a [`Failure`] as context, which `main` finds
+//! however much context was added on top.
+
+use std::fmt;
+
+/// Why a command failed, for the ones automation needs to tell apart
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Failure {
+    Config,
+    Connectivity,
+    Gate,
+    Conservation,
+}
+
+impl Failure {
+    /// The tag on `err`, if it has one
+    pub fn of(err: &anyhow::Error) -> Option<Self> {
+        err.downcast_ref::<Failure>().copied()
+    }
+
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Failure::Config => 2,
+            Failure::Connectivity => 3,
+            Failure::Gate => 4,
+            Failure::Conservation => 5,
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Failure::Config => "invalid configuration",
+            Failure::Connectivity => "couldn't connect",
+            Failure::Gate => "run gate failed",
+            Failure::Conservation => "consistency check failed",
+        })
+    }
+}
+
+impl std::error::Error for Failure {}
@@ -0,0 +1,194 @@
+use crate::{
+    cli::{DbArgs, Endpoint, LambdaArgs, MockArgs},
+    credentials::CredentialCache,
+    db,
+    failure::Failure,
+    lambda::{self, greeting},
+    progress,
+};
+use anyhow::Result;
+use hdrhistogram::Histogram;
+use indicatif::ProgressStyle;
+use std::time::{Duration, Instant};
+
+/// Pre-flight check that the Lambda can reach DSQL and that this machine can
+/// too. Both checks always run; fails if either does.
+pub async fn health_check(
+    creds: &CredentialCache,
+    db_args: &DbArgs,
+    lambda_args: &LambdaArgs,
+    mock: &MockArgs,
+) -> Result<()> {
+    println!("Health check\n");
+
+    let lambda_ok = report("Lambda", check_lambda(creds, lambda_args, db_args, mock).await);
+    let dsql_ok = report("DSQL", check_dsql(creds, db_args).await);
+    println!();
+
+    if !(lambda_ok && dsql_ok) {
+        return Err(anyhow::anyhow!("Health check failed").context(Failure::Connectivity));
+    }
+    println!("✅ All checks passed");
+    Ok(())
+}
+
+fn report(name: &str, result: Result<Duration>) -> bool {
+    match result {
+        Ok(latency) => {
+            println!("✅ {:<8} {}ms", name, latency.as_millis());
+            true
+        }
+        Err(err) => {
+            println!("❌ {:<8} {:#}", name, err);
+            false
+        }
+    }
+}
+
+async fn check_lambda(
+    creds: &CredentialCache,
+    lambda_args: &LambdaArgs,
+    db_args: &DbArgs,
+    mock: &MockArgs,
+) -> Result<Duration> {
+    let client_pool = lambda::client_pool(creds, 1, lambda_args, db_args, mock).await?;
+    let req = greeting::Request {
+        name: "healthcheck".to_string(),
+    };
+
+    let start = Instant::now();
+    let response: greeting::Response = lambda::invoke(client_pool.get(), &req).await?;
+    let latency = start.elapsed();
+
+    if !response.greeting.contains("connected to DSQL successfully") {
+        anyhow::bail!("unexpected greeting: {:?}", response.greeting);
+    }
+    Ok(latency)
+}
+
+async fn check_dsql(creds: &CredentialCache, db_args: &DbArgs) -> Result<Duration> {
+    let pool = db::get_pool(creds, db_args).await?;
+
+    let start = Instant::now();
+    sqlx::query("SELECT 1").execute(&pool).await?;
+    Ok(start.elapsed())
+}
+
+/// Open `count` fresh connections one after another, each in its own
+/// single-connection pool, and report how long the handshakes took with and
+/// without generating the auth token
+pub async fn conn_latency(creds: &CredentialCache, db_args: &DbArgs, count: usize) -> Result<()> {
+    anyhow::ensure!(count > 0, "--count must be at least 1");
+    let Endpoint {
+        host: cluster_endpoint,
+        region,
+    } = db::cluster_endpoints(db_args)?.swap_remove(0);
+    let pool_config = db::PoolConfig {
+        max_connections: 1,
+        ..db::PoolConfig::default()
+    };
+
+    println!("Opening {} connections to {}\n", count, cluster_endpoint);
+
+    // Microseconds, as handshakes can be only a few milliseconds
+    let mut token_hist: Histogram<u64> = Histogram::new(3)?;
+    let mut connect_hist: Histogram<u64> = Histogram::new(3)?;
+    let mut total_hist: Histogram<u64> = Histogram::new(3)?;
+
+    let pb = progress::bar(count as u64);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("[{bar:40}] {pos}/{len} connections")?
+            .progress_chars("=>-"),
+    );
+    for _ in 0..count {
+        let start = Instant::now();
+        let options = db::build_dsql_options(creds, &cluster_endpoint, &region).await?;
+        let token = start.elapsed();
+        let pool = db::connect(db::with_ssl(options, db_args), &pool_config).await?;
+        let total = start.elapsed();
+        pool.close().await;
+
+        token_hist.record(token.as_micros() as u64)?;
+        connect_hist.record((total - token).as_micros() as u64)?;
+        total_hist.record(total.as_micros() as u64)?;
+        pb.inc(1);
+    }
+    pb.finish_and_clear();
+
+    println!(
+        "{:<10} {:>9} {:>9} {:>9} {:>9}",
+        "(ms)", "min", "p50", "p99", "max"
+    );
+    for (label, hist) in [
+        ("Token", &token_hist),
+        ("Handshake", &connect_hist),
+        ("Total", &total_hist),
+    ] {
+        let ms = |micros: u64| micros as f64 / 1000.0;
+        println!(
+            "{:<10} {:>9.2} {:>9.2} {:>9.2} {:>9.2}",
+            label,
+            ms(hist.min()),
+            ms(hist.value_at_quantile(0.5)),
+            ms(hist.value_at_quantile(0.99)),
+            ms(hist.max())
+        );
+    }
+    println!();
+
+    Ok(())
+}
+
+/// Call the credential provider chain `iterations` times, one after
+/// another and bypassing the cache, and report how long each call took:
+/// the delay a request pays whenever the cached credentials need refreshing
+pub async fn cred_bench(creds: &CredentialCache, iterations: usize) -> Result<()> {
+    anyhow::ensure!(iterations > 0, "--iterations must be at least 1");
+    println!("Fetching credentials {} times\n", iterations);
+
+    // The first call also resolves the provider chain, which only happens
+    // once per process
+    let start = Instant::now();
+    let credentials = creds.provide_uncached().await?;
+    let first = start.elapsed();
+
+    // Microseconds, as environment credentials take next to no time
+    let mut hist: Histogram<u64> = Histogram::new(3)?;
+    let pb = progress::bar(iterations as u64);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("[{bar:40}] {pos}/{len} fetches")?
+            .progress_chars("=>-"),
+    );
+    for _ in 0..iterations {
+        let start = Instant::now();
+        creds.provide_uncached().await?;
+        hist.record(start.elapsed().as_micros() as u64)?;
+        pb.inc(1);
+    }
+    pb.finish_and_clear();
+
+    let ms = |micros: u64| micros as f64 / 1000.0;
+    println!("First fetch (resolving the chain): {:.2}ms", first.as_secs_f64() * 1000.0);
+    match credentials.expiry() {
+        Some(_) => println!("Credentials: temporary, refreshed as they near expiry"),
+        None => println!("Credentials: long-lived, never refreshed"),
+    }
+    println!();
+    println!(
+        "{:<10} {:>9} {:>9} {:>9} {:>9}",
+        "(ms)", "min", "p50", "p99", "max"
+    );
+    println!(
+        "{:<10} {:>9.2} {:>9.2} {:>9.2} {:>9.2}",
+        "Fetch",
+        ms(hist.min()),
+        ms(hist.value_at_quantile(0.5)),
+        ms(hist.value_at_quantile(0.99)),
+        ms(hist.max())
+    );
+    println!();
+
+    Ok(())
+}
@@ -1,15 +1,471 @@
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::time::Duration;
+use std::collections::HashSet;
+use std::io::{ErrorKind, Read, Write};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use aws_config::{retry::RetryConfig, timeout::TimeoutConfig, BehaviorVersion};
-use aws_sdk_lambda::{error::SdkError, primitives::Blob, Client};
-use serde::{de::DeserializeOwned, Serialize};
+use aws_sdk_lambda::config::interceptors::{
+    BeforeDeserializationInterceptorContextRef, BeforeTransmitInterceptorContextRef,
+};
+use aws_sdk_lambda::config::{ConfigBag, Intercept, RuntimeComponents};
+use aws_sdk_lambda::error::BoxError;
+use aws_sdk_lambda::{error::SdkError, operation::RequestId, primitives::Blob, Client};
+use aws_smithy_runtime_api::client::connection::CaptureSmithyConnection;
+use aws_smithy_runtime_api::client::result::DispatchFailure;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use hdrhistogram::Histogram;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use uuid::Uuid;
 
+use crate::cli::{DbArgs, Endpoint, FunctionWeight, LambdaArgs, MockArgs};
 use crate::credentials::CredentialCache;
+use crate::db;
+use crate::mock::MockLambda;
 
 const FUNCTION_NAME: &str = "reinvent-dat401";
 
+/// Something that can be invoked like the Lambda function
+pub struct LambdaClient {
+    backend: Backend,
+    /// Where to write payloads for `--dump-payloads`, shared by the pool
+    dumps: Option<Arc<PayloadDump>>,
+    /// Gzip payloads longer than this many bytes (`--compress-over`)
+    compress_over: Option<usize>,
+    /// Invocations awaiting a reply, counted across the pool
+    in_flight: Arc<AtomicUsize>,
+    /// Which HTTP connections the pool has used, and how fast invocations
+    /// on new and reused ones were
+    connections: Arc<ConnectionStats>,
+    /// The region this client invokes in, shared with the region's other
+    /// clients, when `--endpoints` spreads the pool over several
+    region: Option<Arc<Tally>>,
+    /// The functions to split invocations between, shared by the pool
+    functions: Arc<Functions>,
+    /// Payload bytes sent and received, counted across the pool
+    traffic: Arc<TrafficCounter>,
+    /// The run to name in each invocation's client context, shared by the
+    /// pool, with `--client-context`
+    run_tag: Option<Arc<RunTag>>,
+    /// Time spent in each step of invoking, shared by the pool, with
+    /// `--phase-breakdown`
+    phases: Option<Arc<PhaseTimes>>,
+}
+
+/// Total microseconds invocations spent in each client-side step
+#[derive(Debug, Default)]
+struct PhaseTimes {
+    invocations: AtomicU64,
+    serialize_us: AtomicU64,
+    dispatch_us: AtomicU64,
+    /// Signing happens once per SDK attempt, so is counted separately
+    signings: AtomicU64,
+    sign_us: AtomicU64,
+    deserialize_us: AtomicU64,
+}
+
+impl PhaseTimes {
+    fn record(&self, serialize: Duration, dispatch: Duration, deserialize: Duration) {
+        self.invocations.fetch_add(1, Ordering::Relaxed);
+        add_micros(&self.serialize_us, serialize);
+        add_micros(&self.dispatch_us, dispatch);
+        add_micros(&self.deserialize_us, deserialize);
+    }
+
+    fn snapshot(&self) -> PhaseBreakdown {
+        let invocations = self.invocations.load(Ordering::Relaxed);
+        let signings = self.signings.load(Ordering::Relaxed);
+        let avg = |total: &AtomicU64, count: u64| {
+            Duration::from_micros(total.load(Ordering::Relaxed) / count.max(1))
+        };
+        PhaseBreakdown {
+            invocations,
+            serialize: avg(&self.serialize_us, invocations),
+            dispatch: avg(&self.dispatch_us, invocations),
+            sign: (signings > 0).then(|| avg(&self.sign_us, signings)),
+            deserialize: avg(&self.deserialize_us, invocations),
+        }
+    }
+}
+
+fn add_micros(total: &AtomicU64, elapsed: Duration) {
+    total.fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+}
+
+/// Average time an invocation that got a reply spent in each client-side
+/// step
+#[derive(Clone, Copy, Debug)]
+pub struct PhaseBreakdown {
+    pub invocations: u64,
+    /// Encoding the request, and compressing it with `--compress-over`
+    pub serialize: Duration,
+    /// From handing the request to the backend until its reply came back
+    pub dispatch: Duration,
+    /// Signing each SDK attempt, including looking up credentials; part of
+    /// `dispatch`, and only measured for the SDK
+    pub sign: Option<Duration>,
+    /// Parsing the reply
+    pub deserialize: Duration,
+}
+
+/// The current run's id and workload, encoded as a Lambda client context:
+/// base64 JSON whose `custom` object the function sees as
+/// `context.clientContext.custom`
+#[derive(Default)]
+struct RunTag(Mutex<Option<String>>);
+
+impl RunTag {
+    fn set(&self, run_id: Uuid, workload: &str) {
+        let context = serde_json::json!({
+            "custom": { "run_id": run_id.to_string(), "workload": workload },
+        });
+        *self.0.lock().unwrap() = Some(BASE64.encode(context.to_string()));
+    }
+
+    fn get(&self) -> Option<String> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+/// Client-measured latency and errors of a share of the invocations, such
+/// as one region's
+struct Tally {
+    label: String,
+    latency: Mutex<Histogram<u64>>,
+    errors: AtomicUsize,
+}
+
+impl Tally {
+    fn new(label: &str) -> Self {
+        Self {
+            label: label.to_string(),
+            latency: Mutex::new(Histogram::new(3).unwrap()),
+            errors: AtomicUsize::new(0),
+        }
+    }
+
+    fn record(&self, elapsed: Duration, failed: bool) {
+        let elapsed = elapsed.as_millis() as u64;
+        self.latency.lock().unwrap().record(elapsed).ok();
+        if failed {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn snapshot(&self) -> LabelledLatency {
+        LabelledLatency {
+            label: self.label.clone(),
+            latency: self.latency.lock().unwrap().clone(),
+            errors: self.errors.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// How one share of the invocations went, as of when it was taken
+pub struct LabelledLatency {
+    pub label: String,
+    pub latency: Histogram<u64>,
+    pub errors: usize,
+}
+
+/// The functions invocations are split between, each taking turns in
+/// proportion to its weight
+struct Functions {
+    targets: Vec<(u32, Tally)>,
+    total_weight: u32,
+    next: AtomicUsize,
+}
+
+impl Functions {
+    /// `--functions`, or just `default` if empty
+    fn new(functions: &[FunctionWeight], default: &str) -> Self {
+        let targets: Vec<(u32, Tally)> = if functions.is_empty() {
+            vec![(1, Tally::new(default))]
+        } else {
+            functions
+                .iter()
+                .map(|f| (f.weight, Tally::new(&f.name)))
+                .collect()
+        };
+        Self {
+            total_weight: targets.iter().map(|(weight, _)| weight).sum(),
+            targets,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// The function to send the next invocation to
+    fn pick(&self) -> &Tally {
+        let mut slot = (self.next.fetch_add(1, Ordering::Relaxed) % self.total_weight as usize)
+            as u32;
+        for (weight, target) in &self.targets {
+            if slot < *weight {
+                return target;
+            }
+            slot -= weight;
+        }
+        unreachable!("slot is below the total weight")
+    }
+}
+
+/// Payload bytes sent and received by the pool so far
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Traffic {
+    pub requests: u64,
+    pub sent_bytes: u64,
+    pub responses: u64,
+    pub received_bytes: u64,
+}
+
+impl Traffic {
+    pub fn avg_request_bytes(&self) -> f64 {
+        average(self.sent_bytes, self.requests)
+    }
+
+    pub fn avg_response_bytes(&self) -> f64 {
+        average(self.received_bytes, self.responses)
+    }
+}
+
+fn average(bytes: u64, count: u64) -> f64 {
+    if count > 0 {
+        bytes as f64 / count as f64
+    } else {
+        0.0
+    }
+}
+
+#[derive(Default)]
+struct TrafficCounter {
+    requests: AtomicU64,
+    sent_bytes: AtomicU64,
+    responses: AtomicU64,
+    received_bytes: AtomicU64,
+}
+
+impl TrafficCounter {
+    fn sent(&self, bytes: usize) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.sent_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    fn received(&self, bytes: usize) {
+        self.responses.fetch_add(1, Ordering::Relaxed);
+        self.received_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> Traffic {
+        Traffic {
+            requests: self.requests.load(Ordering::Relaxed),
+            sent_bytes: self.sent_bytes.load(Ordering::Relaxed),
+            responses: self.responses.load(Ordering::Relaxed),
+            received_bytes: self.received_bytes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Counts an invocation as in flight until dropped, even if cancelled
+struct InFlight<'a>(&'a AtomicUsize);
+
+impl<'a> InFlight<'a> {
+    fn start(count: &'a AtomicUsize) -> Self {
+        count.fetch_add(1, Ordering::Relaxed);
+        Self(count)
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Round-trip latency of SDK invocations, split by whether the HTTP
+/// connection was opened for the invocation (paying for TCP and TLS) or
+/// reused from the client's connection pool
+#[derive(Clone)]
+pub struct ConnectionLatency {
+    pub new: Histogram<u64>,
+    pub reused: Histogram<u64>,
+}
+
+struct ConnectionStats {
+    /// Local addresses of every connection seen; a connection is new the
+    /// first time its address turns up
+    seen: Mutex<HashSet<SocketAddr>>,
+    latency: Mutex<ConnectionLatency>,
+}
+
+impl ConnectionStats {
+    fn new() -> Self {
+        Self {
+            seen: Mutex::new(HashSet::new()),
+            latency: Mutex::new(ConnectionLatency {
+                new: Histogram::new(3).unwrap(),
+                reused: Histogram::new(3).unwrap(),
+            }),
+        }
+    }
+
+    fn record(&self, new: bool, elapsed: Duration) {
+        let mut latency = self.latency.lock().unwrap();
+        let hist = if new {
+            &mut latency.new
+        } else {
+            &mut latency.reused
+        };
+        hist.record(elapsed.as_millis() as u64).ok();
+    }
+}
+
+impl std::fmt::Debug for ConnectionStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ConnectionStats").finish_non_exhaustive()
+    }
+}
+
+/// Notes whether an invocation went out on a connection the pool hadn't
+/// used before. Installed per request, as the answer belongs to it.
+#[derive(Debug)]
+struct ConnectionTracker {
+    stats: Arc<ConnectionStats>,
+    new: Arc<Mutex<Option<bool>>>,
+}
+
+impl Intercept for ConnectionTracker {
+    fn name(&self) -> &'static str {
+        "ConnectionTracker"
+    }
+
+    fn read_after_transmit(
+        &self,
+        _context: &BeforeDeserializationInterceptorContextRef<'_>,
+        _runtime_components: &RuntimeComponents,
+        cfg: &mut ConfigBag,
+    ) -> Result<(), BoxError> {
+        let local_addr = cfg
+            .load::<CaptureSmithyConnection>()
+            .and_then(CaptureSmithyConnection::get)
+            .and_then(|conn| conn.local_addr());
+        if let Some(addr) = local_addr {
+            let new = self.stats.seen.lock().unwrap().insert(addr);
+            *self.new.lock().unwrap() = Some(new);
+        }
+        Ok(())
+    }
+}
+
+/// Times the SDK's signing of a request, which resolves its credentials
+/// first. Installed per request, like `ConnectionTracker`.
+#[derive(Debug)]
+struct SigningTimer {
+    phases: Arc<PhaseTimes>,
+    started: Mutex<Option<Instant>>,
+}
+
+impl Intercept for SigningTimer {
+    fn name(&self) -> &'static str {
+        "SigningTimer"
+    }
+
+    fn read_before_signing(
+        &self,
+        _context: &BeforeTransmitInterceptorContextRef<'_>,
+        _runtime_components: &RuntimeComponents,
+        _cfg: &mut ConfigBag,
+    ) -> Result<(), BoxError> {
+        *self.started.lock().unwrap() = Some(Instant::now());
+        Ok(())
+    }
+
+    fn read_after_signing(
+        &self,
+        _context: &BeforeTransmitInterceptorContextRef<'_>,
+        _runtime_components: &RuntimeComponents,
+        _cfg: &mut ConfigBag,
+    ) -> Result<(), BoxError> {
+        if let Some(started) = self.started.lock().unwrap().take() {
+            self.phases.signings.fetch_add(1, Ordering::Relaxed);
+            add_micros(&self.phases.sign_us, started.elapsed());
+        }
+        Ok(())
+    }
+}
+
+enum Backend {
+    Sdk(Client),
+    /// Local simulator used with `--mock`, shared by the whole pool so it
+    /// sees every idempotency key
+    Mock(Arc<MockLambda>),
+    /// Run what the Lambda would against DSQL from here (`--direct`)
+    Direct(sqlx::PgPool),
+}
+
+/// Writes the exact request and response of invocations to a directory, for
+/// debugging failures that logs don't explain
+struct PayloadDump {
+    dir: PathBuf,
+    /// Dump every invocation rather than just failed ones
+    all: bool,
+    /// Keeps file names unique when invocations finish in the same
+    /// microsecond
+    seq: AtomicUsize,
+}
+
+impl PayloadDump {
+    fn create(dir: PathBuf, all: bool) -> Result<Self> {
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create payload directory {}", dir.display()))?;
+        Ok(Self {
+            dir,
+            all,
+            seq: AtomicUsize::new(0),
+        })
+    }
+
+    /// Dump the invocation if it failed, or if dumping everything. Failing
+    /// to write is logged rather than failing the invocation.
+    fn record(&self, request: &str, result: &Result<Vec<u8>>) {
+        if !self.all && !failed(result) {
+            return;
+        }
+        let stem = format!(
+            "{}-{:06}",
+            chrono::Utc::now().format("%Y%m%dT%H%M%S%.6fZ"),
+            self.seq.fetch_add(1, Ordering::Relaxed)
+        );
+        let (suffix, response) = match result {
+            Ok(bytes) => ("response.json", bytes.clone()),
+            Err(err) => ("error.txt", format!("{err:#}").into_bytes()),
+        };
+        for (name, contents) in [
+            (format!("{stem}.request.json"), request.as_bytes()),
+            (format!("{stem}.{suffix}"), &response[..]),
+        ] {
+            let path = self.dir.join(name);
+            if let Err(err) = std::fs::write(&path, contents) {
+                tracing::warn!(path = %path.display(), "failed to dump payload: {}", err);
+            }
+        }
+    }
+}
+
+/// Whether an invocation failed outright, returned something other than
+/// JSON, or returned JSON with an `error` set
+fn failed(result: &Result<Vec<u8>>) -> bool {
+    let Ok(bytes) = result else {
+        return true;
+    };
+    match serde_json::from_slice::<serde_json::Value>(bytes) {
+        Ok(value) => value.get("error").is_some_and(|e| !e.is_null()),
+        Err(_) => true,
+    }
+}
+
 /// Pool of Lambda clients to distribute load across multiple HTTP connections
 #[derive(Clone)]
 pub struct ClientPool {
@@ -17,15 +473,172 @@ pub struct ClientPool {
 }
 
 struct ClientPoolInner {
-    clients: Vec<Client>,
+    clients: Vec<LambdaClient>,
     counter: AtomicUsize,
+    /// Requests handed to each client, to check the round-robin spreads load
+    requests: Vec<AtomicUsize>,
+    /// The database pool of each region behind `--direct`
+    direct_pools: Vec<sqlx::PgPool>,
+    region: String,
+    /// `deadline_ms` to send with each transfer, with `--propagate-deadline`
+    deadline_ms: Option<u64>,
 }
 
 impl ClientPool {
-    pub fn get(&self) -> &Client {
+    fn new(
+        clients: Vec<LambdaClient>,
+        direct_pools: Vec<sqlx::PgPool>,
+        region: String,
+        deadline_ms: Option<u64>,
+    ) -> Self {
+        let requests = clients.iter().map(|_| AtomicUsize::new(0)).collect();
+        Self {
+            inner: std::sync::Arc::new(ClientPoolInner {
+                clients,
+                counter: AtomicUsize::new(0),
+                requests,
+                direct_pools,
+                region,
+                deadline_ms,
+            }),
+        }
+    }
+
+    /// The `deadline_ms` transfers sent through the pool should carry
+    pub fn deadline_ms(&self) -> Option<u64> {
+        self.inner.deadline_ms
+    }
+
+    /// The region of the cluster behind the pool, as shown in run metadata
+    pub fn region(&self) -> &str {
+        &self.inner.region
+    }
+
+    pub fn get(&self) -> &LambdaClient {
         let idx = self.inner.counter.fetch_add(1, Ordering::Relaxed) % self.inner.clients.len();
+        self.inner.requests[idx].fetch_add(1, Ordering::Relaxed);
         &self.inner.clients[idx]
     }
+
+    /// How many times `get` has returned each client
+    pub fn request_counts(&self) -> Vec<usize> {
+        self.inner
+            .requests
+            .iter()
+            .map(|n| n.load(Ordering::Relaxed))
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.clients.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.clients.is_empty()
+    }
+
+    /// Invocations sent through the pool that haven't returned yet
+    pub fn in_flight(&self) -> usize {
+        self.inner
+            .clients
+            .first()
+            .map_or(0, |c| c.in_flight.load(Ordering::Relaxed))
+    }
+
+    /// Connection usage of the database pool behind `--direct`, which is
+    /// the only backend with one
+    pub fn db_pool_metrics(&self) -> Option<db::PoolMetrics> {
+        match &self.inner.clients.first()?.backend {
+            Backend::Direct(pool) => Some(db::PoolMetrics::of(pool)),
+            _ => None,
+        }
+    }
+
+    /// The database pools behind `--direct`, one per region, or none
+    pub fn direct_pools(&self) -> &[sqlx::PgPool] {
+        &self.inner.direct_pools
+    }
+
+    /// The functions the pool invokes, as shown in run metadata
+    pub fn function_name(&self) -> String {
+        let Some(client) = self.inner.clients.first() else {
+            return String::new();
+        };
+        match &client.backend {
+            Backend::Mock(_) => "(mock)".to_string(),
+            Backend::Direct(_) => "(direct)".to_string(),
+            Backend::Sdk(_) => {
+                let names: Vec<&str> = client
+                    .functions
+                    .targets
+                    .iter()
+                    .map(|(_, target)| target.label.as_str())
+                    .collect();
+                names.join(",")
+            }
+        }
+    }
+
+    /// Latency and errors per region, in `--endpoints` order, or nothing if
+    /// the pool isn't spread over regions
+    pub fn region_latency(&self) -> Vec<LabelledLatency> {
+        let mut regions: Vec<LabelledLatency> = Vec::new();
+        for stats in self.inner.clients.iter().filter_map(|c| c.region.as_ref()) {
+            if !regions.iter().any(|r| r.label == stats.label) {
+                regions.push(stats.snapshot());
+            }
+        }
+        regions
+    }
+
+    /// Latency and errors per function, in `--functions` order, or nothing
+    /// if the pool only invokes one
+    pub fn function_latency(&self) -> Vec<LabelledLatency> {
+        match self.inner.clients.first() {
+            Some(client) if client.functions.targets.len() > 1 => client
+                .functions
+                .targets
+                .iter()
+                .map(|(_, target)| target.snapshot())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Invocation latency on new vs reused HTTP connections, or `None` if
+    /// no invocation could tell which it used (the mock and `--direct`
+    /// never can)
+    pub fn connection_latency(&self) -> Option<ConnectionLatency> {
+        let latency = self.inner.clients.first()?.connections.latency.lock().unwrap();
+        if latency.new.is_empty() && latency.reused.is_empty() {
+            return None;
+        }
+        Some(latency.clone())
+    }
+
+    /// Name `run_id` and `workload` in the client context of every later
+    /// invocation, if `--client-context` asked for it
+    pub fn tag_run(&self, run_id: Uuid, workload: &str) {
+        if let Some(tag) = self.inner.clients.first().and_then(|c| c.run_tag.as_ref()) {
+            tag.set(run_id, workload);
+        }
+    }
+
+    /// Average time invocations spent in each client-side step, with
+    /// `--phase-breakdown`, or `None` if not asked for or nothing replied
+    pub fn phase_breakdown(&self) -> Option<PhaseBreakdown> {
+        let breakdown = self.inner.clients.first()?.phases.as_ref()?.snapshot();
+        (breakdown.invocations > 0).then_some(breakdown)
+    }
+
+    /// Payload bytes sent and received through the pool, as sent on the
+    /// wire (so after `--compress-over`)
+    pub fn traffic(&self) -> Traffic {
+        self.inner
+            .clients
+            .first()
+            .map_or_else(Traffic::default, |c| c.traffic.snapshot())
+    }
 }
 
 pub mod greeting {
@@ -43,13 +656,82 @@ pub mod greeting {
 }
 
 pub mod tpcb {
-    use serde::{Deserialize, Serialize};
+    use serde::{Deserialize, Serialize, Serializer};
+    use std::fmt;
+    use uuid::Uuid;
+
+    /// How much a transfer moves
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum Amount {
+        /// Whole units, sent as a JSON number and moving `balance`
+        Whole(u32),
+        /// Hundredths, sent as a decimal string such as "12.34" so no float
+        /// rounds it on the way, and moving the NUMERIC `exact_balance`
+        /// (`--amount-decimal`)
+        Cents(u32),
+    }
+
+    impl Amount {
+        /// The amount in hundredths, whichever way it's sent
+        pub fn cents(self) -> i64 {
+            match self {
+                Amount::Whole(units) => units as i64 * 100,
+                Amount::Cents(cents) => cents as i64,
+            }
+        }
+
+        /// The amount of a request as sent: a whole number, or a decimal
+        /// string with at most two places
+        pub fn from_json(value: &serde_json::Value) -> Option<Self> {
+            if let Some(units) = value.as_u64() {
+                return u32::try_from(units).ok().map(Amount::Whole);
+            }
+            let (units, fraction) = value.as_str()?.split_once('.')?;
+            // Digits only: `parse` would take a sign too
+            if !units.bytes().chain(fraction.bytes()).all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            let units: u32 = units.parse().ok()?;
+            let fraction: u32 = match fraction.len() {
+                1 => fraction.parse::<u32>().ok()? * 10,
+                2 => fraction.parse().ok()?,
+                _ => return None,
+            };
+            units.checked_mul(100)?.checked_add(fraction).map(Amount::Cents)
+        }
+    }
+
+    impl fmt::Display for Amount {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Amount::Whole(units) => write!(f, "{}", units),
+                Amount::Cents(cents) => write!(f, "{}.{:02}", cents / 100, cents % 100),
+            }
+        }
+    }
+
+    impl Serialize for Amount {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            match self {
+                Amount::Whole(units) => serializer.serialize_u32(*units),
+                Amount::Cents(_) => serializer.collect_str(self),
+            }
+        }
+    }
 
     #[derive(Serialize, Debug)]
     pub struct Request {
         pub payer_id: u32,
         pub payee_id: u32,
-        pub amount: u32,
+        pub amount: Amount,
+        /// Client-generated key identifying this transfer, so a Lambda that
+        /// deduplicates can recognise a resend
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub idempotency_key: Option<Uuid>,
+        /// Milliseconds the Lambda has before the client stops waiting, so
+        /// it can abandon OCC retries whose reply nobody will read
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub deadline_ms: Option<u64>,
     }
 
     #[derive(Deserialize)]
@@ -58,61 +740,620 @@ pub mod tpcb {
         pub duration: Option<u64>,
         pub retries: Option<u32>,
         pub error: Option<String>,
+        /// The chapter Lambdas send this as `errorCode`
+        #[serde(alias = "errorCode")]
+        pub error_code: Option<String>,
+        /// Set when the Lambda recognised the idempotency key and did not
+        /// apply the transfer again
+        pub deduplicated: Option<bool>,
+        /// Set when the Lambda gave up retrying because `deadline_ms` passed
+        pub deadline_exceeded: Option<bool>,
+    }
+
+    /// Several transfers in one invocation, for Lambdas that accept
+    /// batches. Each is its own transaction.
+    #[derive(Serialize, Debug)]
+    pub struct BatchRequest {
+        pub transfers: Vec<Request>,
+    }
+
+    /// One result per transfer, in request order
+    #[derive(Deserialize)]
+    pub struct BatchResponse {
+        pub results: Vec<Response>,
+    }
+}
+
+/// Single-account balance lookup.
+///
+/// The Lambda must recognise requests carrying `account_id` as reads; the
+/// chapter handlers only implement transfers.
+pub mod balance_query {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Debug)]
+    pub struct Request {
+        pub account_id: u32,
+    }
+
+    #[derive(Deserialize)]
+    pub struct Response {
+        pub balance: Option<u32>,
+        pub duration: Option<u64>,
+        pub error: Option<String>,
+        #[serde(alias = "errorCode")]
         pub error_code: Option<String>,
     }
 }
 
-pub async fn client_pool(creds: &CredentialCache, size: usize) -> Result<ClientPool> {
+/// `--function-name`, or the demo's function
+fn default_function(lambda_args: &LambdaArgs) -> &str {
+    lambda_args.function_name.as_deref().unwrap_or(FUNCTION_NAME)
+}
+
+/// What a pool built from these arguments would invoke: `--functions`, the
+/// demo's function, or the mock or direct stand-in
+pub fn describe_target(lambda_args: &LambdaArgs, mock: &MockArgs) -> String {
+    if mock.mock {
+        return "(mock)".to_string();
+    }
+    if lambda_args.direct {
+        return "(direct)".to_string();
+    }
+    if lambda_args.functions.is_empty() {
+        return default_function(lambda_args).to_string();
+    }
+    let functions: Vec<String> = lambda_args
+        .functions
+        .iter()
+        .map(|f| format!("{} (weight {})", f.name, f.weight))
+        .collect();
+    functions.join(", ")
+}
+
+/// Build `size` clients, or `size` per region when `--endpoints` lists
+/// several, interleaved so the round-robin alternates between regions
+pub async fn client_pool(
+    creds: &CredentialCache,
+    size: usize,
+    lambda_args: &LambdaArgs,
+    db_args: &DbArgs,
+    mock: &MockArgs,
+) -> Result<ClientPool> {
+    anyhow::ensure!(size > 0, "client pool needs at least one client");
+    let dumps = match &lambda_args.dump_payloads {
+        Some(dir) => Some(Arc::new(PayloadDump::create(dir.clone(), lambda_args.dump_all)?)),
+        None => None,
+    };
+    let in_flight = Arc::new(AtomicUsize::new(0));
+    let connections = Arc::new(ConnectionStats::new());
+    let functions = Arc::new(Functions::new(
+        &lambda_args.functions,
+        default_function(lambda_args),
+    ));
+    let traffic = Arc::new(TrafficCounter::default());
+    let run_tag = lambda_args
+        .client_context
+        .then(|| Arc::new(RunTag::default()));
+    let phases = lambda_args
+        .phase_breakdown
+        .then(|| Arc::new(PhaseTimes::default()));
+    let client = |backend, region: &Option<Arc<Tally>>| LambdaClient {
+        backend,
+        dumps: dumps.clone(),
+        compress_over: lambda_args.compress_over,
+        in_flight: in_flight.clone(),
+        connections: connections.clone(),
+        region: region.clone(),
+        functions: functions.clone(),
+        traffic: traffic.clone(),
+        run_tag: run_tag.clone(),
+        phases: phases.clone(),
+    };
+    // The simulator is shared across regions too, so it sees every
+    // idempotency key
+    let simulator = mock.mock.then(|| Arc::new(MockLambda::new(mock)));
+    let endpoints: Vec<Option<&Endpoint>> = if db_args.endpoints.is_empty() {
+        vec![None]
+    } else {
+        db_args.endpoints.iter().map(Some).collect()
+    };
+
+    let mut regions = Vec::new();
+    let mut direct_pools = Vec::new();
+    for endpoint in endpoints {
+        let stats = endpoint.map(|e| Arc::new(Tally::new(&e.region)));
+        let backends: Vec<Backend> = if let Some(simulator) = &simulator {
+            (0..size).map(|_| Backend::Mock(simulator.clone())).collect()
+        } else if lambda_args.direct {
+            let pool = match endpoint {
+                Some(endpoint) => db::get_pool_at(creds, db_args, endpoint).await?,
+                None => db::get_pool(creds, db_args).await?,
+            };
+            direct_pools.push(pool.clone());
+            (0..size).map(|_| Backend::Direct(pool.clone())).collect()
+        } else {
+            let region = endpoint
+                .map(|e| e.region.as_str())
+                .or(db_args.region.as_deref());
+            let config = sdk_config(creds, lambda_args, region).await?;
+            (0..size)
+                .map(|_| Backend::Sdk(Client::new(&config)))
+                .collect()
+        };
+        regions.push(backends.into_iter().map(move |b| (b, stats.clone())));
+    }
+
+    let mut clients = Vec::with_capacity(size * regions.len());
+    for _ in 0..size {
+        for (backend, stats) in regions.iter_mut().filter_map(Iterator::next) {
+            clients.push(client(backend, &stats));
+        }
+    }
+    Ok(ClientPool::new(
+        clients,
+        direct_pools,
+        db::cluster_region(db_args),
+        request_deadline(lambda_args),
+    ))
+}
+
+/// How much of `--operation-timeout-ms` the Lambda is told it has, leaving
+/// time for the reply to get back before the client gives up
+const DEADLINE_MARGIN_MS: u64 = 100;
+
+/// The `deadline_ms` to send with transfers, if `--propagate-deadline`
+fn request_deadline(lambda_args: &LambdaArgs) -> Option<u64> {
+    if !lambda_args.propagate_deadline {
+        return None;
+    }
+    let timeout = lambda_args.operation_timeout_ms?;
+    Some(timeout.saturating_sub(DEADLINE_MARGIN_MS).max(1))
+}
+
+/// SDK configuration for invoking in `region`, or wherever the environment
+/// says if `None`
+async fn sdk_config(
+    creds: &CredentialCache,
+    lambda_args: &LambdaArgs,
+    region: Option<&str>,
+) -> Result<aws_config::SdkConfig> {
     let credentials = creds.get_credentials().await?;
     let credentials_provider =
         aws_credential_types::provider::SharedCredentialsProvider::new(credentials);
 
-    let config = aws_config::defaults(BehaviorVersion::latest())
+    let mut timeouts = TimeoutConfig::builder()
+        .connect_timeout(Duration::from_millis(lambda_args.connect_timeout_ms));
+    timeouts
+        .set_operation_timeout(lambda_args.operation_timeout_ms.map(Duration::from_millis))
+        .set_operation_attempt_timeout(
+            lambda_args
+                .operation_attempt_timeout_ms
+                .map(Duration::from_millis),
+        );
+
+    let mut loader = aws_config::defaults(BehaviorVersion::latest())
         .credentials_provider(credentials_provider)
-        .timeout_config(
-            TimeoutConfig::builder()
-                .connect_timeout(Duration::from_secs(30))
-                .build(),
+        .timeout_config(timeouts.build())
+        .retry_config(RetryConfig::standard().with_max_attempts(lambda_args.max_sdk_attempts));
+    if let Some(region) = region {
+        loader = loader.region(aws_config::Region::new(region.to_string()));
+    }
+    if let Some(url) = &lambda_args.endpoint_url {
+        loader = loader.endpoint_url(url);
+    }
+    Ok(loader.load().await)
+}
+
+pub async fn invoke<T: Serialize, R: DeserializeOwned>(
+    client: &LambdaClient,
+    payload: T,
+) -> Result<R> {
+    let serialize_start = Instant::now();
+    let payload_str = serde_json::to_string(&payload)?;
+    // Dumps keep the uncompressed payload, which is what's worth reading
+    let sent = match client.compress_over {
+        Some(limit) if payload_str.len() > limit => compress(&payload_str)?,
+        _ => payload_str.clone(),
+    };
+    let serialized = serialize_start.elapsed();
+    let in_flight = InFlight::start(&client.in_flight);
+    client.traffic.sent(sent.len());
+    let start = Instant::now();
+    let mut function = None;
+    let result = match &client.backend {
+        Backend::Sdk(sdk) => {
+            let target = function.insert(client.functions.pick());
+            let context = client.run_tag.as_deref().and_then(RunTag::get);
+            let phases = client.phases.as_ref();
+            invoke_sdk(sdk, &target.label, &sent, context, &client.connections, phases).await
+        }
+        Backend::Mock(mock) => mock.invoke(&sent).await,
+        Backend::Direct(pool) => invoke_direct(pool, &payload_str).await,
+    };
+    drop(in_flight);
+    let dispatched = start.elapsed();
+    if let Ok(response) = &result {
+        client.traffic.received(response.len());
+    }
+    for tally in client.region.as_deref().into_iter().chain(function) {
+        tally.record(start.elapsed(), result.is_err());
+    }
+    if let Some(dumps) = &client.dumps {
+        dumps.record(&payload_str, &result);
+    }
+    let response = result?;
+    let deserialize_start = Instant::now();
+    let decoded = decode(&response);
+    if let Some(phases) = &client.phases {
+        phases.record(serialized, dispatched, deserialize_start.elapsed());
+    }
+    decoded
+}
+
+/// How many characters of a response body to quote when it doesn't parse
+const RESPONSE_PREVIEW_CHARS: usize = 200;
+
+/// Parse a response, naming the field that didn't match and quoting the
+/// start of the body, so a Lambda whose contract has drifted is obvious
+fn decode<R: DeserializeOwned>(response: &[u8]) -> Result<R> {
+    let de = &mut serde_json::Deserializer::from_slice(response);
+    serde_path_to_error::deserialize(de).map_err(|err| {
+        let body = String::from_utf8_lossy(response);
+        let preview = match body.char_indices().nth(RESPONSE_PREVIEW_CHARS) {
+            Some((end, _)) => format!("{}...", &body[..end]),
+            None => body.to_string(),
+        };
+        anyhow::anyhow!(
+            "unexpected response shape at `{}`: {} (response: {})",
+            err.path(),
+            err.inner(),
+            preview
         )
-        .retry_config(RetryConfig::standard().with_max_attempts(3))
-        .load()
-        .await;
-
-    let clients = (0..size).map(|_| Client::new(&config)).collect();
-    Ok(ClientPool {
-        inner: std::sync::Arc::new(ClientPoolInner {
-            clients,
-            counter: AtomicUsize::new(0),
+    })
+}
+
+/// Answer a request the way the Lambda would, by running its queries
+/// against DSQL from here. Requests are told apart by their JSON fields, as
+/// the mock does.
+async fn invoke_direct(pool: &sqlx::PgPool, payload: &str) -> Result<Vec<u8>> {
+    let request: serde_json::Value = serde_json::from_str(payload)?;
+    let field = |name: &str| request.get(name).and_then(serde_json::Value::as_i64);
+    let start = std::time::Instant::now();
+
+    let response = if let Some(name) = request.get("name").and_then(|n| n.as_str()) {
+        sqlx::query("SELECT 1").execute(pool).await?;
+        serde_json::json!({ "greeting": format!("Hello {name}, connected to DSQL directly!") })
+    } else if let Some(account_id) = field("account_id") {
+        let balance: Option<(i32,)> = sqlx::query_as("SELECT balance FROM accounts WHERE id = $1")
+            .bind(account_id as i32)
+            .fetch_optional(pool)
+            .await?;
+        let duration = start.elapsed().as_millis() as u64;
+        match balance {
+            Some((balance,)) => serde_json::json!({ "balance": balance, "duration": duration }),
+            None => serde_json::json!({ "error": "Account not found", "duration": duration }),
+        }
+    } else if let Some(transfers) = request.get("transfers").and_then(|t| t.as_array()) {
+        let mut results = Vec::with_capacity(transfers.len());
+        for transfer in transfers {
+            results.push(direct_transfer(pool, transfer).await?);
+        }
+        serde_json::json!({ "results": results })
+    } else if request.get("payer_id").is_some() {
+        direct_transfer(pool, &request).await?
+    } else {
+        anyhow::bail!("function error: --direct doesn't understand {payload}");
+    };
+
+    Ok(serde_json::to_vec(&response)?)
+}
+
+/// Run one transfer request from here, answering as the Lambda would
+async fn direct_transfer(
+    pool: &sqlx::PgPool,
+    request: &serde_json::Value,
+) -> Result<serde_json::Value> {
+    let field = |name: &str| request.get(name).and_then(serde_json::Value::as_i64);
+    let amount = request.get("amount").and_then(tpcb::Amount::from_json);
+    let (Some(payer_id), Some(payee_id), Some(amount)) =
+        (field("payer_id"), field("payee_id"), amount)
+    else {
+        anyhow::bail!("function error: --direct doesn't understand {request}");
+    };
+    let start = std::time::Instant::now();
+    let (result, retries) = db::transfer(pool, payer_id as i32, payee_id as i32, amount).await;
+    let duration = start.elapsed().as_millis() as u64;
+    Ok(match result {
+        Ok(balance) => serde_json::json!({
+            "balance": balance,
+            "duration": duration,
+            "retries": retries,
+        }),
+        Err(err) => serde_json::json!({
+            "error": err.to_string(),
+            "error_code": db::error_code(&err),
+            "duration": duration,
+            "retries": retries,
         }),
     })
 }
 
-pub async fn invoke<T: Serialize, R: DeserializeOwned>(client: &Client, payload: T) -> Result<R> {
-    let payload_str = serde_json::to_string(&payload)?;
-    let response = client
+/// A payload too big to send as is, gzipped and base64-encoded so it's still
+/// the JSON that Lambda requires
+#[derive(Serialize, Deserialize)]
+pub struct Compressed {
+    /// Always "gzip"; a handler sees this field to know to unwrap `payload`
+    pub content_encoding: String,
+    pub payload: String,
+}
+
+fn compress(payload: &str) -> Result<String> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(payload.as_bytes())?;
+    let envelope = Compressed {
+        content_encoding: "gzip".to_string(),
+        payload: BASE64.encode(encoder.finish()?),
+    };
+    Ok(serde_json::to_string(&envelope)?)
+}
+
+/// The original payload inside a `Compressed` envelope, or `None` if
+/// `payload` isn't one
+pub fn decompress(payload: &str) -> Result<Option<String>> {
+    let Ok(envelope) = serde_json::from_str::<Compressed>(payload) else {
+        return Ok(None);
+    };
+    anyhow::ensure!(
+        envelope.content_encoding == "gzip",
+        "unsupported content encoding {}",
+        envelope.content_encoding
+    );
+    let gzipped = BASE64.decode(envelope.payload)?;
+    let mut decoded = String::new();
+    GzDecoder::new(&gzipped[..]).read_to_string(&mut decoded)?;
+    Ok(Some(decoded))
+}
+
+/// A failed invocation, tagged with the request id Lambda assigned it if it
+/// got far enough to be given one. The id finds the invocation's CloudWatch
+/// log entry.
+#[derive(Debug)]
+pub struct InvokeError {
+    pub request_id: Option<String>,
+    pub error: anyhow::Error,
+    /// The request never got a reply: a dispatch failure or timeout, which
+    /// may well succeed if sent again
+    pub retryable: bool,
+    /// The request was given up on after `--operation-timeout-ms` or
+    /// `--operation-attempt-timeout-ms`, rather than failing outright
+    pub timed_out: bool,
+    /// Why the request couldn't be sent, if that's how it failed
+    pub dispatch_cause: Option<DispatchCause>,
+    /// What the handler threw, if the Lambda reported a function error
+    pub function_error: Option<FunctionError>,
+}
+
+/// The payload of a function error, as the Lambda runtime reports an
+/// exception the handler didn't catch. Either part may be missing.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct FunctionError {
+    pub error_type: String,
+    pub error_message: String,
+}
+
+/// Why an invocation failed to dispatch, as far as its error chain tells
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DispatchCause {
+    ConnectionRefused,
+    /// Reset, aborted or broken mid-request
+    ConnectionReset,
+    Dns,
+    Tls,
+    ConnectTimeout,
+    /// Any other I/O error
+    Io,
+    Other,
+}
+
+impl DispatchCause {
+    /// Classify by the first I/O error kind in the chain that says, and
+    /// otherwise by what the messages mention, as hyper wraps DNS and TLS
+    /// failures opaquely
+    fn of(failure: &DispatchFailure) -> Self {
+        let mut source = failure
+            .as_connector_error()
+            .map(|err| err as &(dyn std::error::Error + 'static));
+        let mut messages = String::new();
+        while let Some(err) = source {
+            if let Some(io) = err.downcast_ref::<std::io::Error>() {
+                match io.kind() {
+                    ErrorKind::ConnectionRefused => return Self::ConnectionRefused,
+                    ErrorKind::ConnectionReset
+                    | ErrorKind::ConnectionAborted
+                    | ErrorKind::BrokenPipe => return Self::ConnectionReset,
+                    ErrorKind::TimedOut => return Self::ConnectTimeout,
+                    _ => {}
+                }
+            }
+            messages += &err.to_string().to_lowercase();
+            messages.push('\n');
+            source = err.source();
+        }
+        let mentions = |words: &[&str]| words.iter().any(|word| messages.contains(word));
+        if mentions(&["dns error", "failed to lookup address", "name or service not known"]) {
+            Self::Dns
+        } else if mentions(&["tls", "certificate", "handshake"]) {
+            Self::Tls
+        } else if failure.is_timeout() {
+            Self::ConnectTimeout
+        } else if failure.is_io() {
+            Self::Io
+        } else {
+            Self::Other
+        }
+    }
+}
+
+impl std::fmt::Display for DispatchCause {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::ConnectionRefused => "connection refused",
+            Self::ConnectionReset => "connection reset",
+            Self::Dns => "DNS lookup failed",
+            Self::Tls => "TLS failed",
+            Self::ConnectTimeout => "connect timed out",
+            Self::Io => "other I/O error",
+            Self::Other => "other",
+        })
+    }
+}
+
+impl std::fmt::Display for InvokeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.error)?;
+        if let Some(id) = &self.request_id {
+            write!(f, " (request id {id})")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for InvokeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.error.source()
+    }
+}
+
+async fn invoke_sdk(
+    client: &Client,
+    function_name: &str,
+    payload_str: &str,
+    client_context: Option<String>,
+    connections: &Arc<ConnectionStats>,
+    phases: Option<&Arc<PhaseTimes>>,
+) -> Result<Vec<u8>> {
+    let new_connection = Arc::new(Mutex::new(None));
+    let tracker = ConnectionTracker {
+        stats: connections.clone(),
+        new: new_connection.clone(),
+    };
+    let start = Instant::now();
+    let mut invocation = client
         .invoke()
-        .function_name(FUNCTION_NAME)
+        .function_name(function_name)
         .payload(Blob::new(payload_str.as_bytes()))
-        .send()
-        .await;
+        .set_client_context(client_context)
+        .customize()
+        .interceptor(tracker);
+    if let Some(phases) = phases {
+        invocation = invocation.interceptor(SigningTimer {
+            phases: phases.clone(),
+            started: Mutex::new(None),
+        });
+    }
+    let response = invocation.send().await;
+    if let Some(new) = *new_connection.lock().unwrap() {
+        connections.record(new, start.elapsed());
+    }
     let response = match response {
         Ok(r) => r,
         Err(err) => {
-            if let SdkError::DispatchFailure(ref d) = err {
-                tracing::error!(?d, "dispatch failure");
-            }
-            return Err(err)?;
+            let request_id = err.request_id().map(str::to_string);
+            let timed_out = matches!(err, SdkError::TimeoutError(_));
+            let retryable = timed_out || matches!(err, SdkError::DispatchFailure(_));
+            let dispatch_cause = match &err {
+                SdkError::DispatchFailure(d) => {
+                    let cause = DispatchCause::of(d);
+                    tracing::error!(?d, %cause, "dispatch failure");
+                    Some(cause)
+                }
+                _ => {
+                    tracing::debug!(?request_id, %err, "invocation failed");
+                    None
+                }
+            };
+            return Err(InvokeError {
+                request_id,
+                error: err.into(),
+                retryable,
+                timed_out,
+                dispatch_cause,
+                function_error: None,
+            })?;
         }
     };
 
-    let response_bytes = response.payload().unwrap().as_ref();
-    tracing::trace!(?response_bytes);
+    let request_id = response.request_id();
+    let response_bytes = response.payload().map(Blob::as_ref);
+    tracing::trace!(?request_id, ?response_bytes);
 
     if let Some(err) = response.function_error() {
-        tracing::trace!(?err, "function error");
-        let msg = String::from_utf8_lossy(response_bytes);
-        anyhow::bail!("function error: {msg}");
+        tracing::trace!(?request_id, ?err, "function error");
+        let msg = String::from_utf8_lossy(response_bytes.unwrap_or_default());
+        let thrown = response_bytes.and_then(|bytes| serde_json::from_slice(bytes).ok());
+        return Err(InvokeError {
+            request_id: request_id.map(str::to_string),
+            error: anyhow::anyhow!("function error: {msg}"),
+            retryable: false,
+            timed_out: false,
+            dispatch_cause: None,
+            function_error: Some(thrown.unwrap_or_default()),
+        })?;
+    }
+
+    match response_bytes {
+        Some(bytes) => Ok(bytes.to_vec()),
+        None => Err(InvokeError {
+            request_id: request_id.map(str::to_string),
+            error: anyhow::anyhow!("Lambda returned no payload"),
+            retryable: false,
+            timed_out: false,
+            dispatch_cause: None,
+            function_error: None,
+        })?,
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::tpcb::Amount;
+    use super::FunctionError;
+    use serde_json::json;
 
-    Ok(serde_json::from_slice(response_bytes)?)
+    #[test]
+    fn function_error_reads_what_the_runtime_reports() {
+        let payload = br#"{"errorType":"Error","errorMessage":"Insufficient balance","trace":[]}"#;
+        let thrown: FunctionError = serde_json::from_slice(payload).unwrap();
+        assert_eq!(thrown.error_type, "Error");
+        assert_eq!(thrown.error_message, "Insufficient balance");
+        let thrown: FunctionError = serde_json::from_slice(br#"{}"#).unwrap();
+        assert_eq!(thrown.error_type, "");
+    }
+
+    #[test]
+    fn amount_from_json_reads_whole_and_decimal_amounts() {
+        assert_eq!(Amount::from_json(&json!(7)), Some(Amount::Whole(7)));
+        assert_eq!(Amount::from_json(&json!("0.5")), Some(Amount::Cents(50)));
+        assert_eq!(Amount::from_json(&json!("12.34")), Some(Amount::Cents(1234)));
+    }
+
+    #[test]
+    fn amount_from_json_rejects_what_it_cant_take_exactly() {
+        assert_eq!(Amount::from_json(&json!("12.345")), None);
+        assert_eq!(Amount::from_json(&json!(".5")), None);
+        assert_eq!(Amount::from_json(&json!("1")), None);
+        assert_eq!(Amount::from_json(&json!("1.+5")), None);
+        assert_eq!(Amount::from_json(&json!(u32::MAX as u64 + 1)), None);
+        assert_eq!(Amount::from_json(&json!("42949673.00")), None);
+    }
+
+    #[test]
+    fn amount_displays_and_serializes_as_sent() {
+        assert_eq!(Amount::Cents(5).to_string(), "0.05");
+        assert_eq!(Amount::Cents(1234).to_string(), "12.34");
+        assert_eq!(serde_json::to_value(Amount::Cents(5)).unwrap(), json!("0.05"));
+        assert_eq!(serde_json::to_value(Amount::Whole(10)).unwrap(), json!(10));
+    }
 }
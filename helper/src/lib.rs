@@ -0,0 +1,36 @@
+//! Load-generation and verification tooling for the DSQL + Lambda demo.
+//!
+//! The `helper` binary is a thin CLI over this crate. Other tools can depend
+//! on it to drive load programmatically: build a [`lambda::ClientPool`] from
+//! a [`credentials::CredentialCache`], then call [`stress::run_stress_test`]
+//! or [`stress::run_sustained_load`] and inspect the returned
+//! [`summary::StressSummary`].
+
+pub mod aimd;
+pub mod bench;
+pub mod bounded;
+pub mod cli;
+pub mod cloudwatch;
+pub mod config;
+pub mod coordinator;
+pub mod cost;
+pub mod credentials;
+pub mod db;
+pub mod failure;
+pub mod health;
+pub mod lambda;
+pub mod metadata;
+pub mod migrations;
+pub mod mock;
+pub mod plan;
+pub mod progress;
+pub mod replay;
+pub mod report;
+pub mod results;
+pub mod scenario;
+pub mod selftest;
+pub mod setup;
+pub mod stress;
+pub mod summary;
+pub mod sweep;
+pub mod tests;
@@ -1,40 +1,768 @@
-mod cli;
-mod credentials;
-mod db;
-mod lambda;
-mod setup;
-mod stress;
-mod tests;
-
-use anyhow::Result;
+use anyhow::{Context, Result};
 use clap::Parser;
+use std::time::{Duration, Instant};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
+use helper::failure::Failure;
+use helper::{
+    bench, cli, cloudwatch, config, coordinator, cost, credentials, db, health, lambda, metadata,
+    plan, progress, replay, report, results, scenario, selftest, setup, stress, summary, sweep,
+    tests,
+};
 
-#[tokio::main(flavor = "multi_thread", worker_threads = 64)]
-async fn main() -> Result<()> {
-    tracing_subscriber::fmt::init();
+fn main() {
+    if let Err(err) = try_main() {
+        // Print the error as returning it from main would, but exit with a
+        // status that says what kind of failure it was
+        eprintln!("Error: {:?}", err);
+        std::process::exit(Failure::of(&err).map_or(1, Failure::exit_code));
+    }
+}
+
+fn try_main() -> Result<()> {
+    let mut args = cli::Args::parse();
+    config::resolve(&mut args).context(Failure::Config)?;
+    // Built by hand rather than with #[tokio::main] so the thread count can
+    // come from the command line
+    tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(args.worker_threads as usize)
+        .enable_all()
+        .build()?
+        .block_on(run_capped(args))
+}
+
+/// How long past `--max-runtime` a command gets to drain and report before
+/// it's abandoned
+const MAX_RUNTIME_GRACE: Duration = Duration::from_secs(15);
 
-    let args = cli::Args::parse();
+/// Run the command, stopping it once `--max-runtime` runs out
+async fn run_capped(args: cli::Args) -> Result<()> {
+    let Some(secs) = args.max_runtime else {
+        return run(args).await;
+    };
+    let deadline = Instant::now() + Duration::from_secs(secs);
+    stress::stop_at(deadline);
+    match tokio::time::timeout_at((deadline + MAX_RUNTIME_GRACE).into(), run(args)).await {
+        Ok(result) => result?,
+        Err(_) => anyhow::bail!("Max runtime of {}s exceeded; abandoned the command", secs),
+    }
+    // A load run that stopped at the deadline returns normally, with its
+    // stats printed, but still has to fail the command
+    if Instant::now() >= deadline {
+        anyhow::bail!("Max runtime of {}s exceeded", secs);
+    }
+    Ok(())
+}
+
+async fn run(args: cli::Args) -> Result<()> {
+    // Flushes --log-file when dropped, so it has to last the whole run
+    let _log_guard = init_logging(&args)?;
+    progress::init(args.quiet);
+    metadata::set_tags(args.tags.iter().cloned().collect());
+
+    let cache_file = match args.credential_cache_file {
+        Some(Some(path)) => Some(path),
+        Some(None) => Some(
+            credentials::default_cache_file()
+                .context("Neither XDG_CACHE_HOME nor HOME is set; pass a path")?,
+        ),
+        None => None,
+    };
 
     // Create the credential cache once (shared between Lambda and DB)
-    let credential_cache = credentials::CredentialCache::new().await?;
+    let credential_cache = credentials::CredentialCache::new(cache_file).await?;
 
     match args.command {
-        cli::Command::TestChapter { chapter } => {
-            let client_pool = lambda::client_pool(&credential_cache, 1).await?;
-            tests::run_test(&client_pool, &credential_cache, chapter).await?;
+        cli::Command::TestChapter {
+            chapter,
+            summary_json,
+            ensure_schema,
+        } => {
+            if ensure_schema {
+                db::ensure_schema(&credential_cache, &args.db, 1_000).await?;
+            }
+            let client_pool = lambda::client_pool(
+                &credential_cache,
+                1,
+                &args.lambda,
+                &args.db,
+                &args.mock,
+            )
+            .await?;
+            tests::run_test(
+                &client_pool,
+                &credential_cache,
+                &args.db,
+                chapter,
+                summary_json.as_deref(),
+            )
+            .await?;
+        }
+        cli::Command::Chapters => {
+            tests::list_chapters();
+        }
+        cli::Command::Transfer {
+            payer,
+            payee,
+            amount,
+            repeat,
+        } => {
+            let client_pool = lambda::client_pool(
+                &credential_cache,
+                1,
+                &args.lambda,
+                &args.db,
+                &args.mock,
+            )
+            .await?;
+            tests::transfer(&client_pool, payer, payee, amount, repeat).await?;
+        }
+        cli::Command::HealthCheck => {
+            health::health_check(&credential_cache, &args.db, &args.lambda, &args.mock).await?;
+        }
+        cli::Command::SelfTest {
+            accounts,
+            transfers,
+            parallel,
+        } => {
+            selftest::self_test(
+                &credential_cache,
+                &args.db,
+                &args.lambda,
+                &args.mock,
+                accounts,
+                transfers,
+                parallel,
+            )
+            .await?;
+        }
+        cli::Command::EstimateCost {
+            total,
+            amount,
+            probes,
+            memory_mb,
+            dpu_per_transfer,
+        } => {
+            let assumptions = cost::Assumptions {
+                probes,
+                memory_mb,
+                dpu_per_transfer,
+            };
+            cost::estimate_cost(
+                &credential_cache,
+                &args.db,
+                &args.lambda,
+                &args.mock,
+                total,
+                amount,
+                &assumptions,
+            )
+            .await?;
+        }
+        cli::Command::ConnLatency { count } => {
+            health::conn_latency(&credential_cache, &args.db, count).await?;
+        }
+        cli::Command::CredBench { iterations } => {
+            health::cred_bench(&credential_cache, iterations).await?;
+        }
+        cli::Command::IndexBench { queries, accounts } => {
+            bench::index_bench(&credential_cache, &args.db, queries, accounts).await?;
+        }
+        cli::Command::DbBench {
+            queries,
+            parallel,
+            accounts,
+            transfer,
+        } => {
+            bench::db_bench(&credential_cache, &args.db, queries, parallel, accounts, transfer)
+                .await?;
+        }
+        cli::Command::Setup {
+            accounts,
+            with_indexes,
+            if_empty,
+            dry_run,
+        } => {
+            if dry_run {
+                plan::setup(&args.db, accounts, with_indexes, if_empty);
+                return Ok(());
+            }
+            setup::setup_schema(&credential_cache, &args.db, accounts, with_indexes, if_empty)
+                .await?;
+        }
+        cli::Command::Migrate => {
+            setup::migrate(&credential_cache, &args.db).await?;
+        }
+        cli::Command::SetupCh04 { dry_run } => {
+            if dry_run {
+                plan::setup_chapter4(&args.db);
+                return Ok(());
+            }
+            setup::setup_chapter4(&credential_cache, &args.db).await?;
+        }
+        cli::Command::ImportAccounts { path } => {
+            setup::import_accounts(&credential_cache, &args.db, &path).await?;
+        }
+        cli::Command::PruneTransactions { older_than_hours } => {
+            setup::prune_transactions(&credential_cache, &args.db, older_than_hours).await?;
+        }
+        cli::Command::Repair {
+            initial_balance,
+            apply,
+        } => {
+            setup::repair_balances(&credential_cache, &args.db, initial_balance, apply).await?;
+        }
+        cli::Command::ResetBalances { initial_balance } => {
+            setup::reset_balances(&credential_cache, &args.db, initial_balance).await?;
+        }
+        cli::Command::Teardown { yes, dry_run } => {
+            if dry_run {
+                plan::teardown(&args.db);
+                return Ok(());
+            }
+            setup::teardown(&credential_cache, &args.db, yes).await?;
+        }
+        cli::Command::Stress {
+            total,
+            parallel,
+            rate,
+            ramp_in,
+            accounts,
+            workload,
+            run,
+            clients,
+            summary_json,
+            repeat,
+            dry_run,
+        } => {
+            let pace = stress::Pace {
+                parallel,
+                rate,
+                ramp_in: ramp_in.map(std::time::Duration::from_millis),
+            };
+            if dry_run {
+                let load = stress::describe_stress_load(total, pace);
+                plan::run(&args.db, &args.lambda, &args.mock, clients, accounts, &workload, &load);
+                return Ok(());
+            }
+            let accounts =
+                prepare_run(&credential_cache, &args.db, &args.mock, &run, accounts).await?;
+            if workload.sends_transfers() {
+                probe(&credential_cache, &args.lambda, &args.db, &args.mock, &run, &workload)
+                    .await?;
+            }
+            let ledger = ledger_baseline(&credential_cache, &args.db, &args.mock, &run).await?;
+            let mut summaries = Vec::with_capacity(repeat as usize);
+            for i in 1..=repeat {
+                if repeat > 1 {
+                    println!("Run {} of {}", i, repeat);
+                    println!();
+                }
+                // A fresh pool for each run, so its connection, traffic and
+                // per-client stats are the run's own
+                let client_pool = lambda::client_pool(
+                    &credential_cache,
+                    clients,
+                    &args.lambda,
+                    &args.db,
+                    &args.mock,
+                )
+                .await?;
+                prewarm(&client_pool, &run).await?;
+                let watch =
+                    watch_balances(&credential_cache, &args.db, &args.mock, &run, &client_pool)
+                        .await?;
+                let summary = stress::run_stress_test(
+                    &client_pool,
+                    total,
+                    pace,
+                    accounts,
+                    &workload,
+                    &run,
+                )
+                .await;
+                if let Some(watch) = watch {
+                    watch.abort();
+                }
+                let summary = summary?;
+                if let Some(path) = &summary_json {
+                    match repeat {
+                        1 => summary.save(path)?,
+                        _ => summary.save(&summary::numbered(path, i))?,
+                    }
+                }
+                upload_results(&credential_cache, &run, &summary).await?;
+                summaries.push(summary);
+            }
+            if repeat > 1 {
+                summary::print_repeats(&summaries);
+            }
+            if let Some(before) = ledger {
+                let applied = summaries.iter().map(|s| s.applied_transfers).sum();
+                db::verify_ledger(&credential_cache, &args.db, before, applied).await?;
+            }
+            if run.assert_conservation && !args.mock.mock {
+                let balance = workload.initial_balance;
+                db::verify_conservation(&credential_cache, &args.db, accounts, balance).await?;
+                if workload.amount_decimal {
+                    db::verify_exact_conservation(&credential_cache, &args.db, accounts, balance)
+                        .await?;
+                }
+            }
+            for summary in &summaries {
+                summary.check_sla(run.max_p99_ms)?;
+            }
+        }
+        cli::Command::Replay {
+            path,
+            rate,
+            parallel,
+            run,
+            clients,
+            summary_json,
+        } => {
+            let recording = replay::Recording::load(&path)?;
+            let pace = stress::Pace {
+                parallel,
+                rate,
+                ramp_in: None,
+            };
+            let accounts = recording.max_account();
+            let accounts =
+                prepare_run(&credential_cache, &args.db, &args.mock, &run, accounts).await?;
+            // Recordings hold whole amounts, sent as the default workload would
+            let workload = cli::WorkloadArgs::default();
+            probe(&credential_cache, &args.lambda, &args.db, &args.mock, &run, &workload).await?;
+            let ledger = ledger_baseline(&credential_cache, &args.db, &args.mock, &run).await?;
+            let client_pool =
+                lambda::client_pool(&credential_cache, clients, &args.lambda, &args.db, &args.mock)
+                    .await?;
+            prewarm(&client_pool, &run).await?;
+            let watch =
+                watch_balances(&credential_cache, &args.db, &args.mock, &run, &client_pool).await?;
+            let summary = stress::run_replay(&client_pool, recording, pace, &run).await;
+            if let Some(watch) = watch {
+                watch.abort();
+            }
+            let summary = summary?;
+            if let Some(path) = &summary_json {
+                summary.save(path)?;
+            }
+            upload_results(&credential_cache, &run, &summary).await?;
+            if let Some(before) = ledger {
+                db::verify_ledger(&credential_cache, &args.db, before, summary.applied_transfers)
+                    .await?;
+            }
+            if run.assert_conservation && !args.mock.mock {
+                let balance = workload.initial_balance;
+                db::verify_conservation(&credential_cache, &args.db, accounts, balance).await?;
+            }
+            summary.check_sla(run.max_p99_ms)?;
+        }
+        cli::Command::SustainedLoad {
+            invocations_per_sec,
+            accounts,
+            workload,
+            scenario,
+            ramp,
+            ramp_secs,
+            pattern,
+            baseline,
+            spike,
+            spike_at,
+            spike_for,
+            run,
+            options,
+            clients,
+            summary_json,
+            dry_run,
+        } => {
+            let (schedule, accounts, workload) = match (scenario, ramp, ramp_secs) {
+                (Some(path), _, _) => {
+                    let scenario = scenario::Scenario::load(&path)?;
+                    (scenario.schedule(), scenario.accounts, scenario.workload())
+                }
+                (None, Some(end), Some(duration_secs)) => (
+                    stress::RateSchedule::Ramp {
+                        start: invocations_per_sec,
+                        end,
+                        duration_secs,
+                    },
+                    accounts,
+                    workload,
+                ),
+                _ if pattern == cli::LoadPattern::Step => (
+                    stress::RateSchedule::Step {
+                        baseline: baseline.unwrap_or(invocations_per_sec),
+                        spike: spike.unwrap_or(invocations_per_sec),
+                        spike_at_secs: spike_at.unwrap_or_default(),
+                        spike_for_secs: spike_for.unwrap_or_default(),
+                    },
+                    accounts,
+                    workload,
+                ),
+                _ => (
+                    stress::RateSchedule::Constant(invocations_per_sec),
+                    accounts,
+                    workload,
+                ),
+            };
+            if dry_run {
+                let load = schedule.describe();
+                plan::run(&args.db, &args.lambda, &args.mock, clients, accounts, &workload, &load);
+                return Ok(());
+            }
+            let accounts =
+                prepare_run(&credential_cache, &args.db, &args.mock, &run, accounts).await?;
+            if workload.sends_transfers() {
+                probe(&credential_cache, &args.lambda, &args.db, &args.mock, &run, &workload)
+                    .await?;
+            }
+            let ledger = ledger_baseline(&credential_cache, &args.db, &args.mock, &run).await?;
+            let client_pool = lambda::client_pool(
+                &credential_cache,
+                clients,
+                &args.lambda,
+                &args.db,
+                &args.mock,
+            )
+            .await?;
+            prewarm(&client_pool, &run).await?;
+            let watch =
+                watch_balances(&credential_cache, &args.db, &args.mock, &run, &client_pool).await?;
+            let cloudwatch = match &options.cloudwatch_namespace {
+                Some(namespace) => Some(
+                    cloudwatch::Publisher::connect(
+                        &credential_cache,
+                        client_pool.region(),
+                        namespace,
+                    )
+                    .await?,
+                ),
+                None => None,
+            };
+            let summary = stress::run_sustained_load(
+                &client_pool,
+                &schedule,
+                accounts,
+                &workload,
+                &run,
+                &options,
+                cloudwatch.as_ref(),
+            )
+            .await;
+            if let Some(watch) = watch {
+                watch.abort();
+            }
+            let summary = summary?;
+            if let Some(path) = summary_json {
+                summary.save(&path)?;
+            }
+            upload_results(&credential_cache, &run, &summary).await?;
+            if let Some(before) = ledger {
+                db::verify_ledger(&credential_cache, &args.db, before, summary.applied_transfers)
+                    .await?;
+            }
+            if run.assert_conservation && !args.mock.mock {
+                let balance = workload.initial_balance;
+                db::verify_conservation(&credential_cache, &args.db, accounts, balance).await?;
+                if workload.amount_decimal {
+                    db::verify_exact_conservation(&credential_cache, &args.db, accounts, balance)
+                        .await?;
+                }
+            }
+            summary.check_sla(run.max_p99_ms)?;
+        }
+        cli::Command::Sweep {
+            accounts,
+            workload,
+            start,
+            max_parallel,
+            step_secs,
+            plateau_pct,
+            p99_limit_ms,
+            clients,
+            curve_csv,
+        } => {
+            let run = cli::RunArgs::default();
+            let accounts =
+                prepare_run(&credential_cache, &args.db, &args.mock, &run, accounts).await?;
+            let client_pool = lambda::client_pool(
+                &credential_cache,
+                clients,
+                &args.lambda,
+                &args.db,
+                &args.mock,
+            )
+            .await?;
+            let config = sweep::SweepConfig {
+                start,
+                max: max_parallel,
+                step_secs,
+                plateau_pct,
+                p99_limit_ms,
+                curve_csv,
+            };
+            sweep::run_sweep(&client_pool, accounts, &workload, &config).await?;
+        }
+        cli::Command::FindMaxRate {
+            max_p99_ms,
+            max_error_rate,
+            min_rate,
+            max_rate,
+            resolution,
+            probe_secs,
+            accounts,
+            workload,
+            clients,
+            summary_json,
+            curve_csv,
+        } => {
+            let run = cli::RunArgs::default();
+            let accounts =
+                prepare_run(&credential_cache, &args.db, &args.mock, &run, accounts).await?;
+            let client_pool = lambda::client_pool(
+                &credential_cache,
+                clients,
+                &args.lambda,
+                &args.db,
+                &args.mock,
+            )
+            .await?;
+            let search = sweep::RateSearch {
+                min_rate,
+                max_rate,
+                resolution,
+                probe_secs,
+                max_p99_ms,
+                max_error_rate,
+                curve_csv,
+            };
+            let summary = sweep::find_max_rate(&client_pool, accounts, &workload, &search).await?;
+            if let Some(path) = summary_json {
+                summary.save(&path)?;
+            }
         }
-        cli::Command::Setup { accounts } => {
-            setup::setup_schema(&credential_cache, accounts).await?;
+        cli::Command::Balance { account_ids } => {
+            report::balances(&credential_cache, &args.db, &account_ids).await?;
         }
-        cli::Command::SetupCh04 => {
-            setup::setup_chapter4(&credential_cache).await?;
+        cli::Command::History {
+            account_id,
+            limit,
+            role,
+            since,
+        } => {
+            report::history(&credential_cache, &args.db, account_id, role, limit, since).await?;
         }
-        cli::Command::SustainedLoad { invocations_per_sec, accounts } => {
-            // Use 16 clients to distribute load across multiple HTTP connections
-            let client_pool = lambda::client_pool(&credential_cache, 16).await?;
-            stress::run_sustained_load(&client_pool, invocations_per_sec, accounts).await?;
+        cli::Command::AimdCompare {
+            invocations_per_sec,
+            duration_secs,
+            accounts,
+            workload,
+            clients,
+        } => {
+            let run = cli::RunArgs::default();
+            let accounts =
+                prepare_run(&credential_cache, &args.db, &args.mock, &run, accounts).await?;
+            let client_pool = lambda::client_pool(
+                &credential_cache,
+                clients,
+                &args.lambda,
+                &args.db,
+                &args.mock,
+            )
+            .await?;
+            let phase = stress::Phase {
+                rate: invocations_per_sec,
+                duration_secs,
+            };
+            sweep::compare_aimd(&client_pool, accounts, &workload, phase).await?;
+        }
+        cli::Command::AccountStats { top } => {
+            report::account_stats(&credential_cache, &args.db, top).await?;
+        }
+        cli::Command::Watch { interval } => {
+            report::watch(&credential_cache, &args.db, Duration::from_secs(interval)).await?;
+        }
+        cli::Command::ServeCoordinator { listen } => {
+            coordinator::serve(&listen).await?;
+        }
+        cli::Command::ExportTransactions { path, limit, since } => {
+            report::export_transactions(&credential_cache, &args.db, &path, limit, since).await?;
+        }
+        cli::Command::ExportAccounts { path } => {
+            report::export_accounts(&credential_cache, &args.db, &path).await?;
+        }
+        cli::Command::Compare {
+            baseline,
+            candidate,
+            max_p99_regression,
+        } => {
+            summary::compare(&baseline, &candidate, max_p99_regression)?;
         }
     }
 
     Ok(())
 }
+
+/// Log to stderr, and to `--log-file` as well if given
+fn init_logging(args: &cli::Args) -> Result<Option<WorkerGuard>> {
+    let json = args.log_format == cli::LogFormat::Json;
+    let stderr = if json {
+        tracing_subscriber::fmt::layer().json().boxed()
+    } else {
+        tracing_subscriber::fmt::layer().boxed()
+    };
+
+    let (file, guard) = match &args.log_file {
+        Some(path) => {
+            let rotation = match args.log_rotation {
+                cli::LogRotation::Never => Rotation::NEVER,
+                cli::LogRotation::Hourly => Rotation::HOURLY,
+                cli::LogRotation::Daily => Rotation::DAILY,
+            };
+            let dir = match path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => std::path::Path::new("."),
+            };
+            let name = path.file_name().context("--log-file needs a file name")?;
+            let appender = RollingFileAppender::new(rotation, dir, name);
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            let layer = tracing_subscriber::fmt::layer()
+                .with_writer(writer)
+                .with_ansi(false);
+            let layer = if json { layer.json().boxed() } else { layer.boxed() };
+            (Some(layer), Some(guard))
+        }
+        None => (None, None),
+    };
+
+    tracing_subscriber::registry()
+        .with(LevelFilter::INFO)
+        .with(stderr)
+        .with(file)
+        .init();
+    Ok(guard)
+}
+
+/// Database checks before a stress or sustained-load run, returning the
+/// number of accounts to use
+async fn prepare_run(
+    creds: &credentials::CredentialCache,
+    db_args: &cli::DbArgs,
+    mock: &cli::MockArgs,
+    run: &cli::RunArgs,
+    accounts: u32,
+) -> Result<u32> {
+    if run.ensure_schema {
+        db::ensure_schema(creds, db_args, accounts).await?;
+    }
+    // The simulator needs neither credentials nor a database to check against
+    if mock.mock {
+        return Ok(accounts);
+    }
+    creds.warm().await.context(Failure::Connectivity)?;
+    db::check_account_count(creds, db_args, accounts, run.accounts_check).await
+}
+
+/// Check the Lambda answers transfers the way the run expects, unless
+/// `--no-probe` is given. Uses a pool of its own, before the ledger
+/// baseline is taken, so the probe's transfers aren't counted against the
+/// run
+async fn probe(
+    creds: &credentials::CredentialCache,
+    lambda_args: &cli::LambdaArgs,
+    db_args: &cli::DbArgs,
+    mock: &cli::MockArgs,
+    run: &cli::RunArgs,
+    workload: &cli::WorkloadArgs,
+) -> Result<()> {
+    if run.no_probe {
+        return Ok(());
+    }
+    let client_pool = lambda::client_pool(creds, 1, lambda_args, db_args, mock).await?;
+    stress::probe(&client_pool, workload).await
+}
+
+/// Open `--prewarm` connections in each `--direct` pool before the run
+async fn prewarm(client_pool: &lambda::ClientPool, run: &cli::RunArgs) -> Result<()> {
+    let Some(n) = run.prewarm else {
+        return Ok(());
+    };
+    if client_pool.direct_pools().is_empty() {
+        println!("--prewarm only applies to --direct, skipping");
+        return Ok(());
+    }
+    for pool in client_pool.direct_pools() {
+        let start = std::time::Instant::now();
+        let opened = db::pool_prewarm(pool, n).await?;
+        println!(
+            "Prewarmed {} DSQL connections in {}ms",
+            opened,
+            start.elapsed().as_millis()
+        );
+    }
+    Ok(())
+}
+
+/// Start checking the balance total in the background if
+/// `--invariant-check-interval` is set and there's a real database to check
+async fn watch_balances(
+    creds: &credentials::CredentialCache,
+    db_args: &cli::DbArgs,
+    mock: &cli::MockArgs,
+    run: &cli::RunArgs,
+    client_pool: &lambda::ClientPool,
+) -> Result<Option<tokio::task::JoinHandle<()>>> {
+    let Some(secs) = run.invariant_check_interval else {
+        return Ok(None);
+    };
+    if mock.mock {
+        return Ok(None);
+    }
+    let pool = db::get_pool(creds, db_args).await?;
+    let expected = db::total_balance(&pool).await?;
+    println!(
+        "Checking the balance total ({}) every {}s during the run",
+        expected, secs
+    );
+    Ok(Some(tokio::spawn(db::watch_balances(
+        pool,
+        expected,
+        std::time::Duration::from_secs(secs),
+        client_pool.clone(),
+    ))))
+}
+
+/// Upload the run's results directory if `--s3-results` is set
+async fn upload_results(
+    creds: &credentials::CredentialCache,
+    run: &cli::RunArgs,
+    summary: &summary::StressSummary,
+) -> Result<()> {
+    if let Some(location) = &run.s3_results
+        && let Some(root) = &run.results_dir
+        && let Some(metadata) = &summary.metadata
+    {
+        results::upload_to_s3(creds, root, metadata, location).await?;
+    }
+    Ok(())
+}
+
+/// The transactions count to compare against after the run, if
+/// `--verify-ledger` is set and there's a real database to check
+async fn ledger_baseline(
+    creds: &credentials::CredentialCache,
+    db_args: &cli::DbArgs,
+    mock: &cli::MockArgs,
+    run: &cli::RunArgs,
+) -> Result<Option<i64>> {
+    if !run.verify_ledger || mock.mock {
+        return Ok(None);
+    }
+    Ok(Some(db::count_transactions(creds, db_args).await?))
+}
@@ -0,0 +1,106 @@
+use crate::lambda::ClientPool;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::OnceLock;
+use uuid::Uuid;
+
+/// The `--tag`s every run started from here on carries
+static TAGS: OnceLock<BTreeMap<String, String>> = OnceLock::new();
+
+/// Attach `tags` to the metadata of every run started from here on
+pub fn set_tags(tags: BTreeMap<String, String>) {
+    let _ = TAGS.set(tags);
+}
+
+/// Identifies a run and where it ran, so its artifacts can be matched up
+/// later
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RunMetadata {
+    pub run_id: Uuid,
+    pub version: String,
+    /// Commit the binary was built from, when `GIT_COMMIT` was set at build
+    /// time
+    pub git_commit: Option<String>,
+    pub function: String,
+    pub region: String,
+    pub accounts: u32,
+    /// Target rate or parallelism
+    pub load: String,
+    pub started_at: DateTime<Utc>,
+    pub hostname: Option<String>,
+    /// `--tag KEY=VALUE`s, for sorting archived runs by cluster, branch or
+    /// experiment
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
+}
+
+impl RunMetadata {
+    /// Metadata for a run starting now with a fresh run id
+    pub fn new(client_pool: &ClientPool, accounts: u32, load: String) -> Self {
+        Self {
+            run_id: uuid::Builder::from_random_bytes(rand::random()).into_uuid(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            git_commit: option_env!("GIT_COMMIT").map(str::to_string),
+            function: client_pool.function_name(),
+            region: client_pool.region().to_string(),
+            accounts,
+            load,
+            started_at: Utc::now(),
+            hostname: hostname::get()
+                .ok()
+                .map(|name| name.to_string_lossy().into_owned()),
+            tags: TAGS.get().cloned().unwrap_or_default(),
+        }
+    }
+
+    /// The CSV columns identifying the run: `run_id`, then `tag_<key>` for
+    /// each tag
+    pub fn csv_columns(&self) -> String {
+        let mut columns = "run_id".to_string();
+        for key in self.tags.keys() {
+            columns.push_str(",tag_");
+            columns.push_str(key);
+        }
+        columns
+    }
+
+    /// This run's values for [`Self::csv_columns`]; `--tag` only accepts
+    /// values that need no quoting
+    pub fn csv_values(&self) -> String {
+        let mut values = self.run_id.to_string();
+        for value in self.tags.values() {
+            values.push(',');
+            values.push_str(value);
+        }
+        values
+    }
+
+    /// S3 user metadata naming the run and its tags, for uploaded artifacts
+    pub fn s3_metadata(&self) -> HashMap<String, String> {
+        let mut metadata = HashMap::from([("run-id".to_string(), self.run_id.to_string())]);
+        for (key, value) in &self.tags {
+            metadata.insert(format!("tag-{}", key), value.clone());
+        }
+        metadata
+    }
+
+    pub fn print(&self) {
+        println!("Run ID:   {}", self.run_id);
+        match &self.git_commit {
+            Some(commit) => println!("Build:    {} ({})", self.version, commit),
+            None => println!("Build:    {}", self.version),
+        }
+        println!("Function: {} in {}", self.function, self.region);
+        println!("Accounts: {}", self.accounts);
+        println!("Load:     {}", self.load);
+        println!("Started:  {}", self.started_at.to_rfc3339());
+        if let Some(hostname) = &self.hostname {
+            println!("Host:     {}", hostname);
+        }
+        if !self.tags.is_empty() {
+            let tags: Vec<_> = self.tags.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+            println!("Tags:     {}", tags.join(" "));
+        }
+    }
+}
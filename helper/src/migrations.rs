@@ -0,0 +1,111 @@
+use anyhow::{Context, Result};
+use sqlx::{Pool, Postgres};
+
+/// One step of the schema's history. Versions are applied in order and
+/// never edited once shipped; change the schema by adding a new one.
+struct Migration {
+    version: i32,
+    description: &'static str,
+    sql: &'static str,
+}
+
+/// Every migration, oldest first. Each is a single DDL statement, as DSQL
+/// doesn't allow more than one per transaction.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "create accounts",
+        sql: r#"
+        CREATE TABLE IF NOT EXISTS accounts (
+            id INTEGER PRIMARY KEY,
+            balance INTEGER NOT NULL
+        )
+        "#,
+    },
+    Migration {
+        version: 2,
+        description: "create transactions",
+        sql: r#"
+        CREATE TABLE IF NOT EXISTS transactions (
+            id UUID DEFAULT gen_random_uuid() PRIMARY KEY,
+            payer_id INT,
+            payee_id INT,
+            amount INT,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+        )
+        "#,
+    },
+    // DSQL can't change a column's type, so exact decimal amounts get
+    // columns of their own beside the INTEGER ones
+    Migration {
+        version: 3,
+        description: "add exact balances",
+        sql: r#"
+        ALTER TABLE accounts ADD COLUMN IF NOT EXISTS exact_balance NUMERIC(12, 2)
+        "#,
+    },
+    Migration {
+        version: 4,
+        description: "add exact transaction amounts",
+        sql: r#"
+        ALTER TABLE transactions ADD COLUMN IF NOT EXISTS exact_amount NUMERIC(12, 2)
+        "#,
+    },
+];
+
+/// Every migration's version, description and SQL, oldest first, for
+/// `--dry-run` to show
+pub fn all() -> impl Iterator<Item = (i32, &'static str, &'static str)> {
+    MIGRATIONS
+        .iter()
+        .map(|m| (m.version, m.description, m.sql))
+}
+
+/// Apply the migrations `schema_migrations` doesn't list yet, returning how
+/// many were applied.
+///
+/// Each migration's DDL and its `schema_migrations` row are written in
+/// separate transactions, since DSQL can't mix the two; the statements are
+/// idempotent, so one interrupted in between is simply reapplied.
+pub async fn migrate(pool: &Pool<Postgres>) -> Result<usize> {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS schema_migrations (
+            version INT PRIMARY KEY,
+            applied_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    let applied: Vec<(i32,)> = sqlx::query_as("SELECT version FROM schema_migrations")
+        .fetch_all(pool)
+        .await?;
+
+    let mut count = 0;
+    for migration in MIGRATIONS {
+        if applied.contains(&(migration.version,)) {
+            continue;
+        }
+        sqlx::query(migration.sql)
+            .execute(pool)
+            .await
+            .with_context(|| {
+                format!(
+                    "Migration {} ({}) failed",
+                    migration.version, migration.description
+                )
+            })?;
+        sqlx::query("INSERT INTO schema_migrations (version) VALUES ($1)")
+            .bind(migration.version)
+            .execute(pool)
+            .await?;
+        println!(
+            "Applied migration {}: {}",
+            migration.version, migration.description
+        );
+        count += 1;
+    }
+    Ok(count)
+}
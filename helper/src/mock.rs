@@ -0,0 +1,140 @@
+use crate::cli::MockArgs;
+use crate::lambda;
+use anyhow::Result;
+use rand::Rng;
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Most OCC retries a simulated transfer makes, matching a Lambda that
+/// eventually gives up
+const MAX_SIMULATED_RETRIES: u32 = 100;
+
+/// Local stand-in for the Lambda, for exercising the helper offline.
+///
+/// Requests are told apart by their JSON fields, like the real handler, and
+/// answered after a simulated delay: a fixed floor plus an exponentially
+/// distributed tail, paid again for every simulated OCC retry. Transfers are
+/// not applied anywhere, so balances are always reported as 100.
+#[derive(Debug)]
+pub struct MockLambda {
+    latency_ms: f64,
+    tail_ms: f64,
+    occ_probability: f64,
+    /// Idempotency keys already seen, so resends are reported as deduplicated
+    seen_keys: Mutex<HashSet<String>>,
+}
+
+impl MockLambda {
+    pub fn new(args: &MockArgs) -> Self {
+        Self {
+            latency_ms: args.mock_latency_ms as f64,
+            tail_ms: args.mock_latency_tail_ms as f64,
+            occ_probability: args.mock_occ_probability,
+            seen_keys: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub async fn invoke(&self, payload: &str) -> Result<Vec<u8>> {
+        let payload = &match lambda::decompress(payload)? {
+            Some(decoded) => decoded,
+            None => payload.to_string(),
+        };
+        let request: Value = serde_json::from_str(payload)?;
+
+        let response = if let Some(name) = request.get("name").and_then(Value::as_str) {
+            self.sleep(self.sample_latency()).await;
+            json!({ "greeting": format!("Hello {name}, connected to DSQL successfully!") })
+        } else if request.get("account_id").is_some() {
+            let duration = self.sample_latency();
+            self.sleep(duration).await;
+            json!({ "balance": 100, "duration": duration })
+        } else if request.get("payer_id").is_some() {
+            self.transfer(&request).await
+        } else if let Some(transfers) = request.get("transfers").and_then(Value::as_array) {
+            // One after another, as a single Lambda would
+            let mut results = Vec::with_capacity(transfers.len());
+            for transfer in transfers {
+                results.push(self.transfer(transfer).await);
+            }
+            json!({ "results": results })
+        } else {
+            anyhow::bail!("function error: mock Lambda doesn't understand {payload}");
+        };
+
+        Ok(serde_json::to_vec(&response)?)
+    }
+
+    async fn transfer(&self, request: &Value) -> Value {
+        // There is no accounts table, so only account 0 is known not to
+        // exist
+        for (field, message) in [
+            ("payer_id", "Payer account not found"),
+            ("payee_id", "Payee account not found"),
+        ] {
+            if request.get(field).and_then(Value::as_u64) == Some(0) {
+                let duration = self.sample_latency();
+                self.sleep(duration).await;
+                return json!({ "error": message, "duration": duration });
+            }
+        }
+
+        if let Some(key) = request.get("idempotency_key").and_then(Value::as_str)
+            && !self.seen_keys.lock().unwrap().insert(key.to_string())
+        {
+            let duration = self.sample_latency();
+            self.sleep(duration).await;
+            return json!({
+                "balance": 100,
+                "duration": duration,
+                "retries": 0,
+                "deduplicated": true,
+            });
+        }
+
+        // Like a Lambda honouring `deadline_ms`, stop retrying once the
+        // next attempt would finish past it
+        let deadline = request.get("deadline_ms").and_then(Value::as_u64);
+        let (retries, duration, past_deadline) = {
+            let mut rng = rand::thread_rng();
+            let mut retries = 0;
+            let mut duration = self.sample_latency_with(&mut rng);
+            let mut past_deadline = false;
+            while retries < MAX_SIMULATED_RETRIES && rng.r#gen::<f64>() < self.occ_probability {
+                let attempt = self.sample_latency_with(&mut rng);
+                if deadline.is_some_and(|deadline| duration + attempt > deadline) {
+                    past_deadline = true;
+                    break;
+                }
+                retries += 1;
+                duration += attempt;
+            }
+            (retries, duration, past_deadline)
+        };
+        self.sleep(duration).await;
+        if past_deadline {
+            return json!({
+                "error": "Deadline exceeded",
+                "errorCode": "40001",
+                "duration": duration,
+                "retries": retries,
+                "deadline_exceeded": true,
+            });
+        }
+        json!({ "balance": 100, "duration": duration, "retries": retries })
+    }
+
+    fn sample_latency(&self) -> u64 {
+        self.sample_latency_with(&mut rand::thread_rng())
+    }
+
+    fn sample_latency_with(&self, rng: &mut impl Rng) -> u64 {
+        let tail = -self.tail_ms * (1.0 - rng.r#gen::<f64>()).ln();
+        (self.latency_ms + tail).round() as u64
+    }
+
+    async fn sleep(&self, duration_ms: u64) {
+        tokio::time::sleep(Duration::from_millis(duration_ms)).await;
+    }
+}
@@ -0,0 +1,110 @@
+//! What commands would do under `--dry-run`, printed without contacting AWS
+//! or DSQL, to catch a wrong region or function before an expensive run
+
+use crate::cli::{DbArgs, LambdaArgs, MockArgs, WorkloadArgs};
+use crate::{db, lambda, migrations, setup};
+
+/// Print where a command would connect, as far as it can be told offline
+fn print_endpoints(db_args: &DbArgs) {
+    match db::cluster_endpoints(db_args) {
+        Ok(endpoints) => {
+            for endpoint in endpoints {
+                println!("  Endpoint:           {} ({})", endpoint.host, endpoint.region);
+            }
+            for host in &db_args.failover_endpoints {
+                println!("  Failover endpoint:  {}", host);
+            }
+        }
+        Err(err) => println!("  Endpoint:           ⚠️  {:#}", err),
+    }
+}
+
+/// One statement per line, however it's laid out in the source
+fn print_sql(sql: &str) {
+    println!("  {}", sql.split_whitespace().collect::<Vec<_>>().join(" "));
+}
+
+fn print_header() {
+    println!("Dry run: nothing will be sent to AWS or DSQL\n");
+}
+
+pub fn setup(db_args: &DbArgs, num_accounts: u32, with_indexes: bool, if_empty: bool) {
+    print_header();
+    print_endpoints(db_args);
+    println!();
+    println!("Would run, applying only migrations not yet recorded:");
+    for (_, _, sql) in migrations::all() {
+        print_sql(sql);
+    }
+    if if_empty {
+        println!("    then count the accounts and transactions, stopping if there are any");
+    }
+    print_sql("DELETE FROM accounts");
+    print_sql("DELETE FROM transactions");
+    print_sql(setup::INSERT_ACCOUNTS);
+    println!(
+        "    {} times, covering ids 1..={}",
+        num_accounts.div_ceil(setup::ACCOUNT_BATCH),
+        num_accounts
+    );
+    if with_indexes {
+        print_sql(setup::CREATE_PAYER_INDEX);
+    }
+}
+
+pub fn setup_chapter4(db_args: &DbArgs) {
+    print_header();
+    print_endpoints(db_args);
+    println!();
+    println!("Would run, applying only migrations not yet recorded:");
+    for (_, _, sql) in migrations::all() {
+        print_sql(sql);
+    }
+    println!("    then count the existing accounts, and run:");
+    print_sql(setup::INSERT_ACCOUNTS);
+    println!(
+        "    in batches of {} until there are {} accounts",
+        setup::ACCOUNT_BATCH,
+        setup::CHAPTER4_ACCOUNTS
+    );
+}
+
+pub fn teardown(db_args: &DbArgs) {
+    print_header();
+    print_endpoints(db_args);
+    println!();
+    println!("Would run, for each table that exists:");
+    for table in setup::TEARDOWN_TABLES {
+        print_sql(&format!("DROP TABLE IF EXISTS {}", table));
+    }
+}
+
+/// A load run: what it invokes, where, how hard, and with what requests
+pub fn run(
+    db_args: &DbArgs,
+    lambda_args: &LambdaArgs,
+    mock: &MockArgs,
+    clients: usize,
+    num_accounts: u32,
+    workload: &WorkloadArgs,
+    load: &str,
+) {
+    print_header();
+    println!("  Function:           {}", lambda::describe_target(lambda_args, mock));
+    if db_args.endpoints.is_empty() {
+        let region = db_args.region.as_deref().unwrap_or("(AWS profile)");
+        println!("  Region:             {}", region);
+    } else {
+        let regions: Vec<&str> = db_args.endpoints.iter().map(|e| e.region.as_str()).collect();
+        println!("  Regions:            {}", regions.join(", "));
+    }
+    if let Some(url) = &lambda_args.endpoint_url {
+        println!("  Endpoint URL:       {}", url);
+    }
+    println!("  Clients:            {}", clients);
+    println!("  Accounts:           1..={}", num_accounts);
+    println!("  Load:               {}", load);
+    println!("  Workload:           {}", workload.describe());
+    let seed = workload.seed.map_or("random".to_string(), |seed| seed.to_string());
+    println!("  Seed:               {}", seed);
+}
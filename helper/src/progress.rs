@@ -0,0 +1,178 @@
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget};
+use std::io::IsTerminal;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+static HIDDEN: AtomicBool = AtomicBool::new(false);
+
+/// Hide every progress bar created from here on if `quiet` is set or stderr
+/// isn't a terminal, so redirected output isn't full of carriage returns
+pub fn init(quiet: bool) {
+    HIDDEN.store(quiet || !std::io::stderr().is_terminal(), Ordering::Relaxed);
+}
+
+fn hidden() -> bool {
+    HIDDEN.load(Ordering::Relaxed)
+}
+
+pub fn bar(len: u64) -> ProgressBar {
+    if hidden() {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::new(len)
+    }
+}
+
+pub fn spinner() -> ProgressBar {
+    if hidden() {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::new_spinner()
+    }
+}
+
+pub fn multi() -> MultiProgress {
+    if hidden() {
+        MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+    } else {
+        MultiProgress::new()
+    }
+}
+
+/// Print a line above `pb`, or straight to stdout when `pb` isn't drawn so
+/// the line isn't lost
+pub fn println(pb: &ProgressBar, msg: impl AsRef<str>) {
+    if pb.is_hidden() {
+        println!("{}", msg.as_ref());
+    } else {
+        pb.println(msg);
+    }
+}
+
+/// How often coalesced progress bars are redrawn at most
+const COALESCE_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Redraw coalesced progress bars after this many events regardless
+const COALESCE_EVENTS: u32 = 1_000;
+
+/// Completed and in-flight counters for a pair of bars, applied to them
+/// either at once or, when coalescing, at most every
+/// [`COALESCE_INTERVAL`] or [`COALESCE_EVENTS`], so very high request
+/// rates don't spend their time redrawing
+pub struct Coalesced<'a> {
+    done: &'a ProgressBar,
+    in_flight: &'a ProgressBar,
+    coalesce: bool,
+    pending_done: u64,
+    pending_events: u32,
+    last_flush: Instant,
+}
+
+impl<'a> Coalesced<'a> {
+    pub fn new(done: &'a ProgressBar, in_flight: &'a ProgressBar, coalesce: bool) -> Self {
+        Self {
+            done,
+            in_flight,
+            coalesce,
+            pending_done: 0,
+            pending_events: 0,
+            last_flush: Instant::now(),
+        }
+    }
+
+    /// A request was started, with `in_flight` now outstanding
+    pub fn started(&mut self, in_flight: usize) {
+        self.pending_events += 1;
+        self.flush_if_due(in_flight);
+    }
+
+    /// A request finished, completing `completed` operations, with
+    /// `in_flight` still outstanding
+    pub fn finished(&mut self, completed: usize, in_flight: usize) {
+        self.pending_done += completed as u64;
+        self.pending_events += 1;
+        self.flush_if_due(in_flight);
+    }
+
+    /// Apply anything pending if it's been long enough since the last redraw
+    pub fn flush_if_due(&mut self, in_flight: usize) {
+        if self.pending_events == 0 {
+            return;
+        }
+        if !self.coalesce
+            || self.pending_events >= COALESCE_EVENTS
+            || self.last_flush.elapsed() >= COALESCE_INTERVAL
+        {
+            self.flush(in_flight);
+        }
+    }
+
+    /// Apply anything pending now
+    pub fn flush(&mut self, in_flight: usize) {
+        self.done.inc(self.pending_done);
+        self.in_flight.set_position(in_flight as u64);
+        self.pending_done = 0;
+        self.pending_events = 0;
+        self.last_flush = Instant::now();
+    }
+}
+
+/// Seconds of history a sparkline shows
+const SPARKLINE_WIDTH: usize = 60;
+
+/// A one-line chart of the last minute of per-second values, drawn with
+/// block characters, or ASCII where the terminal can't show them
+pub struct Sparkline {
+    values: VecDeque<u64>,
+    levels: &'static [char],
+}
+
+impl Sparkline {
+    pub fn new() -> Self {
+        Self {
+            values: VecDeque::with_capacity(SPARKLINE_WIDTH),
+            levels: if unicode_terminal() {
+                &['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█']
+            } else {
+                &['_', '.', '-', '~', '=', '+', '*', '#']
+            },
+        }
+    }
+
+    /// Add the latest second, dropping the oldest once the line is full
+    pub fn push(&mut self, value: u64) {
+        if self.values.len() == SPARKLINE_WIDTH {
+            self.values.pop_front();
+        }
+        self.values.push_back(value);
+    }
+
+    /// The line, scaled so the highest value shown fills its cell
+    pub fn render(&self) -> String {
+        let max = self.values.iter().copied().max().unwrap_or(0).max(1);
+        let top = (self.levels.len() - 1) as u64;
+        self.values
+            .iter()
+            .map(|&value| self.levels[(value * top / max) as usize])
+            .collect()
+    }
+}
+
+impl Default for Sparkline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Whether the locale says the terminal speaks UTF-8
+fn unicode_terminal() -> bool {
+    ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .is_some_and(|value| {
+            let value = value.to_ascii_lowercase();
+            value.contains("utf-8") || value.contains("utf8")
+        })
+}
@@ -0,0 +1,122 @@
+use anyhow::{Context, Result};
+use chrono::NaiveDateTime;
+use std::path::Path;
+use std::time::Duration;
+
+/// One transfer from a recorded workload
+#[derive(Clone, Debug)]
+pub struct RecordedTransfer {
+    pub payer_id: u32,
+    pub payee_id: u32,
+    pub amount: u32,
+    /// How long after the first transfer this one was made, when the
+    /// recording has timestamps
+    pub at: Option<Duration>,
+}
+
+/// Transfers read from a CSV with a header naming `payer_id`, `payee_id`,
+/// `amount` and, optionally, `created_at`, so the output of
+/// `export-transactions` can be replayed as it is.
+///
+/// ```csv
+/// payer_id,payee_id,amount,created_at
+/// 12,907,1,2025-12-01 18:30:00.125
+/// 44,3,5,2025-12-01 18:30:00.250
+/// ```
+#[derive(Debug)]
+pub struct Recording {
+    pub transfers: Vec<RecordedTransfer>,
+}
+
+fn parse_timestamp(text: &str) -> Option<NaiveDateTime> {
+    text.parse()
+        .ok()
+        .or_else(|| NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S%.f").ok())
+}
+
+impl Recording {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read recording {}", path.display()))?;
+        let mut lines = text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+        let Some((_, header)) = lines.next() else {
+            anyhow::bail!("Recording {} is empty", path.display());
+        };
+        let columns: Vec<&str> = header.split(',').map(str::trim).collect();
+        let column = |name: &str| columns.iter().position(|c| *c == name);
+        let required = |name: &str| {
+            column(name).with_context(|| {
+                format!("Recording {} has no {} column", path.display(), name)
+            })
+        };
+        let payer = required("payer_id")?;
+        let payee = required("payee_id")?;
+        let amount = required("amount")?;
+        let created_at = column("created_at");
+
+        let mut rows = Vec::new();
+        for (index, line) in lines {
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let line_no = index + 1;
+            let field = |i: usize| {
+                fields.get(i).copied().with_context(|| {
+                    format!("{}:{}: too few fields", path.display(), line_no)
+                })
+            };
+            let number = |i: usize| -> Result<u32> {
+                let text = field(i)?;
+                text.parse().with_context(|| {
+                    format!("{}:{}: {:?} is not a whole number", path.display(), line_no, text)
+                })
+            };
+            let at = match created_at {
+                Some(i) => {
+                    let text = field(i)?;
+                    let at = parse_timestamp(text).with_context(|| {
+                        format!("{}:{}: {:?} is not a timestamp", path.display(), line_no, text)
+                    })?;
+                    Some(at)
+                }
+                None => None,
+            };
+            rows.push((number(payer)?, number(payee)?, number(amount)?, at));
+        }
+        if rows.is_empty() {
+            anyhow::bail!("Recording {} has no transfers", path.display());
+        }
+
+        // Replay in the order the transfers were made, which an export
+        // sorted by id needn't be
+        rows.sort_by_key(|row| row.3);
+        let first = rows[0].3;
+        let transfers = rows
+            .into_iter()
+            .map(|(payer_id, payee_id, amount, at)| RecordedTransfer {
+                payer_id,
+                payee_id,
+                amount,
+                at: at.zip(first).map(|(at, first)| (at - first).to_std().unwrap_or_default()),
+            })
+            .collect();
+        Ok(Self { transfers })
+    }
+
+    /// The highest account id the recording touches, which the accounts
+    /// table needs to cover
+    pub fn max_account(&self) -> u32 {
+        self.transfers
+            .iter()
+            .map(|t| t.payer_id.max(t.payee_id))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Forget the recorded times, to send the transfers in order at
+    /// whatever pace the run sets
+    pub fn untimed(mut self) -> Self {
+        for transfer in &mut self.transfers {
+            transfer.at = None;
+        }
+        self
+    }
+}
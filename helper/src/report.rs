@@ -0,0 +1,256 @@
+use crate::cli::{DbArgs, HistoryRole};
+use crate::{credentials::CredentialCache, db, progress, stress};
+use anyhow::{Context, Result};
+use futures_util::TryStreamExt;
+use indicatif::ProgressStyle;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Print the balance of each of `account_ids`, failing if any don't exist
+pub async fn balances(
+    creds: &CredentialCache,
+    db_args: &DbArgs,
+    account_ids: &[i32],
+) -> Result<()> {
+    let pool = db::get_pool(creds, db_args).await?;
+
+    let found: Vec<(i32, i32)> =
+        sqlx::query_as("SELECT id, balance FROM accounts WHERE id = ANY($1) ORDER BY id")
+            .bind(account_ids)
+            .fetch_all(&pool)
+            .await?;
+
+    if let [(id, balance)] = found[..]
+        && account_ids.len() == 1
+    {
+        println!("Account {}: {}", id, balance);
+        return Ok(());
+    }
+    if !found.is_empty() {
+        println!("  {:>10} {:>12}", "Account", "Balance");
+        for (id, balance) in &found {
+            println!("  {:>10} {:>12}", id, balance);
+        }
+    }
+
+    let missing: Vec<String> = account_ids
+        .iter()
+        .filter(|id| !found.iter().any(|(found, _)| found == *id))
+        .map(|id| id.to_string())
+        .collect();
+    anyhow::ensure!(missing.is_empty(), "No such account: {}", missing.join(", "));
+    Ok(())
+}
+
+/// Print `account_id`'s latest transactions, newest first
+pub async fn history(
+    creds: &CredentialCache,
+    db_args: &DbArgs,
+    account_id: i32,
+    role: HistoryRole,
+    limit: i64,
+    since: Option<chrono::NaiveDateTime>,
+) -> Result<()> {
+    let pool = db::get_pool(creds, db_args).await?;
+    let transactions = db::account_history(&pool, account_id, role, limit, since).await?;
+    if transactions.is_empty() {
+        println!("No transactions recorded for account {}", account_id);
+        return Ok(());
+    }
+    println!("Latest {} transactions of account {}:", transactions.len(), account_id);
+    print_transactions(&transactions);
+    Ok(())
+}
+
+/// Print transactions as a numbered list
+pub fn print_transactions(transactions: &[db::Transaction]) {
+    for (i, tx) in transactions.iter().enumerate() {
+        println!(
+            "  {}. ID: {}, Payer: {}, Payee: {}, Amount: {}, Time: {}",
+            i + 1,
+            tx.id,
+            tx.payer_id,
+            tx.payee_id,
+            tx.amount,
+            tx.created_at
+        );
+    }
+}
+
+/// Print the accounts that paid most often, and how evenly payments were
+/// spread over all accounts
+pub async fn account_stats(creds: &CredentialCache, db_args: &DbArgs, top: usize) -> Result<()> {
+    let pool = db::get_pool(creds, db_args).await?;
+
+    let hottest: Vec<(i32, i64)> = sqlx::query_as(
+        "SELECT payer_id, COUNT(*) FROM transactions GROUP BY payer_id ORDER BY COUNT(*) DESC LIMIT $1",
+    )
+    .bind(top as i64)
+    .fetch_all(&pool)
+    .await?;
+
+    // Accounts that never paid count as zero, so a skewed workload shows up
+    // as a low minimum rather than being hidden
+    let (min, max, avg): (Option<i64>, Option<i64>, Option<f64>) = sqlx::query_as(
+        r#"
+        SELECT MIN(n), MAX(n), AVG(n)::float8
+        FROM (
+            SELECT a.id, COUNT(t.id) AS n
+            FROM accounts a
+            LEFT JOIN transactions t ON t.payer_id = a.id
+            GROUP BY a.id
+        ) per_account
+        "#,
+    )
+    .fetch_one(&pool)
+    .await?;
+
+    if hottest.is_empty() {
+        println!("No transactions recorded");
+        return Ok(());
+    }
+
+    println!("Top {} payers:", hottest.len());
+    println!("  {:>10} {:>12}", "Account", "Payments");
+    for (payer_id, count) in &hottest {
+        println!("  {:>10} {:>12}", payer_id, count);
+    }
+    println!();
+
+    println!("Payments per account:");
+    println!("  Min:                {}", min.unwrap_or(0));
+    println!("  Max:                {}", max.unwrap_or(0));
+    println!("  Avg:                {:.2}", avg.unwrap_or(0.0));
+
+    Ok(())
+}
+
+/// Stream the `transactions` table into a CSV file without holding it in
+/// memory
+pub async fn export_transactions(
+    creds: &CredentialCache,
+    db_args: &DbArgs,
+    path: &Path,
+    limit: Option<usize>,
+    since: Option<chrono::NaiveDateTime>,
+) -> Result<()> {
+    let pool = db::get_pool(creds, db_args).await?;
+
+    let file =
+        File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    let mut w = BufWriter::new(file);
+    writeln!(w, "id,payer_id,payee_id,amount,created_at")?;
+
+    let pb = progress::spinner();
+    pb.set_style(ProgressStyle::default_spinner().template("{spinner:.green} {pos} rows")?);
+
+    // A NULL limit means no limit
+    let sql = format!(
+        r#"
+        SELECT {}
+        FROM transactions
+        WHERE $1::timestamp IS NULL OR created_at >= $1
+        LIMIT $2
+        "#,
+        db::TRANSACTION_COLUMNS
+    );
+    let mut rows = sqlx::query_as::<_, db::Transaction>(&sql)
+        .bind(since)
+        .bind(limit.map(|n| n as i64))
+        .fetch(&pool);
+
+    while let Some(tx) = rows.try_next().await? {
+        writeln!(
+            w,
+            "{},{},{},{},{}",
+            tx.id, tx.payer_id, tx.payee_id, tx.amount, tx.created_at
+        )?;
+        pb.inc(1);
+    }
+    w.flush()?;
+    pb.finish_and_clear();
+
+    println!("Exported {} transactions to {}", pb.position(), path.display());
+    Ok(())
+}
+
+/// Stream every account's balance into an `id,balance` CSV without holding
+/// the table in memory, to snapshot balances before and after a run
+pub async fn export_accounts(creds: &CredentialCache, db_args: &DbArgs, path: &Path) -> Result<()> {
+    let pool = db::get_pool(creds, db_args).await?;
+
+    let file =
+        File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    let mut w = BufWriter::new(file);
+    writeln!(w, "id,balance")?;
+
+    let pb = progress::spinner();
+    pb.set_style(ProgressStyle::default_spinner().template("{spinner:.green} {pos} rows")?);
+
+    let mut rows = sqlx::query_as::<_, (i32, i32)>("SELECT id, balance FROM accounts ORDER BY id")
+        .fetch(&pool);
+    while let Some((id, balance)) = rows.try_next().await? {
+        writeln!(w, "{},{}", id, balance)?;
+        pb.inc(1);
+    }
+    w.flush()?;
+    pb.finish_and_clear();
+
+    println!("Exported {} accounts to {}", pb.position(), path.display());
+    Ok(())
+}
+
+/// Print the accounts and transactions row counts every `interval`, with
+/// the rate transactions were added since the previous sample, until Ctrl-C
+/// or `--max-runtime`. A failed sample is logged and skipped, so a blip
+/// doesn't end a long watch
+pub async fn watch(creds: &CredentialCache, db_args: &DbArgs, interval: Duration) -> Result<()> {
+    let pool = db::get_pool(creds, db_args).await?;
+    println!("Watching row counts every {}s, Ctrl-C to stop", interval.as_secs());
+
+    let start = Instant::now();
+    let mut ticks = tokio::time::interval(interval);
+    let mut last: Option<(Instant, i64)> = None;
+    let stop = stress::stop_requested();
+    tokio::pin!(stop);
+    loop {
+        tokio::select! {
+            _ = ticks.tick() => {}
+            why = &mut stop => {
+                println!("{}, stopping", why);
+                return Ok(());
+            }
+        }
+        let counts: Result<(i64, i64), sqlx::Error> = sqlx::query_as(
+            "SELECT (SELECT COUNT(*) FROM accounts), (SELECT COUNT(*) FROM transactions)",
+        )
+        .fetch_one(&pool)
+        .await;
+        let (accounts, transactions) = match counts {
+            Ok(counts) => counts,
+            Err(err) => {
+                tracing::warn!(%err, "failed to count rows");
+                continue;
+            }
+        };
+        let now = Instant::now();
+        let rate = match last {
+            Some((at, before)) => format!(
+                " | +{} ({:.1}/s)",
+                transactions - before,
+                (transactions - before) as f64 / now.duration_since(at).as_secs_f64()
+            ),
+            None => String::new(),
+        };
+        println!(
+            "[{}s] accounts: {} | transactions: {}{}",
+            now.duration_since(start).as_secs(),
+            accounts,
+            transactions,
+            rate
+        );
+        last = Some((now, transactions));
+    }
+}
@@ -0,0 +1,213 @@
+use crate::cli::S3Location;
+use crate::credentials::CredentialCache;
+use crate::metadata::RunMetadata;
+use crate::summary::StressSummary;
+use anyhow::{Context, Result};
+use aws_config::BehaviorVersion;
+use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart};
+use hdrhistogram::serialization::{Deserializer, Serializer, V2Serializer};
+use hdrhistogram::Histogram;
+use std::fs::File;
+use std::collections::HashMap;
+use std::io::{BufReader, BufWriter, Read};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// Directory holding every artifact of one run, named after its run id
+pub struct ResultsDir {
+    path: PathBuf,
+}
+
+impl ResultsDir {
+    /// Create `<root>/<run_id>`, and `root` itself if needed
+    pub fn create(root: &Path, run_id: Uuid) -> Result<Self> {
+        let path = root.join(run_id.to_string());
+        std::fs::create_dir_all(&path)
+            .with_context(|| format!("Failed to create results directory {}", path.display()))?;
+        Ok(Self { path })
+    }
+
+    /// Where a sustained run's per-second throughput is written as it goes
+    pub fn throughput_csv(&self) -> PathBuf {
+        self.path.join("throughput.csv")
+    }
+
+    /// Write the summary and the full latency histogram
+    pub fn write(&self, summary: &StressSummary, latency: &Histogram<u64>) -> Result<()> {
+        let summary_path = self.path.join("summary.json");
+        let json = serde_json::to_string_pretty(summary)?;
+        std::fs::write(&summary_path, json)
+            .with_context(|| format!("Failed to write {}", summary_path.display()))?;
+
+        let hdr_path = self.path.join("latency.hdr");
+        let mut hdr = BufWriter::new(
+            File::create(&hdr_path)
+                .with_context(|| format!("Failed to create {}", hdr_path.display()))?,
+        );
+        V2Serializer::new()
+            .serialize(latency, &mut hdr)
+            .map_err(|e| anyhow::anyhow!("Failed to write {}: {:?}", hdr_path.display(), e))?;
+
+        println!("Results written to {}", self.path.display());
+        Ok(())
+    }
+}
+
+/// Load and add up the latency histograms saved in `paths`, as
+/// [`ResultsDir::write`] leaves them in latency.hdr, or None if there are
+/// none
+pub fn load_histograms(paths: &[PathBuf]) -> Result<Option<Histogram<u64>>> {
+    let mut merged: Option<Histogram<u64>> = None;
+    for path in paths {
+        let mut reader = BufReader::new(
+            File::open(path).with_context(|| format!("Failed to open {}", path.display()))?,
+        );
+        let hist: Histogram<u64> = Deserializer::new()
+            .deserialize(&mut reader)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {:?}", path.display(), e))?;
+        match &mut merged {
+            Some(merged) => merged
+                .add(&hist)
+                .map_err(|e| anyhow::anyhow!("Failed to merge {}: {:?}", path.display(), e))?,
+            None => merged = Some(hist),
+        }
+    }
+    Ok(merged)
+}
+
+/// Files at least this big are uploaded in parts
+const MULTIPART_THRESHOLD: u64 = 16 * 1024 * 1024;
+
+/// Size of each part of a multipart upload; S3 needs at least 5 MiB
+const PART_SIZE: usize = 8 * 1024 * 1024;
+
+/// Upload every file in `<root>/<run_id>` to `<location>/<run_id>/`,
+/// printing the URI of each. Each object carries the run id and tags as
+/// user metadata.
+pub async fn upload_to_s3(
+    creds: &CredentialCache,
+    root: &Path,
+    metadata: &RunMetadata,
+    location: &S3Location,
+) -> Result<()> {
+    let run_id = metadata.run_id;
+    let object_metadata = metadata.s3_metadata();
+    let credentials = creds.get_credentials().await?;
+    let config = aws_config::defaults(BehaviorVersion::latest())
+        .credentials_provider(credentials)
+        .load()
+        .await;
+    let client = aws_sdk_s3::Client::new(&config);
+
+    let dir = root.join(run_id.to_string());
+    let entries = std::fs::read_dir(&dir)
+        .with_context(|| format!("Failed to read results directory {}", dir.display()))?;
+    for entry in entries {
+        let path = entry?.path();
+        if !path.is_file() {
+            continue;
+        }
+        let name = path.file_name().unwrap().to_string_lossy();
+        let key = format!("{}{}/{}", location.prefix, run_id, name);
+        upload_file(&client, &location.bucket, &key, &path, &object_metadata)
+            .await
+            .with_context(|| format!("Failed to upload {}", path.display()))?;
+        println!("Uploaded s3://{}/{}", location.bucket, key);
+    }
+    Ok(())
+}
+
+async fn upload_file(
+    client: &aws_sdk_s3::Client,
+    bucket: &str,
+    key: &str,
+    path: &Path,
+    metadata: &HashMap<String, String>,
+) -> Result<()> {
+    if std::fs::metadata(path)?.len() < MULTIPART_THRESHOLD {
+        client
+            .put_object()
+            .bucket(bucket)
+            .key(key)
+            .set_metadata(Some(metadata.clone()))
+            .body(ByteStream::from_path(path).await?)
+            .send()
+            .await?;
+        return Ok(());
+    }
+
+    let upload = client
+        .create_multipart_upload()
+        .bucket(bucket)
+        .key(key)
+        .set_metadata(Some(metadata.clone()))
+        .send()
+        .await?;
+    let upload_id = upload
+        .upload_id()
+        .context("S3 returned no multipart upload id")?;
+    let parts = upload_parts(client, bucket, key, upload_id, path).await;
+    let parts = match parts {
+        Ok(parts) => parts,
+        Err(err) => {
+            // Don't leave the parts behind to be billed for
+            let _ = client
+                .abort_multipart_upload()
+                .bucket(bucket)
+                .key(key)
+                .upload_id(upload_id)
+                .send()
+                .await;
+            return Err(err);
+        }
+    };
+    client
+        .complete_multipart_upload()
+        .bucket(bucket)
+        .key(key)
+        .upload_id(upload_id)
+        .multipart_upload(
+            CompletedMultipartUpload::builder()
+                .set_parts(Some(parts))
+                .build(),
+        )
+        .send()
+        .await?;
+    Ok(())
+}
+
+async fn upload_parts(
+    client: &aws_sdk_s3::Client,
+    bucket: &str,
+    key: &str,
+    upload_id: &str,
+    path: &Path,
+) -> Result<Vec<CompletedPart>> {
+    let mut file = File::open(path)?;
+    let mut parts = Vec::new();
+    loop {
+        let mut chunk = Vec::with_capacity(PART_SIZE);
+        (&mut file).take(PART_SIZE as u64).read_to_end(&mut chunk)?;
+        if chunk.is_empty() {
+            break;
+        }
+        let part_number = parts.len() as i32 + 1;
+        let part = client
+            .upload_part()
+            .bucket(bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .part_number(part_number)
+            .body(ByteStream::from(chunk))
+            .send()
+            .await?;
+        parts.push(
+            CompletedPart::builder()
+                .part_number(part_number)
+                .set_e_tag(part.e_tag().map(str::to_string))
+                .build(),
+        );
+    }
+    Ok(parts)
+}
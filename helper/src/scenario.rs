@@ -0,0 +1,78 @@
+use crate::cli::WorkloadArgs;
+use crate::stress::{Phase, RateSchedule};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::path::Path;
+
+/// A sustained-load experiment described in a TOML file, so benchmarks can
+/// be versioned and reviewed alongside the code.
+///
+/// ```toml
+/// accounts = 1000
+/// read_ratio = 0.2
+/// amount = 1
+/// max_amount = 50
+/// seed = 42
+///
+/// [[phases]]
+/// rate = 100
+/// duration_secs = 60
+///
+/// [[phases]]
+/// rate = 500
+/// duration_secs = 60
+/// ```
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    #[serde(default = "default_accounts")]
+    pub accounts: u32,
+    pub read_ratio: Option<f64>,
+    #[serde(default = "default_amount")]
+    pub amount: u32,
+    pub max_amount: Option<u32>,
+    pub seed: Option<u64>,
+    pub phases: Vec<Phase>,
+}
+
+fn default_accounts() -> u32 {
+    1000
+}
+
+fn default_amount() -> u32 {
+    1
+}
+
+impl Scenario {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read scenario {}", path.display()))?;
+        let scenario: Scenario = toml::from_str(&text)
+            .with_context(|| format!("Failed to parse scenario {}", path.display()))?;
+
+        if scenario.phases.is_empty() {
+            anyhow::bail!("Scenario {} has no phases", path.display());
+        }
+        if let Some(ratio) = scenario.read_ratio
+            && !(0.0..=1.0).contains(&ratio)
+        {
+            anyhow::bail!("read_ratio {} is not within [0, 1]", ratio);
+        }
+
+        Ok(scenario)
+    }
+
+    pub fn schedule(&self) -> RateSchedule {
+        RateSchedule::Phases(self.phases.clone())
+    }
+
+    pub fn workload(&self) -> WorkloadArgs {
+        WorkloadArgs {
+            read_ratio: self.read_ratio,
+            amount: self.amount,
+            max_amount: self.max_amount,
+            seed: self.seed,
+            ..WorkloadArgs::default()
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use crate::cli::{DbArgs, HistoryRole, LambdaArgs, MockArgs, RunArgs, WorkloadArgs};
+use crate::credentials::CredentialCache;
+use crate::{db, lambda, report, setup, stress};
+use anyhow::Result;
+
+/// Every account's balance after setup
+const INITIAL_BALANCE: i64 = 100;
+
+/// Transactions of one account the history stage prints
+const HISTORY_LIMIT: i64 = 5;
+
+/// How each stage went, printed as it finishes and again at the end
+#[derive(Default)]
+struct Stages(Vec<(&'static str, Result<()>)>);
+
+impl Stages {
+    /// Record how `name` went; true if it passed
+    fn record(&mut self, name: &'static str, result: Result<()>) -> bool {
+        match &result {
+            Ok(()) => println!("✅ {} passed", name),
+            Err(err) => println!("❌ {} failed: {:#}", name, err),
+        }
+        println!();
+        let passed = result.is_ok();
+        self.0.push((name, result));
+        passed
+    }
+
+    /// Print every stage's outcome, failing with the first stage's error
+    fn finish(self) -> Result<()> {
+        println!("Self-test:");
+        for (name, result) in &self.0 {
+            match result {
+                Ok(()) => println!("  ✅ {}", name),
+                Err(_) => println!("  ❌ {}", name),
+            }
+        }
+        println!();
+        match self.0.into_iter().find_map(|(name, result)| Some((name, result.err()?))) {
+            Some((name, err)) => Err(err.context(format!("Self-test {} stage failed", name))),
+            None => {
+                println!("✅ Self-test PASSED");
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Run setup, a stress burst, the conservation and ledger checks, the
+/// history query and teardown in a schema of their own, so the demo's
+/// tables are never touched. Teardown runs whenever setup got started.
+pub async fn self_test(
+    creds: &CredentialCache,
+    db_args: &DbArgs,
+    lambda_args: &LambdaArgs,
+    mock: &MockArgs,
+    accounts: u32,
+    transfers: usize,
+    parallel: usize,
+) -> Result<()> {
+    let schema = format!("selftest_{:08x}", rand::random::<u32>());
+    println!("Self-test in schema {}", schema);
+    println!();
+    let db_args = DbArgs {
+        schema: Some(schema.clone()),
+        ..db_args.clone()
+    };
+
+    let mut stages = Stages::default();
+    if stages.record("setup", set_up(creds, &db_args, &schema, accounts).await) {
+        let burst = burst(creds, &db_args, lambda_args, mock, accounts, transfers, parallel).await;
+        let applied = burst.as_ref().ok().copied();
+        if stages.record("stress", burst.map(|_| ()))
+            && let Some(applied) = applied
+        {
+            stages.record("conservation", conservation(creds, &db_args, accounts, applied).await);
+            stages.record("history", history(creds, &db_args).await);
+        }
+    }
+    stages.record("teardown", tear_down(creds, &db_args, &schema).await);
+    stages.finish()
+}
+
+async fn set_up(
+    creds: &CredentialCache,
+    db_args: &DbArgs,
+    schema: &str,
+    accounts: u32,
+) -> Result<()> {
+    let pool = db::get_pool(creds, db_args).await?;
+    sqlx::query(&format!("CREATE SCHEMA {}", schema))
+        .execute(&pool)
+        .await?;
+    setup::create_schema(&pool, accounts, true).await
+}
+
+/// Send `transfers` transfers from here, returning how many were applied
+async fn burst(
+    creds: &CredentialCache,
+    db_args: &DbArgs,
+    lambda_args: &LambdaArgs,
+    mock: &MockArgs,
+    accounts: u32,
+    transfers: usize,
+    parallel: usize,
+) -> Result<usize> {
+    // The Lambdas only know the public tables, so the transfers have to
+    // run from here to land in the self-test's schema
+    let lambda_args = LambdaArgs {
+        direct: true,
+        ..lambda_args.clone()
+    };
+    let mock = MockArgs {
+        mock: false,
+        ..mock.clone()
+    };
+    let client_pool = lambda::client_pool(creds, 1, &lambda_args, db_args, &mock).await?;
+    let pace = stress::Pace {
+        parallel,
+        rate: None,
+        ramp_in: None,
+    };
+    let summary = stress::run_stress_test(
+        &client_pool,
+        transfers,
+        pace,
+        accounts,
+        &WorkloadArgs::default(),
+        &RunArgs::default(),
+    )
+    .await?;
+    if summary.errors > 0 {
+        anyhow::bail!("{} of {} transfers failed", summary.errors, summary.total_calls);
+    }
+    Ok(summary.applied_transfers)
+}
+
+async fn conservation(
+    creds: &CredentialCache,
+    db_args: &DbArgs,
+    accounts: u32,
+    applied: usize,
+) -> Result<()> {
+    db::verify_conservation(creds, db_args, accounts, INITIAL_BALANCE).await?;
+    // The schema started with an empty ledger
+    db::verify_ledger(creds, db_args, 0, applied).await
+}
+
+/// Look up the history of the account that paid last, which the burst
+/// guarantees has some
+async fn history(creds: &CredentialCache, db_args: &DbArgs) -> Result<()> {
+    let pool = db::get_pool(creds, db_args).await?;
+    let last_payer: Option<(i32,)> =
+        sqlx::query_as("SELECT payer_id FROM transactions ORDER BY created_at DESC LIMIT 1")
+            .fetch_optional(&pool)
+            .await?;
+    let Some((account_id,)) = last_payer else {
+        anyhow::bail!("The transactions table is empty");
+    };
+    let transactions =
+        db::account_history(&pool, account_id, HistoryRole::Either, HISTORY_LIMIT, None).await?;
+    if transactions.is_empty() {
+        anyhow::bail!("No transactions found for account {}, which paid last", account_id);
+    }
+    println!("Latest {} transactions of account {}:", transactions.len(), account_id);
+    report::print_transactions(&transactions);
+    Ok(())
+}
+
+async fn tear_down(creds: &CredentialCache, db_args: &DbArgs, schema: &str) -> Result<()> {
+    // Only sees the self-test's schema, so can't drop the demo's tables
+    setup::teardown(creds, db_args, true).await?;
+    let pool = db::get_pool(creds, db_args).await?;
+    sqlx::query(&format!("DROP SCHEMA IF EXISTS {}", schema))
+        .execute(&pool)
+        .await?;
+    println!("Dropped schema {}", schema);
+    Ok(())
+}
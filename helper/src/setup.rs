@@ -1,77 +1,218 @@
+use crate::cli::DbArgs;
 use crate::credentials::CredentialCache;
 use crate::db;
-use anyhow::Result;
-use indicatif::{ProgressBar, ProgressStyle};
+use crate::migrations;
+use crate::progress;
+use anyhow::{Context, Result};
+use indicatif::ProgressStyle;
+use sqlx::{Pool, Postgres};
+use std::collections::HashSet;
+use std::path::Path;
 
-pub async fn setup_schema(creds: &CredentialCache, num_accounts: u32) -> Result<()> {
+/// Inserts accounts `$1..=$2` with the starting balance of 100, exact
+/// balance included
+pub const INSERT_ACCOUNTS: &str = "INSERT INTO accounts (id, balance, exact_balance) \
+     SELECT id, 100, 100 FROM generate_series($1, $2) AS id";
+
+/// Most rows setup writes in one transaction, DSQL's limit
+pub const ACCOUNT_BATCH: u32 = 1_000;
+
+/// Accounts chapter 4 needs
+pub const CHAPTER4_ACCOUNTS: i64 = 1_000_000;
+
+pub const CREATE_PAYER_INDEX: &str =
+    "CREATE INDEX IF NOT EXISTS idx_tx_payer ON transactions(payer_id)";
+
+/// Tables teardown drops, in order: the ledger never outlives its accounts,
+/// and the migration history goes last so a fresh setup recreates the tables
+pub const TEARDOWN_TABLES: [&str; 3] = ["transactions", "accounts", "schema_migrations"];
+
+pub async fn setup_schema(
+    creds: &CredentialCache,
+    db_args: &DbArgs,
+    num_accounts: u32,
+    with_indexes: bool,
+    if_empty: bool,
+) -> Result<()> {
     println!("Setting up database schema...");
-    let pool = db::get_pool(creds).await?;
+    let pool = db::get_pool(creds, db_args).await?;
+    create_schema(&pool, num_accounts, if_empty).await?;
+    if with_indexes {
+        create_indexes(&pool).await?;
+    }
+    Ok(())
+}
 
-    // Create accounts table
-    sqlx::query(
-        r#"
-        CREATE TABLE IF NOT EXISTS accounts (
-            id INTEGER PRIMARY KEY,
-            balance INTEGER NOT NULL
-        )
-        "#,
-    )
-    .execute(&pool)
-    .await?;
-    println!("Created accounts table");
+/// Index the chapter 3 history lookup by payer, which otherwise scans the
+/// whole ledger. Does nothing if the index already exists.
+pub async fn create_indexes(pool: &Pool<Postgres>) -> Result<()> {
+    let (exists,): (bool,) = sqlx::query_as("SELECT to_regclass('idx_tx_payer') IS NOT NULL")
+        .fetch_one(pool)
+        .await?;
+    if exists {
+        println!("Index idx_tx_payer already exists");
+        return Ok(());
+    }
+    sqlx::query(CREATE_PAYER_INDEX).execute(pool).await?;
+    println!("Created index idx_tx_payer on transactions(payer_id)");
+    Ok(())
+}
 
-    // Create transactions table
-    sqlx::query(
-        r#"
-        CREATE TABLE IF NOT EXISTS transactions (
-            id UUID DEFAULT gen_random_uuid() PRIMARY KEY,
-            payer_id INT,
-            payee_id INT,
-            amount INT,
-            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
-        )
-        "#,
+/// Bring the schema up to date on `pool` and fill `accounts` with
+/// `num_accounts` accounts of balance 100, discarding any existing rows,
+/// or refusing to if `if_empty`
+pub async fn create_schema(
+    pool: &Pool<Postgres>,
+    num_accounts: u32,
+    if_empty: bool,
+) -> Result<()> {
+    migrations::migrate(pool).await?;
+
+    let (accounts, transactions): (i64, i64) = sqlx::query_as(
+        "SELECT (SELECT COUNT(*) FROM accounts), (SELECT COUNT(*) FROM transactions)",
     )
-    .execute(&pool)
+    .fetch_one(pool)
     .await?;
-    println!("Created transactions table");
+    if accounts + transactions > 0 {
+        if if_empty {
+            anyhow::bail!(
+                "The database already holds {} accounts and {} transactions; \
+                 run setup without --if-empty to replace them",
+                accounts,
+                transactions
+            );
+        }
+        println!(
+            "⚠️  WARNING: deleting {} accounts and {} transactions already in the database",
+            accounts, transactions
+        );
+    }
 
     // Clear existing data
-    sqlx::query("DELETE FROM accounts").execute(&pool).await?;
+    sqlx::query("DELETE FROM accounts").execute(pool).await?;
     sqlx::query("DELETE FROM transactions")
-        .execute(&pool)
+        .execute(pool)
         .await?;
     println!("Cleared existing data");
 
     // Insert accounts using generate_series in batches
     println!("Inserting {} accounts...", num_accounts);
-    const BATCH_SIZE: i32 = 1_000; // DSQL transaction row limit
     let mut inserted = 0i32;
 
     while inserted < num_accounts as i32 {
         let start_id = inserted + 1;
-        let end_id = (inserted + BATCH_SIZE).min(num_accounts as i32);
+        let end_id = (inserted + ACCOUNT_BATCH as i32).min(num_accounts as i32);
+
+        sqlx::query(INSERT_ACCOUNTS)
+            .bind(start_id)
+            .bind(end_id)
+            .execute(pool)
+            .await?;
+
+        inserted = end_id;
+    }
+
+    println!("Database setup complete!");
+    Ok(())
+}
+
+/// Apply any schema migrations the database hasn't had yet, leaving data
+/// alone
+pub async fn migrate(creds: &CredentialCache, db_args: &DbArgs) -> Result<()> {
+    let pool = db::get_pool(creds, db_args).await?;
+    match migrations::migrate(&pool).await? {
+        0 => println!("✅ Schema is up to date"),
+        n => println!("✅ Applied {} migrations", n),
+    }
+    Ok(())
+}
+
+/// Replace the contents of `accounts` with the `id,balance` rows of a CSV
+/// file, so a run can use a real account profile instead of uniform
+/// balances. The file is checked in full before anything is deleted.
+pub async fn import_accounts(creds: &CredentialCache, db_args: &DbArgs, path: &Path) -> Result<()> {
+    let (ids, balances) = read_accounts_csv(path)?;
+    anyhow::ensure!(!ids.is_empty(), "{} has no accounts", path.display());
+
+    let pool = db::get_pool(creds, db_args).await?;
+    // The rows written below need the exact balance column
+    migrations::migrate(&pool).await?;
+    sqlx::query("DELETE FROM accounts").execute(&pool).await?;
+    println!("Cleared existing accounts");
+
+    println!("Importing {} accounts...", ids.len());
+    let pb = progress::bar(ids.len() as u64);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("[{bar:40}] {pos}/{len} accounts")?
+            .progress_chars("=>-"),
+    );
 
+    const BATCH_SIZE: usize = 1_000; // DSQL transaction row limit
+    for (ids, balances) in ids.chunks(BATCH_SIZE).zip(balances.chunks(BATCH_SIZE)) {
         sqlx::query(
-            "INSERT INTO accounts (id, balance) SELECT id, 100 FROM generate_series($1, $2) AS id",
+            "INSERT INTO accounts (id, balance, exact_balance) \
+             SELECT id, balance, balance FROM UNNEST($1::int[], $2::int[]) AS a (id, balance)",
         )
-        .bind(start_id)
-        .bind(end_id)
+        .bind(ids)
+        .bind(balances)
         .execute(&pool)
         .await?;
-
-        inserted = end_id;
+        pb.inc(ids.len() as u64);
     }
+    pb.finish_and_clear();
 
-    println!("Database setup complete!");
+    println!("✅ Imported {} accounts from {}", ids.len(), path.display());
     Ok(())
 }
 
-pub async fn setup_chapter4(creds: &CredentialCache) -> Result<()> {
+/// Parse an `id,balance` CSV, with or without a header row, into ids and
+/// balances. Ids must be unique and positive, and balances non-negative.
+fn read_accounts_csv(path: &Path) -> Result<(Vec<i32>, Vec<i32>)> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+
+    let mut ids = Vec::new();
+    let mut balances = Vec::new();
+    let mut seen = HashSet::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line_no = index + 1;
+        let line = line.trim();
+        if line.is_empty() || (line_no == 1 && line.replace(' ', "") == "id,balance") {
+            continue;
+        }
+        let (id, balance) = line
+            .split_once(',')
+            .with_context(|| format!("line {}: expected id,balance", line_no))?;
+        let id: i32 = id
+            .trim()
+            .parse()
+            .with_context(|| format!("line {}: invalid id {:?}", line_no, id))?;
+        let balance: i32 = balance
+            .trim()
+            .parse()
+            .with_context(|| format!("line {}: invalid balance {:?}", line_no, balance))?;
+        anyhow::ensure!(id > 0, "line {}: id {} is not positive", line_no, id);
+        anyhow::ensure!(
+            balance >= 0,
+            "line {}: account {} has negative balance {}",
+            line_no,
+            id,
+            balance
+        );
+        anyhow::ensure!(seen.insert(id), "line {}: duplicate id {}", line_no, id);
+        ids.push(id);
+        balances.push(balance);
+    }
+    Ok((ids, balances))
+}
+
+pub async fn setup_chapter4(creds: &CredentialCache, db_args: &DbArgs) -> Result<()> {
     println!("Setting up Chapter 4: Creating 1M accounts\n");
 
-    const TARGET_ACCOUNTS: i64 = 1_000_000;
-    let pool = db::get_pool(creds).await?;
+    let pool = db::get_pool(creds, db_args).await?;
+    // The accounts inserted below need the exact balance column
+    migrations::migrate(&pool).await?;
 
     // Check current account count
     let row: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM accounts")
@@ -81,40 +222,37 @@ pub async fn setup_chapter4(creds: &CredentialCache) -> Result<()> {
 
     println!("Current account count: {}", current_count);
 
-    if current_count >= TARGET_ACCOUNTS {
+    if current_count >= CHAPTER4_ACCOUNTS {
         println!("Already have sufficient accounts\n");
         println!("✅ Chapter 4 setup complete");
         return Ok(());
     }
 
-    let needed_accounts = TARGET_ACCOUNTS - current_count;
+    let needed_accounts = CHAPTER4_ACCOUNTS - current_count;
     println!(
         "Inserting {} more accounts to reach {}...\n",
-        needed_accounts, TARGET_ACCOUNTS
+        needed_accounts, CHAPTER4_ACCOUNTS
     );
 
-    let pb = ProgressBar::new(needed_accounts as u64);
+    let pb = progress::bar(needed_accounts as u64);
     pb.set_style(
         ProgressStyle::default_bar()
             .template("[{bar:40}] {pos}/{len} accounts")?
             .progress_chars("=>-"),
     );
 
-    const BATCH_SIZE: i64 = 1_000; // DSQL transaction row limit
     let mut inserted = 0i64;
 
     while inserted < needed_accounts {
         let start_id = current_count + inserted + 1;
-        let end_id = (current_count + inserted + BATCH_SIZE).min(TARGET_ACCOUNTS);
+        let end_id = (current_count + inserted + ACCOUNT_BATCH as i64).min(CHAPTER4_ACCOUNTS);
         let batch_count = end_id - start_id + 1;
 
-        sqlx::query(
-            "INSERT INTO accounts (id, balance) SELECT id, 100 FROM generate_series($1, $2) AS id",
-        )
-        .bind(start_id as i32)
-        .bind(end_id as i32)
-        .execute(&pool)
-        .await?;
+        sqlx::query(INSERT_ACCOUNTS)
+            .bind(start_id as i32)
+            .bind(end_id as i32)
+            .execute(&pool)
+            .await?;
 
         inserted += batch_count;
         pb.set_position(inserted as u64);
@@ -126,3 +264,256 @@ pub async fn setup_chapter4(creds: &CredentialCache) -> Result<()> {
 
     Ok(())
 }
+
+/// Delete transactions created more than `older_than_hours` ago, a batch
+/// at a time so no single transaction gets too big for DSQL
+pub async fn prune_transactions(
+    creds: &CredentialCache,
+    db_args: &DbArgs,
+    older_than_hours: u32,
+) -> Result<()> {
+    let pool = db::get_pool(creds, db_args).await?;
+    // Fixed up front, so rows written while pruning are never caught
+    let (cutoff,): (chrono::NaiveDateTime,) =
+        sqlx::query_as("SELECT LOCALTIMESTAMP - make_interval(hours => $1)")
+            .bind(older_than_hours as i32)
+            .fetch_one(&pool)
+            .await?;
+    println!("Deleting transactions created before {}", cutoff);
+
+    let pb = progress::spinner();
+    pb.set_style(ProgressStyle::default_spinner().template("{spinner:.green} {pos} rows deleted")?);
+
+    const BATCH_SIZE: i64 = 1_000; // DSQL transaction row limit
+    loop {
+        let deleted = sqlx::query(
+            r#"
+            DELETE FROM transactions
+            WHERE id IN (SELECT id FROM transactions WHERE created_at < $1 LIMIT $2)
+            "#,
+        )
+        .bind(cutoff)
+        .bind(BATCH_SIZE)
+        .execute(&pool)
+        .await?
+        .rows_affected();
+        pb.inc(deleted);
+        if deleted == 0 {
+            break;
+        }
+    }
+    pb.finish_and_clear();
+
+    println!("✅ Deleted {} transactions", pb.position());
+    Ok(())
+}
+
+/// Report how far the balances have drifted from `initial_balance` and,
+/// with `apply`, reset every account that drifted back to it. The ledger
+/// is left alone.
+///
+/// The reset is one transaction when no more than 1,000 accounts drifted,
+/// DSQL's row limit, and one per 1,000 accounts beyond that.
+pub async fn repair_balances(
+    creds: &CredentialCache,
+    db_args: &DbArgs,
+    initial_balance: i64,
+    apply: bool,
+) -> Result<()> {
+    let pool = db::get_pool(creds, db_args).await?;
+    let (accounts, total, drifted): (i64, Option<i64>, i64) = sqlx::query_as(
+        r#"
+        SELECT COUNT(*), SUM(balance)::bigint, COUNT(*) FILTER (WHERE balance <> $1)
+        FROM accounts
+        "#,
+    )
+    .bind(initial_balance as i32)
+    .fetch_one(&pool)
+    .await?;
+    let expected = accounts * initial_balance;
+    let drift = total.unwrap_or(0) - expected;
+    println!("Balance Repair:");
+    println!("  Expected total:       {} ({} x {})", expected, accounts, initial_balance);
+    println!("  Actual total:         {}", total.unwrap_or(0));
+    println!("  Drift:                {:+}", drift);
+    println!("  Accounts off {:<8} {}", format!("{}:", initial_balance), drifted);
+    println!();
+
+    if drifted == 0 {
+        println!("✅ Every account holds {}, nothing to repair", initial_balance);
+        return Ok(());
+    }
+    if !apply {
+        println!(
+            "Dry run: pass --apply to reset {} accounts to {}",
+            drifted, initial_balance
+        );
+        return Ok(());
+    }
+
+    let pb = progress::spinner();
+    pb.set_style(
+        ProgressStyle::default_spinner().template("{spinner:.green} {pos} accounts reset")?,
+    );
+    loop {
+        let reset = sqlx::query(
+            r#"
+            UPDATE accounts SET balance = $1
+            WHERE id IN (SELECT id FROM accounts WHERE balance <> $1 LIMIT $2)
+            "#,
+        )
+        .bind(initial_balance as i32)
+        .bind(ACCOUNT_BATCH as i64)
+        .execute(&pool)
+        .await?
+        .rows_affected();
+        pb.inc(reset);
+        if reset == 0 {
+            break;
+        }
+    }
+    pb.finish_and_clear();
+
+    println!(
+        "✅ Reset {} accounts to {}, correcting a drift of {:+}",
+        pb.position(),
+        initial_balance,
+        drift
+    );
+    Ok(())
+}
+
+/// Set every account's balance and exact balance to `initial_balance`,
+/// keeping the schema and the transactions table, for a fresh start between
+/// experiments. DSQL caps the rows one transaction may change, so accounts
+/// are updated `ACCOUNT_BATCH` at a time in id order rather than in one
+/// `UPDATE`
+pub async fn reset_balances(
+    creds: &CredentialCache,
+    db_args: &DbArgs,
+    initial_balance: i64,
+) -> Result<()> {
+    let pool = db::get_pool(creds, db_args).await?;
+    // Accounts set up before the exact balance column existed get one here
+    migrations::migrate(&pool).await?;
+
+    let pb = progress::spinner();
+    pb.set_style(
+        ProgressStyle::default_spinner().template("{spinner:.green} {pos} accounts reset")?,
+    );
+    let mut last_id = 0;
+    loop {
+        let ids: Vec<(i32,)> = sqlx::query_as(
+            r#"
+            UPDATE accounts SET balance = $1, exact_balance = $1
+            WHERE id IN (SELECT id FROM accounts WHERE id > $2 ORDER BY id LIMIT $3)
+            RETURNING id
+            "#,
+        )
+        .bind(initial_balance as i32)
+        .bind(last_id)
+        .bind(ACCOUNT_BATCH as i64)
+        .fetch_all(&pool)
+        .await?;
+        let Some(max_id) = ids.iter().map(|(id,)| *id).max() else {
+            break;
+        };
+        pb.inc(ids.len() as u64);
+        last_id = max_id;
+    }
+    pb.finish_and_clear();
+
+    println!(
+        "✅ Reset {} accounts to {}, keeping the transactions table",
+        pb.position(),
+        initial_balance
+    );
+    Ok(())
+}
+
+pub async fn teardown(creds: &CredentialCache, db_args: &DbArgs, confirmed: bool) -> Result<()> {
+    if !confirmed {
+        anyhow::bail!("Teardown drops the accounts and transactions tables; pass --yes to confirm");
+    }
+
+    println!("Tearing down database schema...");
+    let pool = db::get_pool(creds, db_args).await?;
+
+    for table in TEARDOWN_TABLES {
+        let exists: (bool,) = sqlx::query_as("SELECT to_regclass($1) IS NOT NULL")
+            .bind(table)
+            .fetch_one(&pool)
+            .await?;
+        if !exists.0 {
+            println!("Table {} does not exist, skipping", table);
+            continue;
+        }
+
+        let rows: (i64,) = sqlx::query_as(&format!("SELECT COUNT(*) FROM {}", table))
+            .fetch_one(&pool)
+            .await?;
+        sqlx::query(&format!("DROP TABLE IF EXISTS {}", table))
+            .execute(&pool)
+            .await?;
+        println!("Dropped {} table ({} rows)", table, rows.0);
+    }
+
+    println!("✅ Teardown complete");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::postgres::PgConnectOptions;
+    use testcontainers_modules::{
+        postgres::Postgres as PostgresImage,
+        testcontainers::{runners::AsyncRunner, ImageExt},
+    };
+
+    /// Needs Docker; run with `cargo test -- --ignored`
+    #[tokio::test]
+    #[ignore]
+    async fn create_schema_inserts_funded_accounts() {
+        let container = PostgresImage::default()
+            .with_tag("16-alpine")
+            .start()
+            .await
+            .unwrap();
+        let options = PgConnectOptions::new()
+            .host(&container.get_host().await.unwrap().to_string())
+            .port(container.get_host_port_ipv4(5432).await.unwrap())
+            .username("postgres")
+            .password("postgres")
+            .database("postgres");
+        let pool = db::connect(options, &db::PoolConfig::default()).await.unwrap();
+
+        // More than one insert batch, and run twice to check existing rows
+        // are replaced rather than duplicated
+        create_schema(&pool, 2_500, false).await.unwrap();
+        create_schema(&pool, 2_500, false).await.unwrap();
+        assert!(create_schema(&pool, 2_500, true).await.is_err());
+
+        let (count, min_id, max_id): (i64, i32, i32) =
+            sqlx::query_as("SELECT COUNT(*), MIN(id), MAX(id) FROM accounts")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!((count, min_id, max_id), (2_500, 1, 2_500));
+
+        let unfunded: (i64,) = sqlx::query_as(
+            "SELECT COUNT(*) FROM accounts \
+             WHERE balance <> 100 OR exact_balance IS DISTINCT FROM 100",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(unfunded.0, 0);
+
+        let transactions: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM transactions")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(transactions.0, 0);
+    }
+}
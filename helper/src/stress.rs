@@ -1,25 +1,2918 @@
-use crate::lambda::{self, tpcb, ClientPool};
-use anyhow::Result;
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use std::collections::HashMap;
-use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
-use std::sync::Arc;
+use crate::aimd::AimdController;
+use crate::bounded::{BoundedQueue, WhenFull};
+use crate::cli::{RunArgs, StreamFormat, SustainedArgs, WorkloadArgs};
+use crate::cloudwatch;
+use crate::coordinator;
+use crate::failure::Failure;
+use crate::lambda::{self, balance_query, greeting, tpcb, ClientPool, LabelledLatency, Traffic};
+use crate::metadata::RunMetadata;
+use crate::progress;
+use crate::replay::{RecordedTransfer, Recording};
+use crate::results::{self, ResultsDir};
+use crate::summary::StressSummary;
+use anyhow::{Context, Result};
+use hdrhistogram::Histogram;
+use indicatif::{ProgressBar, ProgressStyle};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
 use std::time::{Duration, Instant};
-use tokio::task::JoinSet;
+use tokio::task::{JoinError, JoinSet};
+use uuid::Uuid;
 
+/// Shape of the traffic a stress run generates
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Workload {
+    /// TPC-B style transfers between two random accounts
+    Transfer,
+    /// Read-only balance lookups of a random account
+    Read,
+    /// Transfers carrying idempotency keys, a fraction of which resend an
+    /// earlier transfer unchanged to check the Lambda deduplicates them
+    Duplicate,
+    /// Transfers the Lambda should refuse: a payer or payee outside the
+    /// accounts table, or an account paying itself
+    Negative,
+    /// Transfers back and forth within a few hot pairs of accounts, half in
+    /// each direction, to maximise write-write conflicts
+    Bidirectional,
+    /// Money passed along a chain of `--hops` transfers, A->B->C->D, each
+    /// sent only once the previous one succeeded
+    Chain,
+    /// Calls to the greeting endpoint, which only checks it can reach DSQL:
+    /// the cost of the Lambda and its connection without a transaction
+    Greeting,
+    /// Read-your-writes: each transfer followed, once it succeeds, by a
+    /// read of the payer's balance, counting any read that doesn't show
+    /// the transfer as a consistency violation
+    Ryow,
+}
+
+/// A single request to send to the Lambda
+#[derive(Clone, Debug)]
+enum Op {
+    Transfer {
+        payer_id: u32,
+        payee_id: u32,
+        amount: tpcb::Amount,
+        idempotency_key: Option<Uuid>,
+        /// Whether this resends an earlier transfer's idempotency key
+        duplicate: bool,
+        /// Whether this is deliberately invalid and should be refused
+        invalid: bool,
+    },
+    Read {
+        account_id: u32,
+    },
+    /// Transfers sent one after another, each paying on what the previous
+    /// one paid in, stopping at the first that fails
+    Chain {
+        hops: Vec<Op>,
+    },
+    /// A call to the greeting endpoint, which writes nothing
+    Greeting,
+    /// A transfer, then a read of the payer's balance to check it shows the
+    /// transfer
+    ReadYourWrite {
+        payer_id: u32,
+        payee_id: u32,
+        amount: tpcb::Amount,
+    },
+}
+
+impl Op {
+    /// Whether the op leaves the database untouched
+    fn is_read(&self) -> bool {
+        matches!(self, Op::Read { .. } | Op::Greeting)
+    }
+
+    /// Whether the op can share an invocation with others in a batch
+    fn is_batchable(&self) -> bool {
+        matches!(self, Op::Transfer { .. })
+    }
+
+    /// How many transfers the op makes if it succeeds
+    fn transfers(&self) -> usize {
+        match self {
+            Op::Transfer { .. } | Op::ReadYourWrite { .. } => 1,
+            Op::Read { .. } | Op::Greeting => 0,
+            Op::Chain { hops } => hops.len(),
+        }
+    }
+
+    fn is_duplicate(&self) -> bool {
+        matches!(self, Op::Transfer { duplicate: true, .. })
+    }
+
+    fn is_invalid(&self) -> bool {
+        matches!(self, Op::Transfer { invalid: true, .. })
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Op::Transfer {
+                payer_id,
+                payee_id,
+                amount,
+                duplicate,
+                invalid,
+                ..
+            } => format!(
+                "transfer {} -> {} ({}){}{}",
+                payer_id,
+                payee_id,
+                amount,
+                if *duplicate { " resent" } else { "" },
+                if *invalid { " invalid" } else { "" }
+            ),
+            Op::Read { account_id } => format!("read {}", account_id),
+            Op::Greeting => "greeting".to_string(),
+            Op::ReadYourWrite {
+                payer_id,
+                payee_id,
+                amount,
+            } => format!("transfer {} -> {} ({}), read back", payer_id, payee_id, amount),
+            Op::Chain { hops } => {
+                let mut accounts = Vec::with_capacity(hops.len() + 1);
+                let mut amount = tpcb::Amount::Whole(0);
+                for hop in hops {
+                    if let Op::Transfer {
+                        payer_id,
+                        payee_id,
+                        amount: hop_amount,
+                        ..
+                    } = *hop
+                    {
+                        if accounts.is_empty() {
+                            accounts.push(payer_id.to_string());
+                        }
+                        accounts.push(payee_id.to_string());
+                        amount = hop_amount;
+                    }
+                }
+                format!("chain {} ({})", accounts.join(" -> "), amount)
+            }
+        }
+    }
+}
+
+/// The parts of a Lambda response the aggregators care about, whatever the
+/// request type
+struct Reply {
+    duration: Option<u64>,
+    retries: Option<u32>,
+    balance: Option<u32>,
+    error: Option<String>,
+    error_code: Option<String>,
+    /// The Lambda recognised the idempotency key and skipped the transfer
+    deduplicated: bool,
+    /// The Lambda stopped retrying because the request's deadline passed
+    deadline_exceeded: bool,
+    /// How the read after a read-your-writes transfer compared
+    read_back: Option<ReadBack>,
+}
+
+/// What the read after a `--workload ryow` transfer saw
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ReadBack {
+    /// The balance the transfer left
+    Consistent,
+    /// Some other balance, though nothing else touched the payer meanwhile
+    Stale,
+    /// Another transfer touched the payer in between, or a reply lacked a
+    /// balance, so the read can't be judged
+    Unchecked,
+}
+
+impl Reply {
+    fn of_transfer(response: tpcb::Response) -> Self {
+        Reply {
+            duration: response.duration,
+            retries: response.retries,
+            balance: response.balance,
+            error: response.error,
+            error_code: response.error_code,
+            deduplicated: response.deduplicated.unwrap_or(false),
+            deadline_exceeded: response.deadline_exceeded.unwrap_or(false),
+            read_back: None,
+        }
+    }
+
+    fn is_occ_error(&self) -> bool {
+        self.error_code.as_deref() == Some("40001")
+    }
+}
+
+/// Whose fault a failed request was
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum ErrorClass {
+    /// The request itself was rejected: bad input, a missing account, an
+    /// overdrawn balance
+    Client,
+    /// The Lambda or database couldn't serve a valid request: throttling,
+    /// dispatch failures, timeouts, exhausted OCC retries
+    Server,
+}
+
+impl ErrorClass {
+    /// Classify a reply's error by its Postgres SQLSTATE class. Errors the
+    /// Lambda raises itself carry no code and are about the request.
+    fn of_reply(reply: &Reply) -> Self {
+        // A deadline runs out because serving took too long, not because of
+        // anything wrong with the request
+        if reply.deadline_exceeded {
+            return ErrorClass::Server;
+        }
+        match reply.error_code.as_deref().map(|code| code.get(..2).unwrap_or(code)) {
+            // Data exceptions, constraint violations, syntax or access rule
+            // violations, and errors raised by PL/pgSQL
+            None | Some("22" | "23" | "42" | "P0") => ErrorClass::Client,
+            Some(_) => ErrorClass::Server,
+        }
+    }
+
+    /// Classify a function error by what the handler threw: the chapter
+    /// Lambdas throw for a missing account or an overdrawn balance, and
+    /// validation failures say so. The runtime's own errors, and anything
+    /// else, are the Lambda's.
+    fn of_function_error(thrown: &lambda::FunctionError) -> Self {
+        let runtime = ["Runtime.", "Sandbox."];
+        if runtime.iter().any(|prefix| thrown.error_type.starts_with(prefix)) {
+            return ErrorClass::Server;
+        }
+        let message = thrown.error_message.to_ascii_lowercase();
+        let about_request = ["not found", "insufficient balance", "invalid", "validation"];
+        if thrown.error_type.contains("Validation")
+            || about_request.iter().any(|phrase| message.contains(phrase))
+        {
+            ErrorClass::Client
+        } else {
+            ErrorClass::Server
+        }
+    }
+}
+
+/// A finished request: what was sent, and what came back
+struct Completion {
+    op: Op,
+    /// Rate-schedule phase the request was sent in
+    phase: usize,
+    result: Result<Reply>,
+    /// Times the request was resent after failing to dispatch
+    dispatch_retries: u32,
+    /// Under the open model, milliseconds from when the request should have
+    /// been sent until its reply arrived
+    corrected_latency: Option<u64>,
+    /// The hop a chain stopped at, counting from 1, if it failed
+    broken_at: Option<usize>,
+}
+
+impl Completion {
+    /// The latency to report: corrected for coordinated omission when
+    /// available, otherwise what the Lambda measured
+    fn latency(&self) -> Option<u64> {
+        let reply = self.result.as_ref().ok()?;
+        self.corrected_latency.or(reply.duration)
+    }
+}
+
+/// How transfer payers and payees are chosen
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Pairing {
+    /// Two distinct random accounts
+    Random,
+    /// Account i pays account i+1 (wrapping), in order, so each pass over
+    /// the accounts debits and credits every account exactly once
+    Sequential,
+}
+
+/// Where a transfer's payee is relative to its payer
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum PayeeStrategy {
+    /// Any other account (in the payer's shard, if sharded)
+    Random,
+    /// The next account up, wrapping: payer i pays i+1
+    Adjacent,
+    /// The account `--payee-offset` up, wrapping
+    FixedOffset,
+}
+
+/// How many random payees are drawn before settling for the account after
+/// the payer, so a pick that keeps landing on the payer can't stall the
+/// generator
+const MAX_PAYEE_REPICKS: usize = 100;
+
+/// Picks the accounts involved in each request
+struct AccountSampler {
+    num_accounts: u32,
+    pairing: Pairing,
+    /// How far above the payer its payee is, unless picked at random
+    payee_offset: Option<u32>,
+    /// Next account in sequential mode
+    cursor: u32,
+    /// Contiguous slices of the accounts that random picks take turns
+    /// between; one shard covering every account when unsharded
+    shards: u32,
+    cross_shard_ratio: f64,
+    /// Shard the current request is drawn from
+    shard: u32,
+}
+
+impl AccountSampler {
+    fn new(num_accounts: u32, workload: &WorkloadArgs) -> Self {
+        // Every shard needs two accounts for a transfer to stay within it
+        let shards = workload.shards.min(num_accounts / 2).max(1);
+        let payee_offset = match workload.payee_strategy {
+            PayeeStrategy::Random => None,
+            PayeeStrategy::Adjacent => Some(1),
+            PayeeStrategy::FixedOffset => Some(workload.payee_offset),
+        };
+        Self {
+            num_accounts,
+            pairing: workload.pairing,
+            payee_offset,
+            cursor: 1,
+            shards,
+            cross_shard_ratio: workload.cross_shard_ratio,
+            shard: 0,
+        }
+    }
+
+    /// The account ids in `shard`
+    fn shard_range(&self, shard: u32) -> std::ops::RangeInclusive<u32> {
+        let n = self.num_accounts as u64;
+        let start = shard as u64 * n / self.shards as u64 + 1;
+        let end = (shard as u64 + 1) * n / self.shards as u64;
+        start as u32..=end as u32
+    }
+
+    /// Move on to the next shard, round robin, for the next request
+    fn next_shard(&mut self) {
+        self.shard = (self.shard + 1) % self.shards;
+    }
+
+    fn account(&mut self, rng: &mut StdRng) -> u32 {
+        match self.pairing {
+            Pairing::Random => rng.gen_range(self.shard_range(self.shard)),
+            Pairing::Sequential => {
+                let id = self.cursor;
+                self.cursor = self.cursor % self.num_accounts + 1;
+                id
+            }
+        }
+    }
+
+    fn pair(&mut self, rng: &mut StdRng) -> (u32, u32) {
+        let payer_id = self.account(rng);
+        (payer_id, self.payee(payer_id, rng))
+    }
+
+    /// An account for `payer_id` to pay, other than itself
+    fn payee(&mut self, payer_id: u32, rng: &mut StdRng) -> u32 {
+        if let Some(offset) = self.payee_offset {
+            let payee_id = (payer_id - 1 + offset % self.num_accounts) % self.num_accounts + 1;
+            // An offset that's a multiple of the account count would land
+            // back on the payer, so take the next account instead
+            return if payee_id == payer_id {
+                payer_id % self.num_accounts + 1
+            } else {
+                payee_id
+            };
+        }
+        if self.pairing == Pairing::Sequential {
+            return payer_id % self.num_accounts + 1;
+        }
+        let payee_shard = if self.shards > 1 && rng.gen_bool(self.cross_shard_ratio) {
+            (self.shard + rng.gen_range(1..self.shards)) % self.shards
+        } else {
+            self.shard
+        };
+        let range = self.shard_range(payee_shard);
+        for _ in 0..MAX_PAYEE_REPICKS {
+            let payee_id = rng.gen_range(range.clone());
+            if payee_id != payer_id {
+                return payee_id;
+            }
+        }
+        // Only a very lopsided pick gets here; the shard always has another
+        // account, as it holds at least two
+        if payer_id == *range.end() {
+            *range.start()
+        } else {
+            payer_id + 1
+        }
+    }
+}
+
+/// How transfer amounts are drawn
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum AmountDist {
+    /// Always `--amount`
+    Fixed,
+    /// Evenly from `--amount` up to `--max-amount`
+    Uniform,
+    /// Exponentially, averaging `--amount`: mostly small, some several
+    /// times larger
+    Exponential,
+    /// Pareto from `--amount` up, heavy-tailed like real payments: most
+    /// near the minimum, a few very large
+    Pareto,
+}
+
+/// Pareto shape giving the 80/20 rule: a fifth of the transfers move four
+/// fifths of the money
+const PARETO_SHAPE: f64 = 1.16;
+
+/// Picks the amount of each transfer, and keeps track of those picked
+struct AmountSampler {
+    dist: AmountDist,
+    amount: u32,
+    /// Upper bound on every distribution, if given
+    max_amount: Option<u32>,
+    picked: Histogram<u64>,
+}
+
+impl AmountSampler {
+    fn new(workload: &WorkloadArgs) -> Self {
+        Self {
+            dist: workload.amount_dist(),
+            amount: workload.amount,
+            max_amount: workload.max_amount,
+            picked: Histogram::new(3).unwrap(),
+        }
+    }
+
+    fn amount(&mut self, rng: &mut StdRng) -> u32 {
+        let amount = self.amount.max(1) as f64;
+        // Draw from (0, 1] so neither tail can reach infinity
+        let u = 1.0 - rng.r#gen::<f64>();
+        let picked = match self.dist {
+            AmountDist::Fixed => self.amount,
+            AmountDist::Uniform => match self.max_amount {
+                Some(max) if max > self.amount => rng.gen_range(self.amount..=max),
+                _ => self.amount,
+            },
+            AmountDist::Exponential => (-amount * u.ln()).round().max(1.0) as u32,
+            AmountDist::Pareto => (amount / u.powf(1.0 / PARETO_SHAPE)).round() as u32,
+        };
+        let picked = match self.max_amount {
+            Some(max) => picked.min(max),
+            None => picked,
+        };
+        self.picked.record(picked as u64).ok();
+        picked
+    }
+}
+
+/// Generates the requests of a run from a single seeded RNG.
+///
+/// Requests are generated by the spawning loop rather than inside the tasks,
+/// so one RNG is enough for the sequence to be reproducible at any
+/// parallelism: completion order never feeds back into what gets sent.
+pub struct RequestGenerator<'a> {
+    workload: &'a WorkloadArgs,
+    pub seed: u64,
+    rng: StdRng,
+    accounts: AccountSampler,
+    amounts: AmountSampler,
+    /// Approximate balance of every account for `--no-overdraw`, indexed by
+    /// account id.
+    ///
+    /// This is updated optimistically as transfers are *generated*, not when
+    /// they commit, and never re-read from the database. Failed transfers are
+    /// not rolled back and other clients' writes are invisible, so the view
+    /// drifts from reality over a run; it keeps the workload mostly within
+    /// balance rather than guaranteeing it. Held in hundredths, so
+    /// `--amount-decimal` amounts count exactly.
+    balances: Option<Vec<i64>>,
+    /// Transfers whose first-choice payer looked short of funds
+    repicked: u64,
+    /// Transfers sent anyway because no funded payer was found
+    unfunded: u64,
+    /// Recently sent keyed transfers, for `--workload duplicate` to resend
+    recent: VecDeque<(u32, u32, tpcb::Amount, Uuid)>,
+    /// Bidirectional transfers generated so far, to alternate directions
+    crossed: u64,
+    /// A read or chain that ended the last batch, to send on its own next
+    pending: Option<Op>,
+    /// Recorded transfers still to send, when replaying
+    replay: Option<VecDeque<RecordedTransfer>>,
+}
+
+/// How many pairs `--no-overdraw` tries before giving up on finding a payer
+/// with enough balance
+const MAX_PAYER_REPICKS: usize = 100;
+
+/// How far back `--workload duplicate` reaches for a transfer to resend
+const RECENT_TRANSFERS: usize = 1000;
+
+impl<'a> RequestGenerator<'a> {
+    pub fn new(workload: &'a WorkloadArgs, num_accounts: u32) -> Self {
+        let seed = workload.seed.unwrap_or_else(rand::random);
+        let balances = workload
+            .no_overdraw
+            .then(|| vec![workload.initial_balance * 100; num_accounts as usize + 1]);
+        Self {
+            workload,
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+            accounts: AccountSampler::new(num_accounts, workload),
+            amounts: AmountSampler::new(workload),
+            balances,
+            repicked: 0,
+            unfunded: 0,
+            recent: VecDeque::new(),
+            crossed: 0,
+            pending: None,
+            replay: None,
+        }
+    }
+
+    /// Send the recorded transfers, in order, instead of generating any
+    pub fn replaying(workload: &'a WorkloadArgs, recording: Recording) -> Self {
+        let num_accounts = recording.max_account();
+        Self {
+            replay: Some(recording.transfers.into()),
+            ..Self::new(workload, num_accounts)
+        }
+    }
+
+    /// The workload as shown before a run
+    pub fn describe(&self) -> String {
+        match &self.replay {
+            Some(_) if self.next_due().is_some() => "replay, at the recorded times".to_string(),
+            Some(_) => "replay, in recorded order".to_string(),
+            None => self.workload.describe(),
+        }
+    }
+
+    /// How far into the run the next recorded transfer is due, when
+    /// replaying with timestamps
+    fn next_due(&self) -> Option<Duration> {
+        self.replay.as_ref()?.front()?.at
+    }
+
+    /// Pick a transfer pair, avoiding payers that would go negative when
+    /// `--no-overdraw` is set
+    fn funded_pair(&mut self, amount: tpcb::Amount) -> (u32, u32) {
+        let Some(balances) = self.balances.as_mut() else {
+            return self.accounts.pair(&mut self.rng);
+        };
+
+        let amount = amount.cents();
+        let mut pair = self.accounts.pair(&mut self.rng);
+        let mut attempts = 1;
+        while balances[pair.0 as usize] < amount {
+            if attempts == MAX_PAYER_REPICKS {
+                self.unfunded += 1;
+                break;
+            }
+            self.repicked += 1;
+            pair = self.accounts.pair(&mut self.rng);
+            attempts += 1;
+        }
+
+        balances[pair.0 as usize] -= amount;
+        balances[pair.1 as usize] += amount;
+        pair
+    }
+
+    /// Print the spread of transfer amounts when they varied, so the
+    /// output records what the workload actually sent
+    pub fn print_amount_stats(&self) {
+        let picked = &self.amounts.picked;
+        if self.amounts.dist == AmountDist::Fixed || picked.is_empty() {
+            return;
+        }
+        println!("Transfer Amounts ({:?}):", self.amounts.dist);
+        println!("  Min:                {}", picked.min());
+        println!("  p50:                {}", picked.value_at_quantile(0.5));
+        println!("  p99:                {}", picked.value_at_quantile(0.99));
+        println!("  Max:                {}", picked.max());
+        println!();
+    }
+
+    pub fn print_overdraw_stats(&self) {
+        if self.balances.is_none() {
+            return;
+        }
+        println!("No-Overdraw Selection:");
+        println!("  Payers re-picked:   {}", self.repicked);
+        println!("  Sent unfunded:      {}", self.unfunded);
+        println!();
+    }
+
+    /// The requests for the next invocation: up to `max` transfers, or a
+    /// single read or chain, as those aren't batched
+    fn next_batch(&mut self, max: usize) -> Vec<Op> {
+        if let Some(op) = self.pending.take() {
+            return vec![op];
+        }
+        let mut ops = Vec::with_capacity(max);
+        while ops.len() < max {
+            let op = self.next_op();
+            if !op.is_batchable() && max > 1 {
+                if ops.is_empty() {
+                    return vec![op];
+                }
+                self.pending = Some(op);
+                break;
+            }
+            ops.push(op);
+        }
+        ops
+    }
+
+    fn next_op(&mut self) -> Op {
+        if let Some(replay) = &mut self.replay
+            && let Some(transfer) = replay.pop_front()
+        {
+            return Op::Transfer {
+                payer_id: transfer.payer_id,
+                payee_id: transfer.payee_id,
+                amount: tpcb::Amount::Whole(transfer.amount),
+                idempotency_key: None,
+                duplicate: false,
+                invalid: false,
+            };
+        }
+        self.accounts.next_shard();
+        let kind = match self.workload.read_ratio {
+            Some(ratio) if self.rng.r#gen::<f64>() < ratio => Workload::Read,
+            Some(_) => Workload::Transfer,
+            None => self.workload.workload,
+        };
+        match kind {
+            Workload::Transfer => {
+                let amount = self.next_amount();
+                let (payer_id, payee_id) = self.funded_pair(amount);
+                Op::Transfer {
+                    payer_id,
+                    payee_id,
+                    amount,
+                    idempotency_key: None,
+                    duplicate: false,
+                    invalid: false,
+                }
+            }
+            Workload::Read => Op::Read {
+                account_id: self.accounts.account(&mut self.rng),
+            },
+            Workload::Duplicate => self.next_keyed_transfer(),
+            Workload::Negative => self.next_invalid_transfer(),
+            Workload::Bidirectional => self.next_crossed_transfer(),
+            Workload::Chain => self.next_chain(),
+            Workload::Greeting => Op::Greeting,
+            Workload::Ryow => {
+                let amount = self.next_amount();
+                let (payer_id, payee_id) = self.funded_pair(amount);
+                Op::ReadYourWrite {
+                    payer_id,
+                    payee_id,
+                    amount,
+                }
+            }
+        }
+    }
+
+    /// `--hops` transfers of one amount, each payee paying it on to the
+    /// next account. Only the first payer needs the funds, so only it is
+    /// checked for `--no-overdraw`.
+    fn next_chain(&mut self) -> Op {
+        let amount = self.next_amount();
+        let (mut payer_id, mut payee_id) = self.funded_pair(amount);
+        let mut hops = Vec::with_capacity(self.workload.hops as usize);
+        loop {
+            hops.push(Op::Transfer {
+                payer_id,
+                payee_id,
+                amount,
+                idempotency_key: None,
+                duplicate: false,
+                invalid: false,
+            });
+            if hops.len() == self.workload.hops as usize {
+                break;
+            }
+            payer_id = payee_id;
+            payee_id = self.accounts.payee(payer_id, &mut self.rng);
+            if let Some(balances) = self.balances.as_mut() {
+                balances[payer_id as usize] -= amount.cents();
+                balances[payee_id as usize] += amount.cents();
+            }
+        }
+        Op::Chain { hops }
+    }
+
+    /// A transfer within one of the `--hot-pairs` pairs (1 and 2, 3 and 4,
+    /// ...), alternating direction so each pair sees as many A->B as B->A.
+    ///
+    /// The alternation means balances stay roughly level, so `--no-overdraw`
+    /// doesn't apply.
+    fn next_crossed_transfer(&mut self) -> Op {
+        let amount = self.next_amount();
+        let pairs = self
+            .workload
+            .hot_pairs
+            .min(self.accounts.num_accounts / 2)
+            .max(1);
+        let a = 2 * self.rng.gen_range(0..pairs) + 1;
+        let b = a + 1;
+        let (payer_id, payee_id) = if self.crossed.is_multiple_of(2) { (a, b) } else { (b, a) };
+        self.crossed += 1;
+        Op::Transfer {
+            payer_id,
+            payee_id,
+            amount,
+            idempotency_key: None,
+            duplicate: false,
+            invalid: false,
+        }
+    }
+
+    /// A transfer that should fail cleanly: from the nonexistent account 0,
+    /// to an account past the end of the table, or from an account to itself
+    fn next_invalid_transfer(&mut self) -> Op {
+        let amount = self.next_amount();
+        let num_accounts = self.accounts.num_accounts;
+        let valid = self.accounts.account(&mut self.rng);
+        let (payer_id, payee_id) = match self.rng.gen_range(0..3) {
+            0 => (0, valid),
+            1 => (valid, num_accounts + 1 + self.rng.gen_range(0..num_accounts.max(1))),
+            _ => (valid, valid),
+        };
+        Op::Transfer {
+            payer_id,
+            payee_id,
+            amount,
+            idempotency_key: None,
+            duplicate: false,
+            invalid: true,
+        }
+    }
+
+    /// A transfer with a fresh idempotency key, or with probability
+    /// `--duplicate-ratio` a verbatim resend of a recent one
+    fn next_keyed_transfer(&mut self) -> Op {
+        if !self.recent.is_empty() && self.rng.r#gen::<f64>() < self.workload.duplicate_ratio {
+            let i = self.rng.gen_range(0..self.recent.len());
+            let (payer_id, payee_id, amount, key) = self.recent[i];
+            return Op::Transfer {
+                payer_id,
+                payee_id,
+                amount,
+                idempotency_key: Some(key),
+                duplicate: true,
+                invalid: false,
+            };
+        }
+
+        let amount = self.next_amount();
+        let (payer_id, payee_id) = self.funded_pair(amount);
+        // Drawn from the seeded RNG so a replayed run resends the same keys
+        let key = uuid::Builder::from_random_bytes(self.rng.r#gen()).into_uuid();
+        if self.recent.len() == RECENT_TRANSFERS {
+            self.recent.pop_front();
+        }
+        self.recent.push_back((payer_id, payee_id, amount, key));
+        Op::Transfer {
+            payer_id,
+            payee_id,
+            amount,
+            idempotency_key: Some(key),
+            duplicate: false,
+            invalid: false,
+        }
+    }
+
+    fn next_amount(&mut self) -> tpcb::Amount {
+        let units = self.amounts.amount(&mut self.rng);
+        if !self.workload.amount_decimal {
+            return tpcb::Amount::Whole(units);
+        }
+        // Anywhere from just over a unit less up to what was drawn
+        let cents = units.max(1).saturating_mul(100);
+        tpcb::Amount::Cents(cents - self.rng.gen_range(0..100))
+    }
+}
+
+/// Send `ops` in one invocation: a lone request as is, several transfers
+/// as a `--batch-size` batch. Returns a reply per op, in order.
+async fn execute(client_pool: &ClientPool, ops: &[Op]) -> Result<Vec<Reply>> {
+    if let [op] = ops {
+        return Ok(vec![execute_one(client_pool, op).await?]);
+    }
+    let transfers = ops
+        .iter()
+        .map(|op| match *op {
+            Op::Transfer {
+                payer_id,
+                payee_id,
+                amount,
+                idempotency_key,
+                ..
+            } => Ok(tpcb::Request {
+                payer_id,
+                payee_id,
+                amount,
+                idempotency_key,
+                deadline_ms: client_pool.deadline_ms(),
+            }),
+            Op::Read { .. } | Op::Chain { .. } | Op::Greeting | Op::ReadYourWrite { .. } => {
+                Err(anyhow::anyhow!(
+                    "only transfers can be batched, not {}",
+                    op.describe()
+                ))
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let response: tpcb::BatchResponse =
+        lambda::invoke(client_pool.get(), tpcb::BatchRequest { transfers }).await?;
+    anyhow::ensure!(
+        response.results.len() == ops.len(),
+        "batch of {} transfers got {} results",
+        ops.len(),
+        response.results.len()
+    );
+    Ok(response.results.into_iter().map(Reply::of_transfer).collect())
+}
+
+async fn execute_one(client_pool: &ClientPool, op: &Op) -> Result<Reply> {
+    match *op {
+        Op::Transfer {
+            payer_id,
+            payee_id,
+            amount,
+            idempotency_key,
+            ..
+        } => {
+            let response: tpcb::Response = lambda::invoke(
+                client_pool.get(),
+                tpcb::Request {
+                    payer_id,
+                    payee_id,
+                    amount,
+                    idempotency_key,
+                    deadline_ms: client_pool.deadline_ms(),
+                },
+            )
+            .await?;
+            Ok(Reply::of_transfer(response))
+        }
+        Op::Read { account_id } => {
+            let response: balance_query::Response =
+                lambda::invoke(client_pool.get(), balance_query::Request { account_id }).await?;
+            // A reply with neither a balance nor an error means the Lambda
+            // doesn't understand balance lookups
+            let error = match (response.balance, response.error) {
+                (None, None) => Some("balance lookup returned no balance".to_string()),
+                (_, error) => error,
+            };
+            // Reads never conflict, so they carry no OCC retry count
+            Ok(Reply {
+                duration: response.duration,
+                retries: None,
+                balance: response.balance,
+                error,
+                error_code: response.error_code,
+                deduplicated: false,
+                deadline_exceeded: false,
+                read_back: None,
+            })
+        }
+        Op::Greeting => {
+            let start = Instant::now();
+            let response: greeting::Response = lambda::invoke(
+                client_pool.get(),
+                greeting::Request {
+                    name: "stress".to_string(),
+                },
+            )
+            .await?;
+            // The greeting reports no duration of its own, so time it here
+            let duration = start.elapsed().as_millis() as u64;
+            let error = (!response.greeting.contains("connected to DSQL"))
+                .then(|| format!("unexpected greeting: {:?}", response.greeting));
+            Ok(Reply {
+                duration: Some(duration),
+                retries: None,
+                balance: None,
+                error,
+                error_code: None,
+                deduplicated: false,
+                deadline_exceeded: false,
+                read_back: None,
+            })
+        }
+        Op::Chain { .. } => anyhow::bail!("chains are sent a hop at a time"),
+        Op::ReadYourWrite { .. } => anyhow::bail!("read-your-writes are sent a step at a time"),
+    }
+}
+
+/// Send a few real transfers before a run and check the replies have the
+/// shape `tpcb::Response` expects, so a Lambda whose contract has drifted
+/// fails in seconds rather than a million invocations later. Moves 1 from
+/// account 1 to 2 and back, as 1.00 with `--amount-decimal` so the exact
+/// balance the run will use is probed too, so balances end where they
+/// started, then pays from account 0, which never exists, to see what a
+/// refusal looks like
+pub async fn probe(client_pool: &ClientPool, workload: &WorkloadArgs) -> Result<()> {
+    let hint = "pass --no-probe to run anyway";
+    let amount = if workload.amount_decimal {
+        tpcb::Amount::Cents(100)
+    } else {
+        tpcb::Amount::Whole(1)
+    };
+    let send = |payer_id, payee_id| {
+        lambda::invoke::<_, tpcb::Response>(
+            client_pool.get(),
+            tpcb::Request {
+                payer_id,
+                payee_id,
+                amount,
+                idempotency_key: None,
+                deadline_ms: client_pool.deadline_ms(),
+            },
+        )
+    };
+
+    let mut succeeded = 0;
+    let mut first_error = None;
+    for (payer_id, payee_id) in [(1, 2), (2, 1)] {
+        let response = send(payer_id, payee_id)
+            .await
+            .with_context(|| format!("Probe transfer failed; {}", hint))?;
+        match (&response.error, response.balance) {
+            (None, Some(_)) => succeeded += 1,
+            (None, None) => anyhow::bail!(
+                "Probe transfer from account {} succeeded but its reply had no `balance`; {}",
+                payer_id,
+                hint
+            ),
+            (Some(err), _) => {
+                first_error.get_or_insert_with(|| err.clone());
+            }
+        }
+    }
+    if succeeded == 0 {
+        anyhow::bail!(
+            "No probe transfer succeeded ({}); {}",
+            first_error.unwrap_or_default(),
+            hint
+        );
+    }
+
+    // The chapter Lambdas only send `errorCode` for database errors, so a
+    // refusal needs an `error` but may lack a code
+    let refusal = send(0, 1)
+        .await
+        .with_context(|| format!("Probe transfer failed; {}", hint))?;
+    if refusal.error.is_none() {
+        anyhow::bail!(
+            "Probe transfer from missing account 0 was not refused with an `error`; {}",
+            hint
+        );
+    }
+    println!(
+        "Probe: {} of 2 transfers succeeded, missing account refused{}",
+        succeeded,
+        if refusal.error_code.is_none() {
+            " (without an error_code)"
+        } else {
+            ""
+        }
+    );
+    Ok(())
+}
+
+/// Backoff before the first `--dispatch-retries` resend; doubles with each
+/// resend, and each wait is drawn uniformly up to it
+const INITIAL_DISPATCH_BACKOFF: Duration = Duration::from_millis(50);
+
+/// Execute `ops`, resending them up to `max_retries` times while they fail
+/// without a reply. Returns a completion per op, each charged with the
+/// resends it took. Read-your-writes transfers are noted in `ryow`, the
+/// run's own.
+async fn execute_with_retries(
+    client_pool: &ClientPool,
+    ryow: &RyowActivity,
+    ops: Vec<Op>,
+    phase: usize,
+    max_retries: u32,
+) -> Vec<Completion> {
+    if let [Op::ReadYourWrite {
+        payer_id,
+        payee_id,
+        amount,
+    }] = *ops.as_slice()
+    {
+        let (result, dispatch_retries, broken_at) =
+            execute_read_your_write(client_pool, ryow, payer_id, payee_id, amount, max_retries)
+                .await;
+        return vec![Completion {
+            op: ops.into_iter().next().unwrap(),
+            phase,
+            result,
+            dispatch_retries,
+            corrected_latency: None,
+            broken_at,
+        }];
+    }
+    if let [Op::Chain { hops }] = ops.as_slice() {
+        let (result, dispatch_retries, broken_at) =
+            execute_chain(client_pool, hops, max_retries).await;
+        return vec![Completion {
+            op: ops.into_iter().next().unwrap(),
+            phase,
+            result,
+            dispatch_retries,
+            corrected_latency: None,
+            broken_at,
+        }];
+    }
+    let (result, dispatch_retries) = dispatch_with_retries(client_pool, &ops, max_retries).await;
+    let results: Vec<Result<Reply>> = match result {
+        Ok(replies) => replies.into_iter().map(Ok).collect(),
+        Err(err) if ops.len() == 1 => vec![Err(err)],
+        // Every transfer in a failed batch failed the same way
+        Err(err) => ops.iter().map(|_| Err(copy_error(&err))).collect(),
+    };
+    ops.into_iter()
+        .zip(results)
+        .map(|(op, result)| Completion {
+            op,
+            phase,
+            result,
+            dispatch_retries,
+            corrected_latency: None,
+            broken_at: None,
+        })
+        .collect()
+}
+
+/// Send a chain's hops in order, each once the last has succeeded, and
+/// answer for the chain as a whole: its duration is end to end, as the
+/// client saw it, and it fails with the first hop that does. Also returns
+/// the resends it took and the hop it broke at.
+async fn execute_chain(
+    client_pool: &ClientPool,
+    hops: &[Op],
+    max_retries: u32,
+) -> (Result<Reply>, u32, Option<usize>) {
+    let start = Instant::now();
+    let mut retries = 0;
+    let mut dispatch_retries = 0;
+    let mut failure = None;
+    for (i, hop) in hops.iter().enumerate() {
+        let (result, resends) =
+            dispatch_with_retries(client_pool, std::slice::from_ref(hop), max_retries).await;
+        dispatch_retries += resends;
+        match result.map(|mut replies| replies.remove(0)) {
+            Ok(reply) if reply.error.is_none() => retries += reply.retries.unwrap_or(0),
+            result => {
+                failure = Some((i + 1, result));
+                break;
+            }
+        }
+    }
+    let duration = start.elapsed().as_millis() as u64;
+    let (broken_at, result) = match failure {
+        Some((hop, Ok(reply))) => (
+            Some(hop),
+            Ok(Reply {
+                duration: Some(duration),
+                retries: Some(retries + reply.retries.unwrap_or(0)),
+                ..reply
+            }),
+        ),
+        Some((hop, Err(err))) => (Some(hop), Err(err)),
+        None => (
+            None,
+            Ok(Reply {
+                duration: Some(duration),
+                retries: Some(retries),
+                balance: None,
+                error: None,
+                error_code: None,
+                deduplicated: false,
+                deadline_exceeded: false,
+                read_back: None,
+            }),
+        ),
+    };
+    (result, dispatch_retries, broken_at)
+}
+
+/// Read-your-writes transfers started and finished per account, so a
+/// read-back can tell a stale read from one another transfer got to first
+#[derive(Default)]
+struct AccountActivity {
+    started: u64,
+    finished: u64,
+}
+
+/// The read-your-writes transfers of one run, by the accounts they touch.
+/// Clones share the counts.
+#[derive(Clone, Default)]
+struct RyowActivity {
+    accounts: Arc<Mutex<HashMap<u32, AccountActivity>>>,
+}
+
+impl RyowActivity {
+    /// Note a transfer touching `accounts` has started; it's over when the
+    /// guard drops
+    fn start(&self, accounts: [u32; 2]) -> RyowGuard<'_> {
+        let mut activity = self.accounts.lock().unwrap();
+        for account in accounts {
+            activity.entry(account).or_default().started += 1;
+        }
+        RyowGuard {
+            activity: self,
+            accounts,
+        }
+    }
+
+    /// How many transfers have started on `account`, and how many of them
+    /// are still going
+    fn snapshot(&self, account: u32) -> (u64, u64) {
+        let activity = self.accounts.lock().unwrap();
+        activity
+            .get(&account)
+            .map_or((0, 0), |a| (a.started, a.started - a.finished))
+    }
+}
+
+/// A read-your-writes transfer in progress. Dropping it, even with the
+/// task aborted mid-transfer, notes the transfer is over and forgets
+/// accounts nothing is touching any more.
+struct RyowGuard<'a> {
+    activity: &'a RyowActivity,
+    accounts: [u32; 2],
+}
+
+impl Drop for RyowGuard<'_> {
+    fn drop(&mut self) {
+        let mut activity = self.activity.accounts.lock().unwrap();
+        for account in self.accounts {
+            if let Some(a) = activity.get_mut(&account) {
+                a.finished += 1;
+                if a.finished == a.started {
+                    activity.remove(&account);
+                }
+            }
+        }
+    }
+}
+
+/// Send a transfer and, once it succeeds, read the payer's balance back,
+/// answering for both end to end like a chain: broken at step 1 if the
+/// transfer failed, 2 if the read did. The read is only judged if no
+/// other transfer touched the payer from the transfer's reply to the
+/// read's, as otherwise its balance would rightly differ.
+async fn execute_read_your_write(
+    client_pool: &ClientPool,
+    activity: &RyowActivity,
+    payer_id: u32,
+    payee_id: u32,
+    amount: tpcb::Amount,
+    max_retries: u32,
+) -> (Result<Reply>, u32, Option<usize>) {
+    let start = Instant::now();
+    let in_progress = activity.start([payer_id, payee_id]);
+    let transfer = Op::Transfer {
+        payer_id,
+        payee_id,
+        amount,
+        idempotency_key: None,
+        duplicate: false,
+        invalid: false,
+    };
+    let (result, mut dispatch_retries) =
+        dispatch_with_retries(client_pool, std::slice::from_ref(&transfer), max_retries).await;
+    let written = match result.map(|mut replies| replies.remove(0)) {
+        Ok(reply) if reply.error.is_none() => reply,
+        result => return (result, dispatch_retries, Some(1)),
+    };
+
+    let (started, active) = activity.snapshot(payer_id);
+    let read = Op::Read {
+        account_id: payer_id,
+    };
+    let (result, resends) =
+        dispatch_with_retries(client_pool, std::slice::from_ref(&read), max_retries).await;
+    dispatch_retries += resends;
+    let (started_after, _) = activity.snapshot(payer_id);
+    drop(in_progress);
+    let read = match result.map(|mut replies| replies.remove(0)) {
+        Ok(reply) if reply.error.is_none() => reply,
+        result => return (result, dispatch_retries, Some(2)),
+    };
+
+    let read_back = match (written.balance, read.balance) {
+        (Some(_), Some(_)) if active > 1 || started_after != started => ReadBack::Unchecked,
+        (Some(expected), Some(seen)) if seen == expected => ReadBack::Consistent,
+        (Some(_), Some(_)) => ReadBack::Stale,
+        _ => ReadBack::Unchecked,
+    };
+    if read_back == ReadBack::Stale {
+        tracing::warn!(
+            payer_id,
+            expected = written.balance,
+            seen = read.balance,
+            "read didn't show the transfer just made"
+        );
+    }
+    let reply = Reply {
+        duration: Some(start.elapsed().as_millis() as u64),
+        read_back: Some(read_back),
+        ..written
+    };
+    (Ok(reply), dispatch_retries, None)
+}
+
+/// An error like `err`, keeping what the aggregators look at
+fn copy_error(err: &anyhow::Error) -> anyhow::Error {
+    match err.downcast_ref::<lambda::InvokeError>() {
+        Some(invoke) => lambda::InvokeError {
+            request_id: invoke.request_id.clone(),
+            error: anyhow::anyhow!("{:#}", invoke.error),
+            retryable: invoke.retryable,
+            timed_out: invoke.timed_out,
+            dispatch_cause: invoke.dispatch_cause,
+            function_error: invoke.function_error.clone(),
+        }
+        .into(),
+        None => anyhow::anyhow!("{:#}", err),
+    }
+}
+
+async fn dispatch_with_retries(
+    client_pool: &ClientPool,
+    ops: &[Op],
+    max_retries: u32,
+) -> (Result<Vec<Reply>>, u32) {
+    let mut backoff = INITIAL_DISPATCH_BACKOFF;
+    let mut retries = 0;
+    loop {
+        let result = execute(client_pool, ops).await;
+        let retryable = match &result {
+            Err(err) => err
+                .downcast_ref::<lambda::InvokeError>()
+                .is_some_and(|err| err.retryable),
+            Ok(_) => false,
+        };
+        if !retryable || retries == max_retries {
+            return (result, retries);
+        }
+        retries += 1;
+        let jitter = rand::thread_rng().gen_range(Duration::ZERO..=backoff);
+        tokio::time::sleep(jitter).await;
+        backoff *= 2;
+    }
+}
+
+/// One fixed-rate step of a rate schedule
+#[derive(Deserialize, Clone, Debug)]
+pub struct Phase {
+    pub rate: u32,
+    pub duration_secs: u64,
+}
+
+/// How the target request rate of a sustained run evolves over time
+#[derive(Clone, Debug)]
+pub enum RateSchedule {
+    /// A constant rate until Ctrl-C
+    Constant(u32),
+    /// A sequence of phases, ending the run after the last one
+    Phases(Vec<Phase>),
+    /// A linear increase from `start` to `end` over `duration_secs`, ending
+    /// the run when the ramp completes
+    Ramp {
+        start: u32,
+        end: u32,
+        duration_secs: u64,
+    },
+    /// Hold `baseline`, jump to `spike` at `spike_at_secs` for
+    /// `spike_for_secs`, then return to `baseline` until Ctrl-C
+    Step {
+        baseline: u32,
+        spike: u32,
+        spike_at_secs: u64,
+        spike_for_secs: u64,
+    },
+}
+
+impl RateSchedule {
+    /// The phase index and target rate at `elapsed`, or `None` once the
+    /// schedule has finished
+    fn position(&self, elapsed: Duration) -> Option<(usize, u32)> {
+        match self {
+            RateSchedule::Constant(rate) => Some((0, *rate)),
+            RateSchedule::Phases(phases) => {
+                let mut phase_end = Duration::ZERO;
+                for (i, phase) in phases.iter().enumerate() {
+                    phase_end += Duration::from_secs(phase.duration_secs);
+                    if elapsed < phase_end {
+                        return Some((i, phase.rate));
+                    }
+                }
+                None
+            }
+            RateSchedule::Ramp {
+                start,
+                end,
+                duration_secs,
+            } => {
+                let progress = elapsed.as_secs_f64() / *duration_secs as f64;
+                if progress >= 1.0 {
+                    return None;
+                }
+                let rate = *start as f64 + (*end as f64 - *start as f64) * progress;
+                Some((0, rate.round() as u32))
+            }
+            RateSchedule::Step {
+                baseline,
+                spike,
+                spike_at_secs,
+                spike_for_secs,
+            } => {
+                let secs = elapsed.as_secs_f64();
+                let spike_at = *spike_at_secs as f64;
+                if secs < spike_at {
+                    Some((0, *baseline))
+                } else if secs < spike_at + *spike_for_secs as f64 {
+                    Some((1, *spike))
+                } else {
+                    Some((2, *baseline))
+                }
+            }
+        }
+    }
+
+    /// Whether the schedule is made of distinct phases worth reporting
+    /// separately
+    fn is_phased(&self) -> bool {
+        matches!(self, RateSchedule::Phases(_) | RateSchedule::Step { .. })
+    }
+
+    fn phase_label(&self, phase: usize) -> String {
+        match self {
+            RateSchedule::Step { .. } => match phase {
+                0 => "Baseline".to_string(),
+                1 => "Spike".to_string(),
+                _ => "Recovery".to_string(),
+            },
+            _ => format!("Phase {}", phase + 1),
+        }
+    }
+
+    fn peak_rate(&self) -> u32 {
+        match self {
+            RateSchedule::Constant(rate) => *rate,
+            RateSchedule::Phases(phases) => phases.iter().map(|p| p.rate).max().unwrap_or(0),
+            RateSchedule::Ramp { start, end, .. } => (*start).max(*end),
+            RateSchedule::Step {
+                baseline, spike, ..
+            } => (*baseline).max(*spike),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            RateSchedule::Constant(rate) => format!("{}/sec", rate),
+            RateSchedule::Phases(phases) => phases
+                .iter()
+                .map(|p| format!("{}/sec for {}s", p.rate, p.duration_secs))
+                .collect::<Vec<_>>()
+                .join(", then "),
+            RateSchedule::Ramp {
+                start,
+                end,
+                duration_secs,
+            } => format!("{}/sec ramping to {}/sec over {}s", start, end, duration_secs),
+            RateSchedule::Step {
+                baseline,
+                spike,
+                spike_at_secs,
+                spike_for_secs,
+            } => format!(
+                "{}/sec, spiking to {}/sec at {}s for {}s",
+                baseline, spike, spike_at_secs, spike_for_secs
+            ),
+        }
+    }
+}
+
+/// How sustained load decides when to send requests
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LoadModel {
+    /// Send only when a concurrency slot frees up, and report the latency
+    /// the Lambda measured. Under overload, the requests that couldn't be
+    /// sent are never measured (coordinated omission).
+    #[default]
+    Closed,
+    /// Schedule each request at the target rate and measure latency from its
+    /// intended send time, so time spent queued for a slot counts
+    Open,
+}
+
+impl LoadModel {
+    fn describe(&self) -> &'static str {
+        match self {
+            LoadModel::Closed => "closed (Lambda-measured latency)",
+            LoadModel::Open => "open (latency from intended send time)",
+        }
+    }
+}
+
+/// The first second in which p99 exceeded `--p99-threshold-ms`
+struct ThresholdCrossing {
+    elapsed_secs: f64,
+    rate: usize,
+    p99_ms: u64,
+}
+
+/// Latency over the last few seconds, for the live display: one histogram
+/// per second, merged on demand, so an early spike ages out of the window
+/// instead of inflating p99 for the rest of the run
+struct LatencyWindow {
+    seconds: VecDeque<Histogram<u64>>,
+    len: usize,
+}
+
+impl LatencyWindow {
+    fn new(len: usize) -> Self {
+        Self {
+            seconds: VecDeque::with_capacity(len),
+            len,
+        }
+    }
+
+    /// Add the second just finished, dropping the oldest once full
+    fn push(&mut self, second: &Histogram<u64>) {
+        if self.seconds.len() == self.len {
+            self.seconds.pop_front();
+        }
+        self.seconds.push_back(second.clone());
+    }
+
+    fn merged(&self) -> Option<Histogram<u64>> {
+        let mut seconds = self.seconds.iter();
+        let mut merged = seconds.next()?.clone();
+        for second in seconds {
+            let _ = merged.add(second);
+        }
+        Some(merged)
+    }
+}
+
+/// Lambda-reported latency and OCC retry count for one completed invocation
+#[derive(Clone, Copy, Debug)]
+struct Sample {
+    duration: u64,
+    retries: u32,
+    read: bool,
+    /// Rate-schedule phase the request was sent in
+    phase: usize,
+}
+
+/// Latency histograms split by request type and, for transfers, by whether
+/// the transaction had to retry.
+///
+/// A transfer that commits first time and one that hit five OCC conflicts
+/// have very different latencies; averaging them hides the cost of conflicts.
+/// Reads behave differently again, so they get their own histogram.
+struct LatencyStats {
+    clean: Histogram<u64>,
+    retried: Histogram<u64>,
+    reads: Histogram<u64>,
+    phases: Vec<Histogram<u64>>,
+    /// Successful requests, and failed ones by SQLSTATE, to show whether
+    /// errors such as OCC conflicts fail fast or slow
+    succeeded: Histogram<u64>,
+    failed: BTreeMap<String, Histogram<u64>>,
+    /// Latencies of earlier runs, from `--merge-hdr`
+    merged: Histogram<u64>,
+    sigfigs: u8,
+}
+
+impl LatencyStats {
+    /// Histograms with `sigfigs` significant figures, which `--hdr-sigfigs`
+    /// keeps within what hdrhistogram supports
+    fn new(sigfigs: u8) -> Self {
+        Self {
+            clean: Histogram::new(sigfigs).unwrap(),
+            retried: Histogram::new(sigfigs).unwrap(),
+            reads: Histogram::new(sigfigs).unwrap(),
+            phases: Vec::new(),
+            succeeded: Histogram::new(sigfigs).unwrap(),
+            failed: BTreeMap::new(),
+            merged: Histogram::new(sigfigs).unwrap(),
+            sigfigs,
+        }
+    }
+
+    /// Record a reply's latency under its outcome: success, or the error
+    /// code it failed with
+    fn record_outcome(&mut self, duration: u64, reply: &Reply) {
+        let hist = match (&reply.error, &reply.error_code) {
+            (None, _) => &mut self.succeeded,
+            (Some(_), code) => {
+                let code = code.as_deref().unwrap_or("no code");
+                let sigfigs = self.sigfigs;
+                self.failed
+                    .entry(code.to_string())
+                    .or_insert_with(|| Histogram::new(sigfigs).unwrap())
+            }
+        };
+        let _ = hist.record(duration);
+    }
+
+    fn record(&mut self, sample: Sample) {
+        if self.phases.len() <= sample.phase {
+            let sigfigs = self.sigfigs;
+            self.phases
+                .resize_with(sample.phase + 1, || Histogram::new(sigfigs).unwrap());
+        }
+        let _ = self.phases[sample.phase].record(sample.duration);
+
+        let hist = if sample.read {
+            &mut self.reads
+        } else if sample.retries > 0 {
+            &mut self.retried
+        } else {
+            &mut self.clean
+        };
+        let _ = hist.record(sample.duration);
+    }
+
+    fn writes(&self) -> Histogram<u64> {
+        let mut hist = self.clean.clone();
+        let _ = hist.add(&self.retried);
+        hist
+    }
+
+    /// This run's latencies, and any merged from earlier runs
+    fn combined(&self) -> Histogram<u64> {
+        let mut hist = self.own();
+        let _ = hist.add(&self.merged);
+        hist
+    }
+
+    fn own(&self) -> Histogram<u64> {
+        let mut hist = self.writes();
+        let _ = hist.add(&self.reads);
+        hist
+    }
+
+    fn print(&self) {
+        if !self.merged.is_empty() {
+            println!("Latency with Merged Runs (--merge-hdr):");
+            print_percentiles("This run", &self.own());
+            print_percentiles("Merged", &self.merged);
+            print_percentiles("Combined", &self.combined());
+            println!();
+        }
+        let writes = self.writes();
+        if !self.reads.is_empty() && !writes.is_empty() {
+            println!("Latency by Request Type:");
+            print_percentiles("Read", &self.reads);
+            print_percentiles("Write", &writes);
+            println!();
+        }
+        if !writes.is_empty() {
+            println!("Latency by Retry Status:");
+            print_percentiles("Clean", &self.clean);
+            print_percentiles("Retried", &self.retried);
+            println!();
+        }
+        if !self.failed.is_empty() {
+            println!("Latency by Outcome:");
+            print_percentiles("Success", &self.succeeded);
+            for (code, hist) in &self.failed {
+                print_percentiles(code, hist);
+            }
+            println!();
+        }
+    }
+
+    fn print_phases(&self, schedule: &RateSchedule) {
+        if !schedule.is_phased() || self.phases.is_empty() {
+            return;
+        }
+        println!("Latency by Phase:");
+        for (phase, hist) in self.phases.iter().enumerate() {
+            print_percentiles(&schedule.phase_label(phase), hist);
+        }
+        println!();
+    }
+}
+
+/// Print one labelled line of millisecond percentiles
+pub fn print_percentiles(label: &str, hist: &Histogram<u64>) {
+    if hist.is_empty() {
+        println!("  {:<8} p99:       n/a (no samples)", label);
+    } else {
+        println!(
+            "  {:<8} p99:       {}ms (p50: {}ms, n={})",
+            label,
+            hist.value_at_quantile(0.99),
+            hist.value_at_quantile(0.5),
+            hist.len()
+        );
+    }
+}
+
+/// How many of the slowest requests `--slow-threshold-ms` keeps
+const SLOWEST_KEPT: usize = 20;
+
+/// A request that took longer than `--slow-threshold-ms`
+struct SlowRequest {
+    duration: u64,
+    op: Op,
+    retries: Option<u32>,
+    error_code: Option<String>,
+}
+
+/// The slowest requests over `--slow-threshold-ms`, bounded so a badly
+/// overloaded run doesn't keep every one of them
+struct SlowRequests {
+    threshold_ms: u64,
+    /// Every request over the threshold, including those not kept
+    count: usize,
+    slowest: Vec<SlowRequest>,
+}
+
+impl SlowRequests {
+    fn new(threshold_ms: u64) -> Self {
+        Self {
+            threshold_ms,
+            count: 0,
+            slowest: Vec::with_capacity(SLOWEST_KEPT),
+        }
+    }
+
+    fn record(&mut self, duration: u64, op: &Op, reply: &Reply) {
+        if duration <= self.threshold_ms {
+            return;
+        }
+        self.count += 1;
+        tracing::warn!(
+            duration_ms = duration,
+            request = %op.describe(),
+            retries = ?reply.retries,
+            error_code = ?reply.error_code,
+            "slow request"
+        );
+
+        let request = SlowRequest {
+            duration,
+            op: op.clone(),
+            retries: reply.retries,
+            error_code: reply.error_code.clone(),
+        };
+        if self.slowest.len() < SLOWEST_KEPT {
+            self.slowest.push(request);
+        } else if let Some(fastest) = self.slowest.iter_mut().min_by_key(|r| r.duration)
+            && fastest.duration < duration
+        {
+            *fastest = request;
+        }
+    }
+
+    fn print(&self) {
+        println!("Slow Requests (> {}ms): {}", self.threshold_ms, self.count);
+        let mut slowest: Vec<_> = self.slowest.iter().collect();
+        slowest.sort_by_key(|r| std::cmp::Reverse(r.duration));
+        for r in slowest {
+            println!(
+                "  {:>6}ms  {:<28} retries: {:<4} {}",
+                r.duration,
+                r.op.describe(),
+                r.retries.map_or("-".to_string(), |n| n.to_string()),
+                r.error_code.as_deref().unwrap_or("")
+            );
+        }
+        println!();
+    }
+}
+
+/// Outcomes of deliberately invalid transfers
+#[derive(Default)]
+struct NegativeStats {
+    sent: usize,
+    /// Answered with an error, as expected
+    rejected: usize,
+    /// Succeeded, so the Lambda let an invalid transfer through
+    accepted: usize,
+    /// Never got an answer, e.g. the function crashed
+    crashed: usize,
+}
+
+/// Outcomes of transfers that resent an earlier idempotency key
+#[derive(Default)]
+struct DuplicateStats {
+    /// Duplicates the Lambda answered
+    sent: usize,
+    /// Refused with an error or reported as deduplicated
+    rejected: usize,
+    /// Succeeded without being deduplicated, so the transfer ran twice
+    applied: usize,
+}
+
+/// Whether an optional reply field has come back from any request that
+/// could have carried it
+#[derive(Default)]
+struct Presence {
+    expected: bool,
+    seen: bool,
+}
+
+impl Presence {
+    fn note<T>(&mut self, field: &Option<T>) {
+        self.expected = true;
+        self.seen |= field.is_some();
+    }
+
+    fn missing(&self) -> bool {
+        self.expected && !self.seen
+    }
+}
+
+/// Which optional fields the Lambda never sent, since different chapter
+/// Lambdas report different subsets and a blank section otherwise looks
+/// like zero
+#[derive(Default)]
+struct ReportedFields {
+    duration: Presence,
+    retries: Presence,
+    balance: Presence,
+    /// Only expected of error replies
+    error_code: Presence,
+}
+
+impl ReportedFields {
+    /// Note the fields of a reply the Lambda built itself; greetings and
+    /// chains are timed and totalled by the client
+    fn record(&mut self, op: &Op, reply: &Reply) {
+        match op {
+            Op::Transfer { .. } => {
+                self.duration.note(&reply.duration);
+                self.retries.note(&reply.retries);
+                self.balance.note(&reply.balance);
+            }
+            Op::Read { .. } => self.duration.note(&reply.duration),
+            Op::Greeting | Op::Chain { .. } | Op::ReadYourWrite { .. } => {}
+        }
+        if reply.error.is_some() {
+            self.error_code.note(&reply.error_code);
+        }
+    }
+
+    fn print(&self) {
+        let missing: Vec<_> = [
+            (&self.duration, "duration", "no execution times"),
+            (&self.retries, "retries", "no OCC retry statistics"),
+            (&self.balance, "balance", "no balances after transfers"),
+            (&self.error_code, "error_code", "errors can't be told apart by SQLSTATE"),
+        ]
+        .into_iter()
+        .filter(|(presence, ..)| presence.missing())
+        .collect();
+        if missing.is_empty() {
+            return;
+        }
+        println!("Missing From Replies:");
+        for (_, field, effect) in missing {
+            println!("  {:<19} not reported by Lambda ({})", format!("{}:", field), effect);
+        }
+        println!();
+    }
+}
+
+/// Outcomes of `--workload chain` chains
+#[derive(Default)]
+struct ChainStats {
+    sent: usize,
+    /// Every hop succeeded
+    completed: usize,
+    /// Chains that broke at each hop, the first hop first
+    broken_at: Vec<usize>,
+}
+
+impl ChainStats {
+    fn record(&mut self, broken_at: Option<usize>) {
+        self.sent += 1;
+        match broken_at {
+            Some(hop) => {
+                if self.broken_at.len() < hop {
+                    self.broken_at.resize(hop, 0);
+                }
+                self.broken_at[hop - 1] += 1;
+            }
+            None => self.completed += 1,
+        }
+    }
+}
+
+/// Outcomes of `--workload ryow` reads
+#[derive(Default)]
+struct ReadBackStats {
+    consistent: usize,
+    stale: usize,
+    unchecked: usize,
+}
+
+impl ReadBackStats {
+    fn record(&mut self, read_back: ReadBack) {
+        match read_back {
+            ReadBack::Consistent => self.consistent += 1,
+            ReadBack::Stale => self.stale += 1,
+            ReadBack::Unchecked => self.unchecked += 1,
+        }
+    }
+
+    fn total(&self) -> usize {
+        self.consistent + self.stale + self.unchecked
+    }
+}
+
+/// Totals over every completed request of a run, shared by both runners
+pub struct RunStats {
+    calls: usize,
+    success: usize,
+    errors: usize,
+    /// Requests that never got a response from the Lambda, other than by
+    /// timing out
+    dispatch_errors: usize,
+    /// Requests given up on after the invocation timeout
+    timeouts: usize,
+    /// Invocations that got a response but no reply to read: a function
+    /// error, no payload, one that didn't decode, or a Lambda service error
+    failed_invokes: usize,
+    /// Error replies from a Lambda that gave up at `deadline_ms`, before
+    /// the client timed out
+    deadline_aborts: usize,
+    /// Why requests that failed to dispatch did
+    dispatch_causes: HashMap<lambda::DispatchCause, usize>,
+    /// Resends made by `--dispatch-retries`, and the calls they rescued
+    dispatch_retries: u64,
+    recovered_calls: usize,
+    occ_errors: usize,
+    /// `--occ-as-success`: leave `occ_errors` out of the success rate
+    occ_as_success: bool,
+    min_duration: u64,
+    max_duration: u64,
+    total_duration: u64,
+    duration_count: usize,
+    total_retries: u64,
+    max_retries: u32,
+    /// Calls whose transaction needed at least one OCC retry
+    retried_calls: usize,
+    /// Successful transfers that weren't deduplicated, each of which should
+    /// have written one ledger row
+    applied_transfers: usize,
+    client_errors: usize,
+    server_errors: usize,
+    error_types: HashMap<(ErrorClass, String), usize>,
+    latency: LatencyStats,
+    duplicates: DuplicateStats,
+    negative: NegativeStats,
+    chains: ChainStats,
+    read_backs: ReadBackStats,
+    slow: Option<SlowRequests>,
+    reported: ReportedFields,
+    /// `--retry-budget`, which `total_retries` must not exceed
+    retry_budget: Option<u64>,
+    /// Invocations abandoned when `--drain-timeout` ran out
+    incomplete: usize,
+    /// `--warmup-invocations`, and how many of them are still to complete
+    warmup: usize,
+    warmup_left: usize,
+    /// When the stats started, and how long the warmup took once it's over,
+    /// which is left out of the measured time
+    started: Instant,
+    warmup_took: Option<Duration>,
+}
+
+/// Ledger rows a completed request should have written: every transfer of
+/// one that succeeded without being deduplicated, the hops of a chain
+/// before the one that broke, or a read-your-writes transfer whose read
+/// failed
+fn applied_transfers(completion: &Completion) -> usize {
+    if let (Op::Chain { .. } | Op::ReadYourWrite { .. }, Some(step)) =
+        (&completion.op, completion.broken_at)
+    {
+        return (step - 1).min(completion.op.transfers());
+    }
+    match &completion.result {
+        Ok(reply) if reply.error.is_none() && !reply.deduplicated => completion.op.transfers(),
+        _ => 0,
+    }
+}
+
+impl RunStats {
+    fn new(run: &RunArgs) -> Self {
+        Self {
+            calls: 0,
+            success: 0,
+            errors: 0,
+            dispatch_errors: 0,
+            timeouts: 0,
+            failed_invokes: 0,
+            deadline_aborts: 0,
+            dispatch_causes: HashMap::new(),
+            dispatch_retries: 0,
+            recovered_calls: 0,
+            occ_errors: 0,
+            occ_as_success: run.occ_as_success,
+            min_duration: u64::MAX,
+            max_duration: 0,
+            total_duration: 0,
+            duration_count: 0,
+            total_retries: 0,
+            max_retries: 0,
+            retried_calls: 0,
+            applied_transfers: 0,
+            client_errors: 0,
+            server_errors: 0,
+            error_types: HashMap::new(),
+            latency: LatencyStats::new(run.hdr_sigfigs),
+            duplicates: DuplicateStats::default(),
+            negative: NegativeStats::default(),
+            chains: ChainStats::default(),
+            read_backs: ReadBackStats::default(),
+            slow: run.slow_threshold_ms.map(SlowRequests::new),
+            reported: ReportedFields::default(),
+            retry_budget: run.retry_budget,
+            incomplete: 0,
+            warmup: run.warmup_invocations,
+            warmup_left: run.warmup_invocations,
+            started: Instant::now(),
+            warmup_took: None,
+        }
+    }
+
+    fn record(&mut self, completion: &Completion) {
+        // Warmup transfers still write to the ledger
+        self.applied_transfers += applied_transfers(completion);
+        if self.warmup_left > 0 {
+            self.warmup_left -= 1;
+            if self.warmup_left == 0 {
+                self.warmup_took = Some(self.started.elapsed());
+            }
+            return;
+        }
+
+        self.calls += 1;
+        if completion.dispatch_retries > 0 {
+            self.dispatch_retries += completion.dispatch_retries as u64;
+            if completion.result.is_ok() {
+                self.recovered_calls += 1;
+            }
+        }
+
+        if completion.op.is_invalid() {
+            self.negative.sent += 1;
+            match &completion.result {
+                Err(_) => self.negative.crashed += 1,
+                Ok(reply) if reply.error.is_some() => self.negative.rejected += 1,
+                Ok(_) => self.negative.accepted += 1,
+            }
+        }
+
+        if let Op::Chain { .. } = completion.op {
+            self.chains.record(completion.broken_at);
+        }
+        if let Ok(Reply {
+            read_back: Some(read_back),
+            ..
+        }) = completion.result
+        {
+            self.read_backs.record(read_back);
+        }
+
+        let reply = match &completion.result {
+            Ok(reply) => reply,
+            Err(err) => {
+                self.errors += 1;
+                let invoke = err.downcast_ref::<lambda::InvokeError>();
+                // SDK, dispatch and timeout failures are never the request's
+                let class = match invoke.and_then(|invoke| invoke.function_error.as_ref()) {
+                    Some(thrown) => ErrorClass::of_function_error(thrown),
+                    None => ErrorClass::Server,
+                };
+                match class {
+                    ErrorClass::Client => self.client_errors += 1,
+                    ErrorClass::Server => self.server_errors += 1,
+                }
+                let what = if invoke.is_some_and(|invoke| invoke.timed_out) {
+                    self.timeouts += 1;
+                    "timed out"
+                } else if let Some(cause) = invoke.and_then(|invoke| invoke.dispatch_cause) {
+                    self.dispatch_errors += 1;
+                    *self.dispatch_causes.entry(cause).or_insert(0) += 1;
+                    "failed"
+                } else {
+                    // The Lambda answered, so this says nothing about load
+                    self.failed_invokes += 1;
+                    "failed"
+                };
+                // Group by what went wrong, not by the request it happened to
+                let err = match invoke {
+                    Some(invoke) => &invoke.error,
+                    None => err,
+                };
+                *self
+                    .error_types
+                    .entry((class, format!("Lambda invocation {what}: {err}")))
+                    .or_insert(0) += 1;
+                return;
+            }
+        };
+
+        self.reported.record(&completion.op, reply);
+        if let Some(error) = &reply.error {
+            self.errors += 1;
+            if reply.is_occ_error() {
+                self.occ_errors += 1;
+            }
+            if reply.deadline_exceeded {
+                self.deadline_aborts += 1;
+            }
+            let class = ErrorClass::of_reply(reply);
+            match class {
+                ErrorClass::Client => self.client_errors += 1,
+                ErrorClass::Server => self.server_errors += 1,
+            }
+            let error_key = if let Some(code) = &reply.error_code {
+                format!("{} ({})", error, code)
+            } else {
+                error.clone()
+            };
+            *self.error_types.entry((class, error_key)).or_insert(0) += 1;
+        } else {
+            self.success += 1;
+        }
+
+        if completion.op.is_duplicate() {
+            self.duplicates.sent += 1;
+            if reply.error.is_some() || reply.deduplicated {
+                self.duplicates.rejected += 1;
+            } else {
+                self.duplicates.applied += 1;
+            }
+        }
+
+        if let Some(duration) = reply.duration {
+            self.min_duration = self.min_duration.min(duration);
+            self.max_duration = self.max_duration.max(duration);
+            self.total_duration += duration;
+            self.duration_count += 1;
+        }
+
+        if let Some(latency) = completion.latency() {
+            if let Some(slow) = self.slow.as_mut() {
+                slow.record(latency, &completion.op, reply);
+            }
+            self.latency.record(Sample {
+                duration: latency,
+                retries: reply.retries.unwrap_or(0),
+                read: completion.op.is_read(),
+                phase: completion.phase,
+            });
+            self.latency.record_outcome(latency, reply);
+        }
+
+        if let Some(retries) = reply.retries {
+            self.total_retries += retries as u64;
+            self.max_retries = self.max_retries.max(retries);
+            if retries > 0 {
+                self.retried_calls += 1;
+            }
+        }
+    }
+
+    /// Whether the run has made more OCC retries than `--retry-budget` allows
+    fn retry_budget_spent(&self) -> bool {
+        self.retry_budget.is_some_and(|budget| self.total_retries > budget)
+    }
+
+    /// The error rate, if it has passed `--max-error-rate`
+    fn error_rate_exceeded(&self, run: &RunArgs) -> Option<f64> {
+        let max = run.max_error_rate?;
+        let rate = self.failures() as f64 / self.calls as f64;
+        (self.calls >= run.error_rate_min_calls && rate > max).then_some(rate)
+    }
+
+    /// Errors that count against the success rate: all of them, or all
+    /// but OCC conflicts with `--occ-as-success`
+    fn failures(&self) -> usize {
+        if self.occ_as_success {
+            self.errors - self.occ_errors
+        } else {
+            self.errors
+        }
+    }
+
+    fn percent(&self, n: usize) -> f64 {
+        if self.calls > 0 {
+            n as f64 / self.calls as f64 * 100.0
+        } else {
+            0.0
+        }
+    }
+
+    /// Mean execution time the Lambda reported, in milliseconds
+    fn avg_duration(&self) -> Option<f64> {
+        (self.duration_count > 0)
+            .then(|| self.total_duration as f64 / self.duration_count as f64)
+    }
+
+    /// Whether `--warmup-invocations` are still being discarded
+    fn warming_up(&self) -> bool {
+        self.warmup_left > 0
+    }
+
+    /// The part of `elapsed` after the warmup
+    fn measured(&self, elapsed: Duration) -> Duration {
+        elapsed.saturating_sub(self.warmup_took.unwrap_or_default())
+    }
+
+    fn throughput(&self, elapsed: Duration) -> f64 {
+        let elapsed = self.measured(elapsed);
+        if elapsed.as_secs_f64() > 0.0 {
+            self.calls as f64 / elapsed.as_secs_f64()
+        } else {
+            0.0
+        }
+    }
+
+    fn print(&self, title: &str, elapsed: Duration) {
+        println!();
+        println!("{}", "=".repeat(60));
+        println!("{}", title);
+        println!("{}", "=".repeat(60));
+        println!("Total calls:        {}", self.calls);
+        println!(
+            "Successful:         {} ({:.2}%)",
+            self.success,
+            self.percent(self.success)
+        );
+        if self.occ_as_success {
+            let succeeded = self.calls - self.failures();
+            println!(
+                "  With OCC:         {} ({:.2}%, counting OCC conflicts as successes)",
+                succeeded,
+                self.percent(succeeded)
+            );
+        }
+        println!(
+            "Errors:             {} ({:.2}%)",
+            self.errors,
+            self.percent(self.errors)
+        );
+        if self.errors > 0 {
+            // Kept apart so a slow service, a failing one and an unreachable
+            // one each stand out
+            let replied =
+                self.errors - self.timeouts - self.dispatch_errors - self.failed_invokes;
+            println!("  Error replies:    {}", replied);
+            if self.occ_errors > 0 {
+                println!("    OCC conflicts:  {}", self.occ_errors);
+            }
+            if self.deadline_aborts > 0 {
+                println!("    Past deadline:  {}", self.deadline_aborts);
+            }
+            println!("  Failed invokes:   {}", self.failed_invokes);
+            println!("  Timed out:        {}", self.timeouts);
+            println!("  No reply:         {}", self.dispatch_errors);
+            let mut causes: Vec<_> = self.dispatch_causes.iter().collect();
+            causes.sort_by(|a, b| b.1.cmp(a.1));
+            for (cause, count) in causes {
+                println!("    {:<22} {}", format!("{}:", cause), count);
+            }
+        }
+        if self.dispatch_retries > 0 {
+            println!(
+                "Dispatch retries:   {} ({} calls recovered)",
+                self.dispatch_retries, self.recovered_calls
+            );
+        }
+        if self.incomplete > 0 {
+            println!(
+                "Incomplete:         {} invocations abandoned at --drain-timeout",
+                self.incomplete
+            );
+        }
+        if let Some(took) = self.warmup_took {
+            println!(
+                "Warmup:             {} invocations discarded, over {:.2}s",
+                self.warmup,
+                took.as_secs_f64()
+            );
+        } else if self.warming_up() {
+            println!(
+                "Warmup:             only {} of {} invocations completed, nothing measured",
+                self.warmup - self.warmup_left,
+                self.warmup
+            );
+        }
+        println!();
+        println!("Total time:         {:.2}s", self.measured(elapsed).as_secs_f64());
+        println!(
+            "Throughput:         {:.0} calls/second",
+            self.throughput(elapsed)
+        );
+        println!();
+        self.reported.print();
+
+        if let Some(avg_duration) = self.avg_duration() {
+            println!("Lambda Execution Times:");
+            println!("  Min:                {:.2}ms", self.min_duration);
+            println!("  Max:                {:.2}ms", self.max_duration);
+            println!("  Avg:                {:.2}ms", avg_duration);
+            println!();
+        }
+
+        self.latency.print();
+        if let Some(slow) = &self.slow {
+            slow.print();
+        }
+
+        if self.total_retries > 0 {
+            let avg_retries = self.total_retries as f64 / self.calls as f64;
+            println!("OCC Retry Statistics:");
+            println!("  Total retries:      {}", self.total_retries);
+            if let Some(budget) = self.retry_budget {
+                let verdict = if self.retry_budget_spent() {
+                    "exhausted"
+                } else {
+                    "not reached"
+                };
+                println!("  Retry budget:       {} ({})", budget, verdict);
+            }
+            println!("  Max retries:        {}", self.max_retries);
+            println!("  Avg retries/call:   {:.2}", avg_retries);
+            println!(
+                "  Transactions with retries: {} ({:.2}%)",
+                self.retried_calls,
+                self.percent(self.retried_calls)
+            );
+            println!();
+        }
+
+        if self.duplicates.sent > 0 {
+            println!("Idempotency:");
+            println!("  Duplicates sent:    {}", self.duplicates.sent);
+            println!("  Rejected:           {}", self.duplicates.rejected);
+            println!("  Applied twice:      {}", self.duplicates.applied);
+            println!();
+        }
+
+        if self.negative.sent > 0 {
+            let percent = |n: usize| n as f64 / self.negative.sent as f64 * 100.0;
+            println!("Negative Testing:");
+            println!("  Invalid sent:       {}", self.negative.sent);
+            println!(
+                "  Expected errors:    {} ({:.2}%)",
+                self.negative.rejected,
+                percent(self.negative.rejected)
+            );
+            println!(
+                "  Accepted:           {} ({:.2}%)",
+                self.negative.accepted,
+                percent(self.negative.accepted)
+            );
+            println!(
+                "  Crashed:            {} ({:.2}%)",
+                self.negative.crashed,
+                percent(self.negative.crashed)
+            );
+            println!();
+        }
+
+        if self.chains.sent > 0 {
+            let percent = |n: usize| n as f64 / self.chains.sent as f64 * 100.0;
+            println!("Chains (latency above is end to end):");
+            println!("  Chains sent:        {}", self.chains.sent);
+            println!(
+                "  Completed:          {} ({:.2}%)",
+                self.chains.completed,
+                percent(self.chains.completed)
+            );
+            for (i, &broken) in self.chains.broken_at.iter().enumerate() {
+                println!(
+                    "  Broke at hop {:<7}{} ({:.2}%)",
+                    format!("{}:", i + 1),
+                    broken,
+                    percent(broken)
+                );
+            }
+            println!();
+        }
+
+        if self.read_backs.total() > 0 {
+            let total = self.read_backs.total();
+            let percent = |n: usize| n as f64 / total as f64 * 100.0;
+            println!("Read-Your-Writes (latency above is end to end):");
+            println!("  Read back:          {}", total);
+            println!(
+                "  Consistent:         {} ({:.2}%)",
+                self.read_backs.consistent,
+                percent(self.read_backs.consistent)
+            );
+            println!(
+                "  Violations:         {} ({:.2}%)",
+                self.read_backs.stale,
+                percent(self.read_backs.stale)
+            );
+            println!(
+                "  Unchecked:          {} ({:.2}%, payer touched meanwhile)",
+                self.read_backs.unchecked,
+                percent(self.read_backs.unchecked)
+            );
+            println!();
+        }
+
+        if !self.error_types.is_empty() {
+            println!("Error Breakdown:");
+            println!("  {:>8} {:>8}  Error", "Client", "Server");
+            println!("  {:>8} {:>8}  (total)", self.client_errors, self.server_errors);
+            let mut error_vec: Vec<_> = self.error_types.iter().collect();
+            error_vec.sort_by(|a, b| b.1.cmp(a.1));
+            for ((class, error_type), count) in error_vec {
+                let (client, server) = match class {
+                    ErrorClass::Client => (count.to_string(), "-".to_string()),
+                    ErrorClass::Server => ("-".to_string(), count.to_string()),
+                };
+                println!("  {:>8} {:>8}  {}", client, server, error_type);
+            }
+            println!();
+        }
+    }
+
+    /// Count the latencies of earlier runs, saved with `--results-dir`, in
+    /// this run's percentiles; call counts and rates stay this run's own
+    pub fn merge_latency(&mut self, saved: &Histogram<u64>) -> Result<()> {
+        self.latency
+            .merged
+            .add(saved)
+            .map_err(|e| anyhow::anyhow!("Failed to merge saved latencies: {:?}", e))
+    }
+
+    /// Latency of every measured request, reads and writes together, and
+    /// of any runs merged in
+    pub fn latency_histogram(&self) -> Histogram<u64> {
+        self.latency.combined()
+    }
+
+    pub fn summary(&self, elapsed: Duration, model: LoadModel) -> StressSummary {
+        let latency = self.latency.combined();
+        StressSummary {
+            total_calls: self.calls,
+            success: self.success,
+            errors: self.errors,
+            occ_errors: self.occ_errors,
+            occ_as_success: self.occ_as_success,
+            client_errors: self.client_errors,
+            server_errors: self.server_errors,
+            timeouts: self.timeouts,
+            deadline_aborts: self.deadline_aborts,
+            elapsed_secs: self.measured(elapsed).as_secs_f64(),
+            throughput: self.throughput(elapsed),
+            p50_ms: latency.value_at_quantile(0.5),
+            p99_ms: latency.value_at_quantile(0.99),
+            total_retries: self.total_retries,
+            retried_calls: self.retried_calls,
+            applied_transfers: self.applied_transfers,
+            consistency_violations: self.read_backs.stale,
+            bytes_sent: 0,
+            bytes_received: 0,
+            model,
+            metadata: None,
+        }
+    }
+}
+
+/// Print how late requests were spawned relative to the schedule. A large
+/// lag means the client, or the concurrency target, was holding requests
+/// back rather than the server being slow.
+fn print_scheduling_lag(lag: &Histogram<u64>) {
+    if lag.is_empty() {
+        return;
+    }
+    let ms = |micros: u64| micros as f64 / 1000.0;
+    println!("Scheduling Lag (spawned after intended send time):");
+    println!("  p50:                {:.2}ms", ms(lag.value_at_quantile(0.5)));
+    println!("  p99:                {:.2}ms", ms(lag.value_at_quantile(0.99)));
+    println!("  Max:                {:.2}ms", ms(lag.max()));
+    println!();
+}
+
+/// Print how each share of the invocations went, such as each region's
+/// when `--endpoints` spread the pool over several
+fn print_breakdown(title: &str, shares: &[LabelledLatency]) {
+    if shares.is_empty() {
+        return;
+    }
+    println!("Latency by {} (client-measured):", title);
+    for share in shares {
+        print_percentiles(&share.label, &share.latency);
+        if share.errors > 0 {
+            println!("  {:<8} errors:    {}", "", share.errors);
+        }
+    }
+    println!();
+}
+
+/// Print client-measured latency on new vs reused HTTP connections, which
+/// shows what connection setup costs and so whether more clients (each with
+/// its own connections to warm) are worth it
+fn print_connection_latency(client_pool: &ClientPool) {
+    let Some(latency) = client_pool.connection_latency() else {
+        return;
+    };
+    println!("Latency by Connection (client-measured):");
+    print_percentiles("New", &latency.new);
+    print_percentiles("Reused", &latency.reused);
+    println!();
+}
+
+/// Print the average time an invocation spent in each step, nested the way
+/// the steps are, so whether client-side overhead or the Lambda's execution
+/// dominates is plain
+fn print_phase_breakdown(client_pool: &ClientPool, stats: &RunStats) {
+    let Some(phases) = client_pool.phase_breakdown() else {
+        return;
+    };
+    let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+    let dispatch = ms(phases.dispatch);
+    println!("Time per Invocation (averages of {}):", phases.invocations);
+    println!("  Serialize:          {:.3}ms", ms(phases.serialize));
+    println!("  Dispatch:           {:.3}ms", dispatch);
+    let mut accounted = 0.0;
+    if let Some(sign) = phases.sign {
+        println!("    Sign:             {:.3}ms (including credentials)", ms(sign));
+        accounted += ms(sign);
+    }
+    if let Some(execution) = stats.avg_duration() {
+        println!("    Lambda execution: {:.3}ms", execution);
+        accounted += execution;
+    }
+    println!("    Network and SDK:  {:.3}ms", (dispatch - accounted).max(0.0));
+    println!("  Deserialize:        {:.3}ms", ms(phases.deserialize));
+    println!();
+}
+
+/// Print how much payload went back and forth, which matters when data
+/// transfer rather than compute dominates a workload's cost
+fn print_traffic(traffic: &Traffic) {
+    if traffic.requests == 0 {
+        return;
+    }
+    let mb = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+    println!("Payload Traffic:");
+    println!(
+        "  Sent:               {:.2} MB ({} bytes)",
+        mb(traffic.sent_bytes),
+        traffic.sent_bytes
+    );
+    println!(
+        "  Received:           {:.2} MB ({} bytes)",
+        mb(traffic.received_bytes),
+        traffic.received_bytes
+    );
+    println!("  Avg request:        {:.0} bytes", traffic.avg_request_bytes());
+    println!("  Avg response:       {:.0} bytes", traffic.avg_response_bytes());
+    println!();
+}
+
+/// Print how evenly requests were spread over the pool's clients
+fn print_client_distribution(client_pool: &ClientPool) {
+    let counts = client_pool.request_counts();
+    if counts.len() < 2 {
+        return;
+    }
+    let mean = counts.iter().sum::<usize>() as f64 / counts.len() as f64;
+    let variance = counts
+        .iter()
+        .map(|&n| (n as f64 - mean).powi(2))
+        .sum::<f64>()
+        / counts.len() as f64;
+    println!("Requests per Client ({} clients):", counts.len());
+    println!("  Min:                {}", counts.iter().min().unwrap());
+    println!("  Max:                {}", counts.iter().max().unwrap());
+    println!("  Mean:               {:.1}", mean);
+    println!("  Stddev:             {:.1}", variance.sqrt());
+    println!();
+}
+
+/// When `--max-runtime` runs out, if it was given
+static MAX_RUNTIME_DEADLINE: OnceLock<Instant> = OnceLock::new();
+
+/// Make every run stop at `deadline` as though Ctrl-C had been pressed, so
+/// it drains and reports what it has
+pub fn stop_at(deadline: Instant) {
+    let _ = MAX_RUNTIME_DEADLINE.set(deadline);
+}
+
+/// Wait for Ctrl-C or for `--max-runtime` to run out, and say which
+pub async fn stop_requested() -> &'static str {
+    let deadline = async {
+        match MAX_RUNTIME_DEADLINE.get() {
+            Some(deadline) => tokio::time::sleep_until((*deadline).into()).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => "Interrupted",
+        _ = deadline => "Max runtime reached",
+    }
+}
+
+/// When a closed-loop run stops launching requests
+#[derive(Clone, Copy, Debug)]
+pub enum Budget {
+    /// After this many requests
+    Calls(usize),
+    /// Once this much time has passed
+    Duration(Duration),
+}
+
+/// How hard a closed-loop run pushes
+#[derive(Clone, Copy, Debug)]
+pub struct Pace {
+    /// Requests to keep in flight
+    pub parallel: usize,
+    /// At most this many invocations started per second, if set
+    pub rate: Option<u32>,
+    /// Spread the first `parallel` launches evenly over this long rather
+    /// than starting them all at once
+    pub ramp_in: Option<Duration>,
+}
+
+impl Pace {
+    /// As many requests per second as `parallel` in flight allows
+    pub fn parallel(parallel: usize) -> Self {
+        Self {
+            parallel,
+            rate: None,
+            ramp_in: None,
+        }
+    }
+
+    /// How many launches the ramp-in allows `elapsed` into the run: the
+    /// n-th of the first `parallel` waits until n/parallel of the way in
+    fn ramped(&self, elapsed: Duration) -> Option<usize> {
+        let ramp = self.ramp_in?;
+        if elapsed >= ramp {
+            return None;
+        }
+        let due = elapsed.as_secs_f64() / ramp.as_secs_f64() * self.parallel as f64;
+        Some(due as usize + 1)
+    }
+}
+
+/// What a closed-loop run measured
+pub struct ClosedLoopOutcome {
+    pub stats: RunStats,
+    pub elapsed: Duration,
+    /// The error rate that tripped `--max-error-rate`, if it did
+    pub aborted: Option<f64>,
+    /// Whether Ctrl-C or --max-runtime stopped the run before its budget was spent
+    pub interrupted: bool,
+    /// What a request task panicked with, if one did; its requests went
+    /// unrecorded and the run stopped launching
+    pub panicked: Option<String>,
+}
+
+/// Describe why a request task failed to join
+fn join_failure(err: JoinError) -> String {
+    if !err.is_panic() {
+        return err.to_string();
+    }
+    let payload = err.into_panic();
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "panicked with a non-string payload".to_string()
+    }
+}
+
+/// Keep `pace.parallel` requests in flight, starting no more than
+/// `pace.rate` a second, until the budget is spent or Ctrl-C is pressed,
+/// then wait for the stragglers.
+///
+/// `pb` advances once per completed request and `concurrent` tracks how many
+/// are in flight, redrawn at most every 50ms with `--coalesce-progress`;
+/// pass hidden bars to run quietly.
+pub async fn run_closed_loop(
+    client_pool: &ClientPool,
+    generator: &mut RequestGenerator<'_>,
+    pace: Pace,
+    budget: Budget,
+    run: &RunArgs,
+    pb: &ProgressBar,
+    concurrent: &ProgressBar,
+) -> ClosedLoopOutcome {
+    let start = Instant::now();
+    let mut stats = RunStats::new(run);
+    let max_dispatch_retries = run.dispatch_retries;
+    let batch_size = generator.workload.batch_size as usize;
+    let ryow = RyowActivity::default();
+
+    let mut tasks = JoinSet::new();
+    let mut launched = 0;
+    // Tasks started, which differs from `launched` when batching
+    let mut started = 0usize;
+    let mut aborted = None;
+    let mut interrupted = false;
+    let mut panicked = None;
+    let mut budget_spent = false;
+    let mut draining = false;
+    // When to give up on the stragglers, once draining
+    let mut drain_deadline = None;
+    // Per-second rate limit, as the sustained loop counts it
+    let mut spawned_this_sec = 0usize;
+    let mut last_reset = Instant::now();
+    let mut bars = progress::Coalesced::new(pb, concurrent, run.coalesce_progress);
+    let ctrl_c = stop_requested();
+    tokio::pin!(ctrl_c);
+
+    loop {
+        bars.flush_if_due(tasks.len());
+        let within_budget = match budget {
+            Budget::Calls(total_calls) => launched < total_calls,
+            Budget::Duration(duration) => start.elapsed() < duration,
+        };
+        if last_reset.elapsed() >= Duration::from_secs(1) {
+            spawned_this_sec = 0;
+            last_reset = Instant::now();
+        }
+        let mut rem = pace.parallel - tasks.len();
+        if let Some(rate) = pace.rate {
+            rem = rem.min((rate as usize).saturating_sub(spawned_this_sec));
+        }
+        let ramp_due = pace.ramped(start.elapsed());
+        if let Some(due) = ramp_due {
+            rem = rem.min(due.saturating_sub(started));
+        }
+        let launching = within_budget
+            && aborted.is_none()
+            && !interrupted
+            && !budget_spent
+            && panicked.is_none();
+        if launching && rem > 0 {
+            for _ in 0..rem {
+                let batch_size = match budget {
+                    Budget::Calls(total_calls) => batch_size.min(total_calls - launched),
+                    Budget::Duration(_) => batch_size,
+                };
+                if batch_size == 0 {
+                    break;
+                }
+                if generator.next_due().is_some_and(|due| due > start.elapsed()) {
+                    break;
+                }
+                let ops = generator.next_batch(batch_size);
+                launched += ops.len();
+                let pool = client_pool.clone();
+                let ryow = ryow.clone();
+                tasks.spawn(async move {
+                    execute_with_retries(&pool, &ryow, ops, 0, max_dispatch_retries).await
+                });
+                bars.started(tasks.len());
+                spawned_this_sec += 1;
+                started += 1;
+            }
+        }
+        let exhausted = match budget {
+            Budget::Calls(total_calls) => launched >= total_calls,
+            Budget::Duration(duration) => start.elapsed() >= duration,
+        };
+        if !draining && (!launching || exhausted) {
+            draining = true;
+            drain_deadline = run
+                .drain_timeout
+                .map(|secs| tokio::time::Instant::now() + Duration::from_secs(secs));
+        }
+
+        // Wake when a duration budget runs out, even if every request in
+        // flight is stuck, so the drain timeout starts on time
+        let budget_end = async {
+            match budget {
+                Budget::Duration(duration) if !draining => {
+                    tokio::time::sleep_until((start + duration).into()).await
+                }
+                _ => std::future::pending().await,
+            }
+        };
+        let drained = async {
+            match drain_deadline {
+                Some(deadline) => tokio::time::sleep_until(deadline).await,
+                None => std::future::pending().await,
+            }
+        };
+        // Out of this second's allowance: wait for the next one, even with
+        // nothing in flight
+        let throttled = !draining && pace.rate.is_some_and(|r| spawned_this_sec >= r as usize);
+        let next_second = tokio::time::sleep_until((last_reset + Duration::from_secs(1)).into());
+        // Ramping in: wait for the next launch's turn
+        let ramping = !draining && ramp_due.is_some_and(|due| started >= due);
+        let next_turn = async {
+            match pace.ramp_in {
+                Some(ramp) if ramping => {
+                    let turn = ramp.mul_f64(started as f64 / pace.parallel as f64);
+                    tokio::time::sleep_until((start + turn).into()).await
+                }
+                _ => std::future::pending().await,
+            }
+        };
+        // Replaying: wait until the next recorded transfer is due
+        let replay_due = generator.next_due().filter(|due| !draining && *due > start.elapsed());
+        let next_recorded = async {
+            match replay_due {
+                Some(due) => tokio::time::sleep_until((start + due).into()).await,
+                None => std::future::pending().await,
+            }
+        };
+        let waiting = throttled || ramping || replay_due.is_some();
+        let joined = tokio::select! {
+            joined = tasks.join_next(), if !(waiting && tasks.is_empty()) => joined,
+            _ = next_second, if throttled => continue,
+            _ = next_turn => continue,
+            _ = next_recorded => continue,
+            _ = budget_end => continue,
+            why = &mut ctrl_c, if !interrupted => {
+                progress::println(pb, format!("{}, draining in-flight requests...", why));
+                interrupted = true;
+                continue;
+            }
+            _ = drained => {
+                stats.incomplete = tasks.len();
+                tasks.abort_all();
+                break;
+            }
+        };
+        if let Some(result) = joined {
+            let completions = match result {
+                Ok(completions) => completions,
+                Err(err) => {
+                    bars.finished(0, tasks.len());
+                    let message = join_failure(err);
+                    if panicked.is_none() {
+                        progress::println(
+                            pb,
+                            format!(
+                                "Request task failed ({}), draining in-flight requests...",
+                                message
+                            ),
+                        );
+                        panicked = Some(message);
+                    }
+                    continue;
+                }
+            };
+            for completion in &completions {
+                stats.record(completion);
+            }
+            bars.finished(completions.len(), tasks.len());
+
+            if aborted.is_none()
+                && let Some(rate) = stats.error_rate_exceeded(run)
+            {
+                progress::println(
+                    pb,
+                    format!(
+                        "Error rate {:.2}% exceeded the limit, draining in-flight requests...",
+                        rate * 100.0
+                    ),
+                );
+                aborted = Some(rate);
+            }
+            if !budget_spent && stats.retry_budget_spent() {
+                progress::println(pb, "Retry budget exhausted, draining in-flight requests...");
+                budget_spent = true;
+            }
+        } else {
+            break;
+        }
+    }
+    bars.flush(tasks.len());
+
+    ClosedLoopOutcome {
+        stats,
+        elapsed: start.elapsed(),
+        aborted,
+        interrupted,
+        panicked,
+    }
+}
+
+/// Fail fast if transfers have no payee to pick other than the payer,
+/// rather than hang looking for one, or if the workload can't check what
+/// it sends
+pub fn check_accounts(num_accounts: u32, workload: &WorkloadArgs) -> Result<()> {
+    if workload.sends_transfers() && num_accounts < 2 {
+        return Err(anyhow::anyhow!(
+            "Transfers need at least 2 accounts, one to pay and another to be paid, \
+             but the run has {}; pass --accounts 2 or more",
+            num_accounts
+        )
+        .context(Failure::Config));
+    }
+    if workload.amount_decimal && workload.workload == Workload::Ryow {
+        return Err(anyhow::anyhow!(
+            "--amount-decimal moves exact balances, which the read-backs of \
+             --workload ryow don't see"
+        )
+        .context(Failure::Config));
+    }
+    Ok(())
+}
+
+/// Send `total_calls` invocations at `pace`: up to `pace.parallel` in
+/// flight and, if `pace.rate` is set, no more than that many started per
+/// second
 pub async fn run_stress_test(
     client_pool: &ClientPool,
     total_calls: usize,
-    parallel_calls: usize,
+    pace: Pace,
     num_accounts: u32,
-) -> Result<()> {
+    workload: &WorkloadArgs,
+    run: &RunArgs,
+) -> Result<StressSummary> {
+    check_accounts(num_accounts, workload)?;
+    let generator = RequestGenerator::new(workload, num_accounts);
+    run_generated(client_pool, generator, total_calls, pace, num_accounts, run).await
+}
+
+/// Send the transfers of a recording in order at `pace`, at the recorded
+/// times when it has them and `pace.rate` isn't set
+pub async fn run_replay(
+    client_pool: &ClientPool,
+    recording: Recording,
+    pace: Pace,
+    run: &RunArgs,
+) -> Result<StressSummary> {
+    let recording = match pace.rate {
+        Some(_) => recording.untimed(),
+        None => recording,
+    };
+    let total_calls = recording.transfers.len();
+    let num_accounts = recording.max_account();
+    let workload = WorkloadArgs::default();
+    let generator = RequestGenerator::replaying(&workload, recording);
+    run_generated(client_pool, generator, total_calls, pace, num_accounts, run).await
+}
+
+/// The stress run proper, sending `total_calls` of whatever `generator`
+/// produces
+async fn run_generated(
+    client_pool: &ClientPool,
+    mut generator: RequestGenerator<'_>,
+    total_calls: usize,
+    pace: Pace,
+    num_accounts: u32,
+    run: &RunArgs,
+) -> Result<StressSummary> {
+    let parallel_calls = pace.parallel;
+    let load = describe_stress_load(total_calls, pace);
+    let metadata = RunMetadata::new(client_pool, num_accounts, load);
+    metadata.print();
+    println!();
+    let merged = results::load_histograms(&run.merge_hdr)?;
+    let results = match &run.results_dir {
+        Some(root) => Some(ResultsDir::create(root, metadata.run_id)?),
+        None => None,
+    };
     println!("Total invocations: {}", total_calls);
     println!("Max parallel requests: {}", parallel_calls);
+    if let Some(rate) = pace.rate {
+        println!("Rate limit: {}/sec", rate);
+    }
+    if let Some(ramp) = pace.ramp_in {
+        println!("Ramp-in: {}ms", ramp.as_millis());
+    }
+    println!("Clients: {}", client_pool.len());
+    println!("Workload: {}", generator.describe());
+    client_pool.tag_run(metadata.run_id, &generator.describe());
+    if generator.replay.is_none() {
+        println!("Seed: {}", generator.seed);
+    }
     println!();
 
-    let client_pool = client_pool.clone();
-
-    let m = MultiProgress::new();
+    let m = progress::multi();
 
     let concurrent = m.add(ProgressBar::new(parallel_calls as u64));
     let pb = m.add(ProgressBar::new(total_calls as u64));
@@ -29,403 +2922,995 @@ pub async fn run_stress_test(
             .progress_chars("=>-"),
     );
 
-    let start = Instant::now();
-    let mut success = 0;
-    let mut errors = 0;
-    let mut min_duration = u64::MAX;
-    let mut max_duration = 0u64;
-    let mut total_duration = 0u64;
-    let mut duration_count = 0usize;
-    let mut total_retries = 0u64;
-    let mut max_retries = 0u32;
-    let mut transactions_with_retries = 0usize;
-    let mut error_types: HashMap<String, usize> = HashMap::new();
-
-    let mut tasks = JoinSet::new();
-    let mut launched = 0;
-
-    loop {
-        let rem = parallel_calls - tasks.len();
-        if launched < total_calls && rem > 0 {
-            for _ in 0..rem {
-                let payer_id = rand::random::<u32>() % num_accounts + 1;
-                let mut payee_id = rand::random::<u32>() % num_accounts + 1;
-                while payee_id == payer_id {
-                    payee_id = rand::random::<u32>() % num_accounts + 1;
-                }
-
-                let pool = client_pool.clone();
-                tasks.spawn(async move {
-                    lambda::invoke::<_, tpcb::Response>(pool.get(), tpcb::Request {
-                        payer_id,
-                        payee_id,
-                        amount: 1,
-                    })
-                    .await
-                });
-                launched += 1;
-                concurrent.inc(1);
-            }
-        }
-
-        if let Some(result) = tasks.join_next().await {
-            concurrent.dec(1);
-
-            match result {
-                Ok(Ok(response)) => {
-                    if let Some(error) = &response.error {
-                        errors += 1;
-                        let error_key = if let Some(code) = &response.error_code {
-                            format!("{} ({})", error, code)
-                        } else {
-                            error.clone()
-                        };
-                        *error_types.entry(error_key).or_insert(0) += 1;
-                    } else {
-                        success += 1;
-                    }
+    let ClosedLoopOutcome {
+        mut stats,
+        elapsed,
+        aborted,
+        panicked,
+        ..
+    } = run_closed_loop(
+        client_pool,
+        &mut generator,
+        pace,
+        Budget::Calls(total_calls),
+        run,
+        &pb,
+        &concurrent,
+    )
+    .await;
 
-                    if let Some(duration) = response.duration {
-                        min_duration = min_duration.min(duration);
-                        max_duration = max_duration.max(duration);
-                        total_duration += duration;
-                        duration_count += 1;
-                    }
+    concurrent.finish_and_clear();
+    pb.finish_and_clear();
 
-                    if let Some(retries) = response.retries {
-                        total_retries += retries as u64;
-                        max_retries = max_retries.max(retries);
-                        if retries > 0 {
-                            transactions_with_retries += 1;
-                        }
-                    }
-                }
-                Ok(Err(err)) => {
-                    errors += 1;
-                    *error_types
-                        .entry(format!("Lambda invocation failed: {err}"))
-                        .or_insert(0) += 1;
-                }
-                _ => unreachable!("tasks should not be crashing"),
-            }
+    if let Some(merged) = &merged {
+        stats.merge_latency(merged)?;
+    }
+    stats.print("STATS", elapsed);
+    print_breakdown("Region", &client_pool.region_latency());
+    print_breakdown("Function", &client_pool.function_latency());
+    print_connection_latency(client_pool);
+    print_phase_breakdown(client_pool, &stats);
+    let traffic = client_pool.traffic();
+    print_traffic(&traffic);
+    print_client_distribution(client_pool);
+    generator.print_amount_stats();
+    generator.print_overdraw_stats();
 
-            pb.inc(1);
-        } else {
-            break;
-        }
+    let mut summary = stats.summary(elapsed, LoadModel::Closed);
+    summary.bytes_sent = traffic.sent_bytes;
+    summary.bytes_received = traffic.received_bytes;
+    summary.metadata = Some(metadata);
+    if let Some(results) = &results {
+        results.write(&summary, &stats.latency_histogram())?;
     }
 
-    concurrent.finish_and_clear();
-    pb.finish_and_clear();
+    if let Some(message) = panicked {
+        anyhow::bail!(
+            "Stopped after {} calls: a request task failed: {}",
+            stats.calls,
+            message
+        );
+    }
+    if let Some(rate) = aborted {
+        return Err(anyhow::anyhow!(
+            "Aborted after {} calls: error rate {:.2}% exceeded --max-error-rate",
+            stats.calls,
+            rate * 100.0
+        )
+        .context(Failure::Gate));
+    }
+    Ok(summary)
+}
 
-    let elapsed = start.elapsed();
+/// The load a stress run applies, as shown in run metadata
+pub fn describe_stress_load(total_calls: usize, pace: Pace) -> String {
+    let mut load = format!("{} calls, {} parallel", total_calls, pace.parallel);
+    if let Some(rate) = pace.rate {
+        load += &format!(", at most {}/sec", rate);
+    }
+    if let Some(ramp) = pace.ramp_in {
+        load += &format!(", ramped in over {}ms", ramp.as_millis());
+    }
+    load
+}
 
+pub async fn run_sustained_load(
+    client_pool: &ClientPool,
+    schedule: &RateSchedule,
+    num_accounts: u32,
+    workload: &WorkloadArgs,
+    run: &RunArgs,
+    options: &SustainedArgs,
+    cloudwatch: Option<&cloudwatch::Publisher>,
+) -> Result<StressSummary> {
+    check_accounts(num_accounts, workload)?;
+    println!("Sustained Load Generator (AIMD)");
+    println!("========================================");
+    let metadata = RunMetadata::new(client_pool, num_accounts, schedule.describe());
+    metadata.print();
     println!();
-    println!("{}", "=".repeat(60));
-    println!("STATS");
-    println!("{}", "=".repeat(60));
-    println!("Total calls:        {}", total_calls);
-    println!(
-        "Successful:         {} ({:.2}%)",
-        success,
-        (success as f64 / total_calls as f64) * 100.0
-    );
-    println!(
-        "Errors:             {} ({:.2}%)",
-        errors,
-        (errors as f64 / total_calls as f64) * 100.0
-    );
+    let merged = results::load_histograms(&run.merge_hdr)?;
+    let results = match &run.results_dir {
+        Some(root) => Some(ResultsDir::create(root, metadata.run_id)?),
+        None => None,
+    };
+    // Without --throughput-csv the per-second CSV goes straight into the
+    // results directory
+    let options = &match (&results, &options.throughput_csv) {
+        (Some(results), None) => SustainedArgs {
+            throughput_csv: Some(results.throughput_csv()),
+            ..options.clone()
+        },
+        _ => options.clone(),
+    };
+    println!("Target rate: {}", schedule.describe());
+    println!("Max in-flight: {}", max_in_flight(schedule));
+    if let Some(fixed) = options.fixed_concurrency {
+        println!("Concurrency: fixed at {} (AIMD off)", fixed);
+    }
+    if let Some(initial) = options.initial_concurrency {
+        let initial = (initial as usize).min(max_in_flight(schedule));
+        println!("Concurrency: AIMD, starting from {}", initial);
+    }
+    if options.occ_aware_aimd {
+        println!("Concurrency: AIMD, backing off on OCC errors (weight {})", options.occ_weight);
+    }
+    println!("Account pool: {}", num_accounts);
+    println!("Clients: {}", client_pool.len());
+    println!("Workload: {}", workload.describe());
+    println!("Load model: {}", options.model.describe());
+    if let Some(namespace) = &options.cloudwatch_namespace {
+        println!("CloudWatch: publishing to {} in {}", namespace, client_pool.region());
+    }
+    client_pool.tag_run(metadata.run_id, &workload.describe());
+
+    let mut generator = RequestGenerator::new(workload, num_accounts);
+    println!("Seed: {}", generator.seed);
     println!();
-    println!("Total time:         {:.2}s", elapsed.as_secs_f64());
-    println!(
-        "Throughput:         {:.0} calls/second",
-        total_calls as f64 / elapsed.as_secs_f64()
-    );
+    println!("Press Ctrl-C to stop. Type p or r and Enter to pause or resume, or");
+    println!("rate N to change the target rate...");
     println!();
 
-    if duration_count > 0 {
-        let avg_duration = total_duration as f64 / duration_count as f64;
-        println!("Lambda Execution Times:");
-        println!("  Min:                {:.2}ms", min_duration);
-        println!("  Max:                {:.2}ms", max_duration);
-        println!("  Avg:                {:.2}ms", avg_duration);
-        println!();
+    // Ctrl-C and --max-runtime handler
+    let controls = RunControls::new();
+    let running = controls.running.clone();
+    tokio::spawn(async move {
+        let why = stop_requested().await;
+        println!("\n{}, shutting down...", why);
+        running.store(false, Ordering::SeqCst);
+    });
+    spawn_console(controls.clone());
+
+    let outcome = run_rate_schedule(
+        client_pool,
+        &mut generator,
+        schedule,
+        run,
+        options,
+        &metadata,
+        cloudwatch,
+        &controls,
+    )
+    .await?;
+
+    let ScheduleOutcome {
+        mut stats,
+        elapsed,
+        crossing,
+        aborted,
+        scheduling_lag,
+        curve,
+        ..
+    } = outcome;
+    if let Some(merged) = &merged {
+        stats.merge_latency(merged)?;
     }
+    stats.print("FINAL STATS", elapsed);
+    stats.latency.print_phases(schedule);
+    print_scheduling_lag(&scheduling_lag);
+    print_breakdown("Region", &client_pool.region_latency());
+    print_breakdown("Function", &client_pool.function_latency());
+    print_connection_latency(client_pool);
+    print_phase_breakdown(client_pool, &stats);
+    let traffic = client_pool.traffic();
+    print_traffic(&traffic);
+    print_client_distribution(client_pool);
+    generator.print_amount_stats();
+    generator.print_overdraw_stats();
 
-    if total_retries > 0 {
-        let avg_retries = total_retries as f64 / total_calls as f64;
-        let retry_rate = (transactions_with_retries as f64 / total_calls as f64) * 100.0;
-        println!("OCC Retry Statistics:");
-        println!("  Total retries:      {}", total_retries);
-        println!("  Max retries:        {}", max_retries);
-        println!("  Avg retries/call:   {:.2}", avg_retries);
-        println!(
-            "  Transactions with retries: {} ({:.2}%)",
-            transactions_with_retries, retry_rate
-        );
+    if let Some(threshold) = options.p99_threshold_ms {
+        match crossing {
+            Some(c) => println!(
+                "p99 first exceeded {}ms at {}/sec ({}ms after {:.0}s)",
+                threshold, c.rate, c.p99_ms, c.elapsed_secs
+            ),
+            None => println!("p99 never exceeded {}ms", threshold),
+        }
         println!();
     }
 
-    if !error_types.is_empty() {
-        println!("Error Breakdown:");
-        let mut error_vec: Vec<_> = error_types.iter().collect();
-        error_vec.sort_by(|a, b| b.1.cmp(a.1));
-        for (error_type, count) in error_vec {
-            println!("  {}: {}", error_type, count);
+    let mut summary = stats.summary(elapsed, options.model);
+    summary.bytes_sent = traffic.sent_bytes;
+    summary.bytes_received = traffic.received_bytes;
+    if let Some(path) = &options.curve_csv {
+        write_curve_csv(path, &metadata, "offered_rate", &curve)?;
+    }
+    summary.metadata = Some(metadata);
+    if let Some(results) = &results {
+        if let Some(csv) = &options.throughput_csv
+            && *csv != results.throughput_csv()
+        {
+            std::fs::copy(csv, results.throughput_csv())?;
         }
-        println!();
+        results.write(&summary, &stats.latency_histogram())?;
     }
 
-    Ok(())
+    if let Some(rate) = aborted {
+        return Err(anyhow::anyhow!(
+            "Aborted after {} calls: error rate {:.2}% exceeded --max-error-rate",
+            stats.calls,
+            rate * 100.0
+        )
+        .context(Failure::Gate));
+    }
+    Ok(summary)
 }
 
-pub async fn run_sustained_load(
-    client_pool: &ClientPool,
-    invocations_per_sec: u32,
-    num_accounts: u32,
+/// Upper bound on requests in flight for a schedule, far above what its
+/// peak rate should need
+fn max_in_flight(schedule: &RateSchedule) -> usize {
+    (schedule.peak_rate() * 50) as usize
+}
+
+/// One second of a sustained run, as written to `--throughput-csv` and
+/// `--stream jsonl`
+#[derive(Serialize)]
+struct Tick {
+    run_id: Uuid,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    tags: BTreeMap<String, String>,
+    elapsed_sec: f64,
+    success_per_sec: usize,
+    error_per_sec: usize,
+    occ_per_sec: usize,
+    target: usize,
+    in_flight: usize,
+    /// Over the run so far
+    p50_ms: u64,
+    p99_ms: u64,
+    /// Database connections busy and idle, with `--direct`
+    pool_active: Option<u32>,
+    pool_idle: Option<u32>,
+}
+
+/// One offered rate, or one parallelism of a sweep, and how the system
+/// coped with it, as written to `--curve-csv` for plotting throughput
+/// against latency
+#[derive(Clone, Debug)]
+pub struct CurvePoint {
+    /// Rate offered, or in-flight requests allowed for a sweep
+    pub offered: u32,
+    pub throughput: f64,
+    pub p50_ms: u64,
+    pub p99_ms: u64,
+    pub error_rate: f64,
+}
+
+impl CurvePoint {
+    /// The point a run at `offered` makes
+    pub fn of(offered: u32, summary: &StressSummary) -> Self {
+        Self {
+            offered,
+            throughput: summary.throughput,
+            p50_ms: summary.p50_ms,
+            p99_ms: summary.p99_ms,
+            error_rate: summary.error_rate(),
+        }
+    }
+}
+
+/// Completions since the offered rate last changed
+struct CurveLevel {
+    offered_rate: u32,
+    started: Instant,
+    success: usize,
+    errors: usize,
+    latency: Histogram<u64>,
+}
+
+impl CurveLevel {
+    fn new(offered_rate: u32, sigfigs: u8) -> Self {
+        Self {
+            offered_rate,
+            started: Instant::now(),
+            success: 0,
+            errors: 0,
+            latency: Histogram::new(sigfigs).unwrap(),
+        }
+    }
+
+    /// The level as a point, unless nothing was offered
+    fn point(&self) -> Option<CurvePoint> {
+        if self.offered_rate == 0 {
+            return None;
+        }
+        let calls = self.success + self.errors;
+        Some(CurvePoint {
+            offered: self.offered_rate,
+            throughput: self.success as f64 / self.started.elapsed().as_secs_f64(),
+            p50_ms: self.latency.value_at_quantile(0.5),
+            p99_ms: self.latency.value_at_quantile(0.99),
+            error_rate: if calls > 0 {
+                self.errors as f64 / calls as f64
+            } else {
+                0.0
+            },
+        })
+    }
+}
+
+/// Write `points` to `path` as CSV, one row per offered rate or
+/// parallelism in ascending order under the column `offered`, ready for
+/// pandas or gnuplot
+pub fn write_curve_csv(
+    path: &Path,
+    metadata: &RunMetadata,
+    offered: &str,
+    points: &[CurvePoint],
 ) -> Result<()> {
-    println!("Sustained Load Generator (AIMD)");
-    println!("========================================");
-    println!("Target rate: {}/sec", invocations_per_sec);
-    println!("Max in-flight: {}", invocations_per_sec * 50);
-    println!("Account pool: {}", num_accounts);
-    println!();
-    println!("Press Ctrl-C to stop...");
+    let mut points = points.to_vec();
+    points.sort_by_key(|point| point.offered);
+    let mut w = BufWriter::new(
+        File::create(path).with_context(|| format!("Failed to create {}", path.display()))?,
+    );
+    writeln!(
+        w,
+        "{},{},achieved_throughput,p50_ms,p99_ms,error_rate",
+        metadata.csv_columns(),
+        offered
+    )?;
+    let run = metadata.csv_values();
+    for point in &points {
+        writeln!(
+            w,
+            "{},{},{:.1},{},{},{:.4}",
+            run,
+            point.offered,
+            point.throughput,
+            point.p50_ms,
+            point.p99_ms,
+            point.error_rate
+        )?;
+    }
+    w.flush()?;
+    println!("Wrote {} curve points to {}", points.len(), path.display());
     println!();
+    Ok(())
+}
+
+/// What a rate-scheduled run measured
+pub struct ScheduleOutcome {
+    pub stats: RunStats,
+    pub elapsed: Duration,
+    /// When one second's p99 first exceeded `--p99-threshold-ms`
+    crossing: Option<ThresholdCrossing>,
+    /// The error rate that tripped `--max-error-rate`, if it did
+    pub aborted: Option<f64>,
+    /// Microseconds each request was spawned after the schedule wanted it
+    /// sent
+    scheduling_lag: Histogram<u64>,
+    /// The concurrency target at the end of each second
+    pub targets: Vec<usize>,
+    /// Throughput and latency at each rate the schedule offered, in order
+    pub curve: Vec<CurvePoint>,
+}
+
+/// Flags a rate-scheduled run checks as it goes, so it can be steered from
+/// outside while it runs
+#[derive(Clone)]
+pub struct RunControls {
+    /// Cleared to stop the run, draining requests in flight
+    pub running: Arc<AtomicBool>,
+    /// While set, no new requests are sent and the schedule's clock stops
+    pub paused: Arc<AtomicBool>,
+    /// Rate to send at instead of what the schedule asks for
+    pub rate: Arc<Mutex<Option<u32>>>,
+}
+
+impl RunControls {
+    pub fn new() -> Self {
+        Self {
+            running: Arc::new(AtomicBool::new(true)),
+            paused: Arc::new(AtomicBool::new(false)),
+            rate: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    fn rate(&self) -> Option<u32> {
+        *self.rate.lock().unwrap()
+    }
+}
+
+impl Default for RunControls {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// How often a paused run with nothing in flight checks for a resume
+const PAUSE_POLL: Duration = Duration::from_millis(50);
+
+/// Read commands typed on stdin for the rest of the process: `pause` (or
+/// `p`), `resume` (or `r`), and `rate N` to send N/sec from now on whatever
+/// the schedule says, or `rate off` to follow the schedule again.
+///
+/// A plain thread rather than a task, since a blocking stdin read can't be
+/// cancelled and would keep the runtime from shutting down.
+fn spawn_console(controls: RunControls) {
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            match line.trim() {
+                "" => {}
+                "p" | "pause" => {
+                    controls.paused.store(true, Ordering::SeqCst);
+                    println!("Paused, draining in-flight requests; type r to resume");
+                }
+                "r" | "resume" => {
+                    controls.paused.store(false, Ordering::SeqCst);
+                    println!("Resumed");
+                }
+                "rate off" => {
+                    *controls.rate.lock().unwrap() = None;
+                    println!("Following the schedule again");
+                }
+                other => match other.strip_prefix("rate ").map(|n| n.trim().parse::<u32>()) {
+                    Some(Ok(rate)) => {
+                        *controls.rate.lock().unwrap() = Some(rate);
+                        println!("Target rate set to {}/sec", rate);
+                    }
+                    _ => println!(
+                        "Unknown command {:?}; try pause (p), resume (r), rate N or rate off",
+                        other
+                    ),
+                },
+            }
+        }
+    });
+}
 
+/// Send requests at the rate `schedule` asks for, with AIMD limiting how many
+/// are in flight, until the schedule finishes or `controls.running` is
+/// cleared. Pausing stops new requests, and the schedule, until resumed.
+///
+/// Progress is shown on a spinner that is cleared at the end. The run id
+/// and tags from `metadata` are written to every row of `--throughput-csv`.
+/// Each second also goes to `cloudwatch`, if given, which is finished at the
+/// end.
+pub async fn run_rate_schedule(
+    client_pool: &ClientPool,
+    generator: &mut RequestGenerator<'_>,
+    schedule: &RateSchedule,
+    run: &RunArgs,
+    options: &SustainedArgs,
+    metadata: &RunMetadata,
+    cloudwatch: Option<&cloudwatch::Publisher>,
+    controls: &RunControls,
+) -> Result<ScheduleOutcome> {
+    let run_id = metadata.run_id;
+    let max_in_flight = max_in_flight(schedule);
     let client_pool = client_pool.clone();
-    let max_in_flight = (invocations_per_sec * 50) as usize;
-
-    let running = Arc::new(AtomicBool::new(true));
-    let total_calls = Arc::new(AtomicUsize::new(0));
-    let success_count = Arc::new(AtomicUsize::new(0));
-    let error_count = Arc::new(AtomicUsize::new(0));
-    let dispatch_error_count = Arc::new(AtomicUsize::new(0)); // Failed to call Lambda - triggers AIMD backoff
-    let occ_error_count = Arc::new(AtomicUsize::new(0)); // OCC errors (40001)
-    let total_duration = Arc::new(AtomicU64::new(0));
-    let total_retries = Arc::new(AtomicU64::new(0));
+    let max_dispatch_retries = run.dispatch_retries;
+    let batch_size = generator.workload.batch_size as usize;
+    let ryow = RyowActivity::default();
+
     let in_flight = Arc::new(AtomicUsize::new(0));
-    let concurrency_target = Arc::new(AtomicUsize::new(10)); // Start small
+    let mut aimd = AimdController::new(max_in_flight);
+    if let Some(initial) = options.initial_concurrency {
+        aimd = aimd.with_initial_concurrency(initial as usize);
+    }
+    if options.occ_aware_aimd {
+        aimd = aimd.with_occ_weight(options.occ_weight);
+    }
+    let fixed_concurrency = options.fixed_concurrency.map(|n| n as usize);
+    let concurrency_target =
+        Arc::new(AtomicUsize::new(fixed_concurrency.unwrap_or(aimd.target())));
+    let current_rate = Arc::new(AtomicUsize::new(0)); // Rate the schedule currently asks for
 
-    // Channel for latency samples
-    let (latency_tx, mut latency_rx) = tokio::sync::mpsc::unbounded_channel::<u64>();
+    // Completed requests, aggregated by the AIMD task. Bounded so a stats
+    // task that falls behind can't grow memory for the length of the run;
+    // what it drops is left out of the stats and counted
+    let completions = Arc::new(BoundedQueue::<Completion>::new(
+        options.completion_buffer as usize,
+        options.completion_when_full,
+    ));
 
-    // Ctrl-C handler
-    let running_clone = running.clone();
-    tokio::spawn(async move {
-        tokio::signal::ctrl_c().await.ok();
-        println!("\nShutting down...");
-        running_clone.store(false, Ordering::SeqCst);
-    });
+    let mut throughput_csv = match &options.throughput_csv {
+        Some(path) => {
+            let mut w = BufWriter::new(File::create(path)?);
+            writeln!(
+                w,
+                "{},elapsed_sec,success_per_sec,error_per_sec,occ_per_sec,target,in_flight,p50_ms,p99_ms,pool_active,pool_idle",
+                metadata.csv_columns()
+            )?;
+            Some(w)
+        }
+        None => None,
+    };
 
     let start = Instant::now();
 
-    let m = MultiProgress::new();
-    let pb = m.add(ProgressBar::new_spinner());
-    pb.set_style(ProgressStyle::default_spinner().template("{spinner:.green} {msg}").unwrap());
+    // The spinner would garble a stream on stdout, and plain progress lines
+    // replace it
+    let pb = match (options.stream, options.progress_interval) {
+        (None, None) => progress::spinner(),
+        _ => ProgressBar::hidden(),
+    };
+    pb.set_style(ProgressStyle::default_spinner().template("{spinner:.green} {msg}")?);
 
     // AIMD controller - adjusts concurrency based on dispatch errors only
-    let aimd_running = running.clone();
-    let aimd_success = success_count.clone();
-    let aimd_errors = dispatch_error_count.clone(); // Only dispatch failures trigger AIMD backoff
-    let aimd_display_errors = error_count.clone(); // All errors for display
-    let aimd_occ_errors = occ_error_count.clone(); // OCC errors for display
     let aimd_target = concurrency_target.clone();
     let aimd_pb = pb.clone();
     let aimd_in_flight = in_flight.clone();
+    let aimd_rate = current_rate.clone();
+    // Set by the AIMD task when the error rate aborts the run, leaving the
+    // caller's `running` alone
+    let aborting = Arc::new(AtomicBool::new(false));
+    let aimd_aborting = aborting.clone();
+    let aimd_run = run.clone();
+    let aimd_clients = client_pool.clone();
+    let aimd_paused = controls.paused.clone();
+    let aimd_completions = completions.clone();
+    let p99_threshold_ms = options.p99_threshold_ms;
+    let publisher = cloudwatch.cloned();
+    let stream = options.stream;
+    let progress_interval = options.progress_interval;
+    let latency_window = options.latency_window as usize;
+    let coordinator = match &options.coordinator {
+        Some(addr) => Some(
+            coordinator::Reporter::connect(
+                addr,
+                run_id,
+                options.coordinator_buffer as usize,
+                options.coordinator_when_full,
+            )
+            .await?,
+        ),
+        None => None,
+    };
+    let delivery = coordinator.as_ref().map(coordinator::Reporter::delivery);
+    let mut sparkline = options.sparkline.then(progress::Sparkline::new);
+    let mut stats = RunStats::new(run);
+    let csv_run = metadata.csv_values();
+    let tags = metadata.tags.clone();
 
     let aimd_handle = tokio::spawn(async move {
-        use hdrhistogram::Histogram;
-        let mut hist: Histogram<u64> = Histogram::new(3).unwrap();
+        let mut hist: Histogram<u64> = Histogram::new(aimd_run.hdr_sigfigs).unwrap();
+        // Latency of just the last second, so threshold crossings aren't
+        // masked by the whole run's history
+        let mut tick_hist: Histogram<u64> = Histogram::new(aimd_run.hdr_sigfigs).unwrap();
+        let mut window = LatencyWindow::new(latency_window);
+        let mut crossing = None;
+        let mut aborted = None;
         let mut last_success = 0usize;
         let mut last_errors = 0usize;
-        let mut last_good_concurrency = 10usize;
+        let mut last_display_errors = 0usize;
+        let mut last_occ_errors = 0usize;
+        let mut targets = Vec::new();
+        let mut curve = Vec::new();
+        let mut level: Option<CurveLevel> = None;
+        // Successes and seconds as of the last --progress-interval line
+        let mut progress_success = 0usize;
+        let mut progress_secs = 0u64;
         let mut interval = tokio::time::interval(Duration::from_secs(1));
 
-        while aimd_running.load(Ordering::SeqCst) {
-            interval.tick().await;
+        loop {
+            // Record completions as they arrive. The queue runs dry once the
+            // main loop has finished it after every task, at which point
+            // nothing more can arrive and the final partial second is
+            // reported.
+            let is_final = tokio::select! {
+                completion = aimd_completions.pop() => match completion {
+                    Some(completion) => {
+                        if let Ok(reply) = &completion.result
+                            && let Some(err) = &reply.error
+                            && !reply.is_occ_error()
+                        {
+                            tracing::warn!(error = %err, code = ?reply.error_code, "Lambda error");
+                        }
+                        if let Some(d) = completion.latency()
+                            && !stats.warming_up()
+                        {
+                            let _ = hist.record(d);
+                            let _ = tick_hist.record(d);
+                        }
+                        stats.record(&completion);
+                        if stats.retry_budget_spent()
+                            && !aimd_aborting.swap(true, Ordering::SeqCst)
+                        {
+                            progress::println(
+                                &aimd_pb,
+                                "Retry budget exhausted, shutting down...",
+                            );
+                        }
+                        continue;
+                    }
+                    None => true,
+                },
+                _ = interval.tick() => false,
+            };
+
+
+            if aborted.is_none()
+                && let Some(rate) = stats.error_rate_exceeded(&aimd_run)
+            {
+                progress::println(
+                    &aimd_pb,
+                    format!(
+                        "Error rate {:.2}% exceeded the limit, shutting down...",
+                        rate * 100.0
+                    ),
+                );
+                aborted = Some(rate);
+                aimd_aborting.store(true, Ordering::SeqCst);
+            }
 
-            // Drain all pending latency samples
-            while let Ok(latency) = latency_rx.try_recv() {
-                let _ = hist.record(latency);
+            if let Some(threshold) = p99_threshold_ms
+                && crossing.is_none()
+                && !tick_hist.is_empty()
+                && tick_hist.value_at_quantile(0.99) > threshold
+            {
+                let rate = aimd_rate.load(Ordering::Relaxed);
+                let p99_ms = tick_hist.value_at_quantile(0.99);
+                progress::println(
+                    &aimd_pb,
+                    format!("p99 crossed {}ms at {}/sec", threshold, rate),
+                );
+                crossing = Some(ThresholdCrossing {
+                    elapsed_secs: start.elapsed().as_secs_f64(),
+                    rate,
+                    p99_ms,
+                });
             }
+            if let Some(reporter) = &coordinator {
+                reporter.send(
+                    stats.success - last_success,
+                    stats.errors - last_display_errors,
+                    stats.occ_errors - last_occ_errors,
+                    &tick_hist,
+                )
+                .await;
+            }
+            if let Some(publisher) = &publisher {
+                publisher
+                    .send(
+                        stats.success - last_success,
+                        stats.errors - last_display_errors,
+                        stats.occ_errors - last_occ_errors,
+                        &tick_hist,
+                    )
+                    .await;
+            }
+            // A ramp offers a new rate every second, phases one per phase
+            let offered_rate = aimd_rate.load(Ordering::Relaxed) as u32;
+            if level.as_ref().is_some_and(|l| l.offered_rate != offered_rate) {
+                curve.extend(level.take().and_then(|l| l.point()));
+            }
+            let current = level
+                .get_or_insert_with(|| CurveLevel::new(offered_rate, aimd_run.hdr_sigfigs));
+            current.success += stats.success - last_success;
+            current.errors += stats.errors - last_display_errors;
+            let _ = current.latency.add(&tick_hist);
+            if is_final {
+                curve.extend(level.take().and_then(|l| l.point()));
+            }
+            window.push(&tick_hist);
+            tick_hist.reset();
 
-            let current_success = aimd_success.load(Ordering::Relaxed);
-            let current_dispatch_errors = aimd_errors.load(Ordering::Relaxed);
-            let display_errors = aimd_display_errors.load(Ordering::Relaxed);
-            let occ_errors = aimd_occ_errors.load(Ordering::Relaxed);
-            let success_this_sec = current_success - last_success;
-            let dispatch_errors_this_sec = current_dispatch_errors - last_errors;
+            // Only dispatch failures and timeouts, the signs of overload,
+            // trigger AIMD backoff; failed invokes and error replies, which
+            // the Lambda answered, are only displayed
+            let success_this_sec = stats.success - last_success;
+            let dispatch_errors_this_sec = stats.dispatch_errors + stats.timeouts - last_errors;
             let flying = aimd_in_flight.load(Ordering::Relaxed);
-            let current_target = aimd_target.load(Ordering::Relaxed);
-
-            // AIMD: back off only on dispatch failures (couldn't reach Lambda)
-            // Any Lambda response (success or error) means we can increase
-            let new_target = if dispatch_errors_this_sec == 0 && success_this_sec > 0 {
-                last_good_concurrency = current_target;
-                (current_target + 10).min(max_in_flight)
-            } else if dispatch_errors_this_sec > 0 {
-                last_good_concurrency.max(10)
-            } else {
-                current_target
+
+            // A paused second says nothing about capacity
+            let paused = aimd_paused.load(Ordering::Relaxed);
+            let new_target = match fixed_concurrency {
+                Some(fixed) => fixed,
+                None if paused => aimd.target(),
+                None => aimd.observe(
+                    success_this_sec as u64,
+                    dispatch_errors_this_sec as u64,
+                    (stats.occ_errors - last_occ_errors) as u64,
+                ),
             };
             aimd_target.store(new_target, Ordering::Relaxed);
+            if !is_final {
+                targets.push(new_target);
+            }
 
             let p50 = hist.value_at_quantile(0.5);
             let p99 = hist.value_at_quantile(0.99);
+            // What the display shows: the window, not the whole run
+            let recent = window.merged().unwrap_or_else(|| hist.clone());
+            let (recent_p50, recent_p99) =
+                (recent.value_at_quantile(0.5), recent.value_at_quantile(0.99));
 
+            let pool = aimd_clients.db_pool_metrics();
+            let pool_status = pool
+                .map(|pool| format!(" | Pool: {} active {} idle", pool.active, pool.idle))
+                .unwrap_or_default();
+            let chart = match sparkline.as_mut() {
+                Some(sparkline) => {
+                    if !is_final {
+                        sparkline.push(success_this_sec as u64);
+                    }
+                    format!("{} ", sparkline.render())
+                }
+                None => String::new(),
+            };
             aimd_pb.set_message(format!(
-                "{}/s | p50: {}ms p99: {}ms | Err: {} OCC: {} | Target: {} | Inflight: {}",
-                success_this_sec, p50, p99, display_errors, occ_errors, new_target, flying
+                "{}{}{}/s | {}s p50: {}ms p99: {}ms | Err: {} OCC: {} | Target: {} | Inflight: {}{}",
+                chart,
+                if paused {
+                    "PAUSED | "
+                } else if stats.warming_up() {
+                    "WARMUP | "
+                } else {
+                    ""
+                },
+                success_this_sec,
+                latency_window,
+                recent_p50,
+                recent_p99,
+                stats.errors,
+                stats.occ_errors,
+                new_target,
+                flying,
+                pool_status
             ));
 
-            last_success = current_success;
-            last_errors = current_dispatch_errors;
+            let tick = Tick {
+                run_id,
+                tags: tags.clone(),
+                elapsed_sec: start.elapsed().as_secs_f64(),
+                success_per_sec: success_this_sec,
+                error_per_sec: stats.errors - last_display_errors,
+                occ_per_sec: stats.occ_errors - last_occ_errors,
+                target: new_target,
+                in_flight: flying,
+                p50_ms: p50,
+                p99_ms: p99,
+                pool_active: pool.map(|pool| pool.active),
+                pool_idle: pool.map(|pool| pool.idle),
+            };
+
+            if stream == Some(StreamFormat::Jsonl) {
+                match serde_json::to_string(&tick) {
+                    Ok(line) => println!("{}", line),
+                    Err(err) => tracing::warn!(%err, "failed to serialize tick"),
+                }
+            }
+
+            let secs = start.elapsed().as_secs();
+            if let Some(every) = progress_interval
+                && !is_final
+                && secs >= progress_secs + every
+            {
+                let per_sec =
+                    (stats.success - progress_success) as f64 / (secs - progress_secs) as f64;
+                println!(
+                    "[{}s] calls: {} | {:.0}/s | {}s p99: {}ms | errors: {} | target: {}",
+                    secs, stats.calls, per_sec, latency_window, recent_p99, stats.errors, new_target
+                );
+                progress_success = stats.success;
+                progress_secs = secs;
+            }
+
+            if let Some(w) = throughput_csv.as_mut() {
+                let optional = |v: Option<u32>| v.map(|v| v.to_string()).unwrap_or_default();
+                let written = writeln!(
+                    w,
+                    "{},{:.3},{},{},{},{},{},{},{},{},{}",
+                    csv_run,
+                    tick.elapsed_sec,
+                    tick.success_per_sec,
+                    tick.error_per_sec,
+                    tick.occ_per_sec,
+                    tick.target,
+                    tick.in_flight,
+                    tick.p50_ms,
+                    tick.p99_ms,
+                    optional(tick.pool_active),
+                    optional(tick.pool_idle)
+                )
+                .and_then(|_| w.flush());
+                if let Err(err) = written {
+                    tracing::warn!(%err, "failed to write throughput CSV, disabling it");
+                    throughput_csv = None;
+                }
+            }
+
+            last_success = stats.success;
+            last_errors = stats.dispatch_errors + stats.timeouts;
+            last_display_errors = stats.errors;
+            last_occ_errors = stats.occ_errors;
+
+            if is_final {
+                break;
+            }
         }
+
+        (stats, crossing, aborted, targets, curve)
     });
 
     // Main loop - spawn tasks up to concurrency target, rate limited
     let mut tasks = JoinSet::new();
     let mut spawned_this_sec = 0usize;
+    let mut spawned = 0usize;
     let mut last_reset = Instant::now();
-    let target_rate = invocations_per_sec as usize;
+    let mut scheduling_lag: Histogram<u64> = Histogram::new(run.hdr_sigfigs)?;
+    let mut current_phase = None;
+    // Open model: when the next request should be sent, whether or not the
+    // concurrency target lets it go yet
+    let mut next_send = start;
+    // Time spent paused, which the schedule doesn't count
+    let mut paused_for = Duration::ZERO;
+    let mut paused_at: Option<Instant> = None;
+    let keep_going =
+        || controls.running.load(Ordering::SeqCst) && !aborting.load(Ordering::SeqCst);
 
-    while running.load(Ordering::SeqCst) {
+    while keep_going() {
         // Reset rate limit counter every second
         if last_reset.elapsed() >= Duration::from_secs(1) {
             spawned_this_sec = 0;
             last_reset = Instant::now();
         }
 
+        if let Some(max_calls) = options.max_calls
+            && spawned >= max_calls
+        {
+            progress::println(&pb, format!("Sent {} calls, shutting down...", max_calls));
+            break;
+        }
+
+        if controls.paused.load(Ordering::SeqCst) {
+            paused_at.get_or_insert_with(Instant::now);
+            if tasks.is_empty() {
+                // Drained: join_next would return at once, so wait for a
+                // resume or stop rather than spin
+                tokio::time::sleep(PAUSE_POLL).await;
+            } else {
+                let _ = tokio::time::timeout(Duration::from_millis(10), tasks.join_next()).await;
+            }
+            continue;
+        }
+        if completions.when_full() == WhenFull::Block && !completions.has_room() {
+            // The stats task is behind: hold off sending until it catches
+            // up rather than lose what it hasn't recorded
+            let _ = tokio::time::timeout(Duration::from_millis(10), completions.room()).await;
+            continue;
+        }
+        if let Some(at) = paused_at.take() {
+            // Pick up the pacing where it left off rather than sending the
+            // pause's worth of requests at once
+            paused_for += at.elapsed();
+            next_send += at.elapsed();
+        }
+
+        let Some((phase, rate)) = schedule.position(start.elapsed() - paused_for) else {
+            progress::println(&pb, "Schedule complete, shutting down...");
+            break;
+        };
+        if schedule.is_phased() && current_phase != Some(phase) {
+            let label = schedule.phase_label(phase);
+            progress::println(&pb, format!("{}: {}/sec", label, rate));
+            current_phase = Some(phase);
+        }
+        let rate = controls.rate().unwrap_or(rate);
+        let target_rate = rate as usize;
+        current_rate.store(target_rate, Ordering::Relaxed);
+
         let target = concurrency_target.load(Ordering::Relaxed);
         let current = in_flight.load(Ordering::Relaxed);
 
-        // Spawn tasks up to concurrency target AND rate limit
-        let to_spawn = target.saturating_sub(current)
-            .min(target_rate.saturating_sub(spawned_this_sec));
+        let to_spawn = match options.model {
+            // Spawn tasks up to concurrency target AND rate limit
+            LoadModel::Closed => target.saturating_sub(current)
+                .min(target_rate.saturating_sub(spawned_this_sec)),
+            // Spawn every request that is due, up to the concurrency target;
+            // the rest stay due and are charged for the wait
+            LoadModel::Open => {
+                let now = Instant::now();
+                if rate == 0 {
+                    next_send = now;
+                    0
+                } else if now < next_send {
+                    0
+                } else {
+                    let due = ((now - next_send).as_secs_f64() * rate as f64) as usize + 1;
+                    due.min(target.saturating_sub(current))
+                }
+            }
+        };
 
+        let to_spawn = match options.max_calls {
+            Some(max_calls) => to_spawn.min(max_calls - spawned),
+            None => to_spawn,
+        };
         for _ in 0..to_spawn {
-            if !running.load(Ordering::SeqCst) { break; }
-
-            let payer_id = rand::random::<u32>() % num_accounts + 1;
-            let mut payee_id = rand::random::<u32>() % num_accounts + 1;
-            while payee_id == payer_id {
-                payee_id = rand::random::<u32>() % num_accounts + 1;
-            }
+            if !keep_going() { break; }
 
+            let ops = generator.next_batch(batch_size);
             let pool = client_pool.clone();
-            let total = total_calls.clone();
-            let success = success_count.clone();
-            let errors = error_count.clone();
-            let dispatch_errors = dispatch_error_count.clone();
-            let occ_errors = occ_error_count.clone();
-            let duration_sum = total_duration.clone();
-            let retries_sum = total_retries.clone();
+            let ryow = ryow.clone();
             let flying = in_flight.clone();
-            let lat_tx = latency_tx.clone();
+            let queue = completions.clone();
+            // When the schedule wanted this request sent: its slot in the
+            // open model's pacing, or its even share of the current second
+            let intended_send = match options.model {
+                LoadModel::Open => {
+                    let intended = next_send;
+                    next_send += Duration::from_secs_f64(1.0 / rate as f64);
+                    intended
+                }
+                LoadModel::Closed => {
+                    last_reset + Duration::from_secs_f64(spawned_this_sec as f64 / rate as f64)
+                }
+            };
+            let lag = Instant::now().saturating_duration_since(intended_send);
+            let _ = scheduling_lag.record(lag.as_micros() as u64);
+            let intended = (options.model == LoadModel::Open).then_some(intended_send);
 
             flying.fetch_add(1, Ordering::Relaxed);
 
             tasks.spawn(async move {
-                let result = lambda::invoke::<_, tpcb::Response>(pool.get(), tpcb::Request {
-                    payer_id, payee_id, amount: 1,
-                }).await;
-
+                let completions =
+                    execute_with_retries(&pool, &ryow, ops, phase, max_dispatch_retries).await;
                 flying.fetch_sub(1, Ordering::Relaxed);
-                total.fetch_add(1, Ordering::Relaxed);
-
-                match result {
-                    Ok(response) => {
-                        // Got a response from Lambda - this is good for AIMD
-                        if let Some(ref err) = response.error {
-                            errors.fetch_add(1, Ordering::Relaxed);
-                            if response.error_code.as_deref() == Some("40001") {
-                                occ_errors.fetch_add(1, Ordering::Relaxed);
-                            } else {
-                                tracing::warn!(error = %err, code = ?response.error_code, "Lambda error");
-                            }
-                        } else {
-                            success.fetch_add(1, Ordering::Relaxed);
-                        }
-                        if let Some(d) = response.duration {
-                            duration_sum.fetch_add(d, Ordering::Relaxed);
-                            let _ = lat_tx.send(d);
-                        }
-                        if let Some(r) = response.retries { retries_sum.fetch_add(r as u64, Ordering::Relaxed); }
-                    }
-                    Err(_) => {
-                        // Failed to call Lambda - triggers AIMD backoff
-                        errors.fetch_add(1, Ordering::Relaxed);
-                        dispatch_errors.fetch_add(1, Ordering::Relaxed);
-                    }
+                let corrected_latency = intended.map(|t| t.elapsed().as_millis() as u64);
+                for completion in completions {
+                    queue
+                        .push(Completion {
+                            corrected_latency,
+                            ..completion
+                        })
+                        .await;
                 }
             });
             spawned_this_sec += 1;
+            spawned += 1;
         }
 
         // Process completed tasks
-        match tokio::time::timeout(Duration::from_millis(10), tasks.join_next()).await {
-            Ok(Some(_)) => {}
-            _ => {}
-        }
+        let _ = tokio::time::timeout(Duration::from_millis(10), tasks.join_next()).await;
     }
 
     // Drain remaining tasks
     pb.set_message("Waiting for in-flight requests to complete...");
-    while tasks.join_next().await.is_some() {}
-
-    aimd_handle.abort();
-    pb.finish_and_clear();
-
-    let elapsed = start.elapsed();
-    let final_calls = total_calls.load(Ordering::Relaxed);
-    let final_success = success_count.load(Ordering::Relaxed);
-    let final_errors = error_count.load(Ordering::Relaxed);
-    let final_duration = total_duration.load(Ordering::Relaxed);
-    let final_retries = total_retries.load(Ordering::Relaxed);
-
-    println!();
-    println!("{}", "=".repeat(60));
-    println!("FINAL STATS");
-    println!("{}", "=".repeat(60));
-    println!("Total calls:        {}", final_calls);
-    println!(
-        "Successful:         {} ({:.2}%)",
-        final_success,
-        if final_calls > 0 {
-            (final_success as f64 / final_calls as f64) * 100.0
-        } else {
-            0.0
-        }
-    );
-    println!(
-        "Errors:             {} ({:.2}%)",
-        final_errors,
-        if final_calls > 0 {
-            (final_errors as f64 / final_calls as f64) * 100.0
-        } else {
-            0.0
-        }
-    );
-    println!();
-    println!("Total time:         {:.2}s", elapsed.as_secs_f64());
-    println!(
-        "Throughput:         {:.0} calls/second",
-        if elapsed.as_secs_f64() > 0.0 {
-            final_calls as f64 / elapsed.as_secs_f64()
-        } else {
-            0.0
+    let drain = async { while tasks.join_next().await.is_some() {} };
+    let mut incomplete = 0;
+    match run.drain_timeout {
+        Some(secs) => {
+            if tokio::time::timeout(Duration::from_secs(secs), drain).await.is_err() {
+                incomplete = tasks.len();
+                // Aborted tasks never push, so finishing the queue still
+                // ends the AIMD task
+                tasks.abort_all();
+            }
         }
-    );
-
-    if final_calls > 0 {
-        let avg_duration = final_duration as f64 / final_calls as f64;
-        println!();
-        println!("Avg Lambda Time:    {:.2}ms", avg_duration);
-        println!("Total OCC Retries:  {}", final_retries);
+        None => drain.await,
     }
 
-    println!();
+    // Finish the queue so the AIMD task records the final partial second
+    // and exits
+    completions.finish();
+    let (mut stats, crossing, aborted, targets, curve) = aimd_handle.await?;
+    stats.incomplete = incomplete;
+    pb.finish_and_clear();
+    if completions.dropped() > 0 {
+        println!(
+            "Completions: {} dropped due to backpressure, so the stats undercount",
+            completions.dropped()
+        );
+    }
+    if let Some(delivery) = delivery {
+        delivery.print();
+    }
+    if let Some(cloudwatch) = cloudwatch {
+        cloudwatch.finish().await;
+    }
 
-    Ok(())
+    Ok(ScheduleOutcome {
+        stats,
+        elapsed: start.elapsed(),
+        crossing,
+        aborted,
+        scheduling_lag,
+        targets,
+        curve,
+    })
 }
@@ -0,0 +1,308 @@
+use crate::metadata::RunMetadata;
+use crate::failure::Failure;
+use crate::stress::LoadModel;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Headline results of a stress or sustained-load run
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StressSummary {
+    pub total_calls: usize,
+    pub success: usize,
+    pub errors: usize,
+    /// OCC conflicts (SQLSTATE 40001), counted in `errors` too
+    #[serde(default)]
+    pub occ_errors: usize,
+    /// Whether `failure_rate` leaves OCC conflicts out, for `--occ-as-success`
+    #[serde(default)]
+    pub occ_as_success: bool,
+    /// Errors caused by the request, such as an overdrawn or missing account
+    #[serde(default)]
+    pub client_errors: usize,
+    /// Errors serving a valid request, such as throttling or dispatch failures
+    #[serde(default)]
+    pub server_errors: usize,
+    /// Requests given up on after the invocation timeout, counted in
+    /// `errors` and `server_errors` too
+    #[serde(default)]
+    pub timeouts: usize,
+    /// Error replies from a Lambda that stopped at the request's
+    /// `deadline_ms` rather than leave the client to time out
+    #[serde(default)]
+    pub deadline_aborts: usize,
+    pub elapsed_secs: f64,
+    pub throughput: f64,
+    pub p50_ms: u64,
+    pub p99_ms: u64,
+    pub total_retries: u64,
+    /// Calls whose transaction needed at least one OCC retry
+    pub retried_calls: usize,
+    /// Successful transfers that should each have written a ledger row
+    #[serde(default)]
+    pub applied_transfers: usize,
+    /// `--workload ryow` reads that didn't show the transfer just made
+    #[serde(default)]
+    pub consistency_violations: usize,
+    /// Payload bytes sent to and received from the Lambda
+    #[serde(default)]
+    pub bytes_sent: u64,
+    #[serde(default)]
+    pub bytes_received: u64,
+    /// How latency was measured; open-model latencies include queueing
+    #[serde(default)]
+    pub model: LoadModel,
+    /// Which run produced these results
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<RunMetadata>,
+}
+
+impl StressSummary {
+    pub fn error_rate(&self) -> f64 {
+        ratio(self.errors, self.total_calls)
+    }
+
+    /// The share of calls that failed, leaving out OCC conflicts if the
+    /// run counted them as successes
+    pub fn failure_rate(&self) -> f64 {
+        let failures = match self.occ_as_success {
+            true => self.errors - self.occ_errors,
+            false => self.errors,
+        };
+        ratio(failures, self.total_calls)
+    }
+
+    pub fn client_error_rate(&self) -> f64 {
+        ratio(self.client_errors, self.total_calls)
+    }
+
+    pub fn server_error_rate(&self) -> f64 {
+        ratio(self.server_errors, self.total_calls)
+    }
+
+    pub fn occ_retry_rate(&self) -> f64 {
+        ratio(self.retried_calls, self.total_calls)
+    }
+
+    /// Fail if p99 exceeded `max_p99_ms`, when given
+    pub fn check_sla(&self, max_p99_ms: Option<u64>) -> Result<()> {
+        if let Some(max) = max_p99_ms
+            && self.p99_ms > max
+        {
+            println!("SLA VIOLATED: p99 {}ms exceeds {}ms", self.p99_ms, max);
+            return Err(anyhow::anyhow!("p99 {}ms exceeded --max-p99-ms {}", self.p99_ms, max)
+                .context(Failure::Gate));
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json)
+            .with_context(|| format!("Failed to write summary to {}", path.display()))?;
+        println!("Summary written to {}", path.display());
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read summary from {}", path.display()))?;
+        serde_json::from_str(&json)
+            .with_context(|| format!("Failed to parse summary in {}", path.display()))
+    }
+}
+
+/// `path` with `-n` added to its file stem, to save the n-th of several
+/// runs' summaries: summary.json becomes summary-2.json
+pub fn numbered(path: &Path, n: u32) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{}-{}.{}", stem, n, ext.to_string_lossy()),
+        None => format!("{}-{}", stem, n),
+    };
+    path.with_file_name(name)
+}
+
+/// Print each of a repeated test's runs, then the mean and standard
+/// deviation of their throughput and latency, as one run alone is noisy
+pub fn print_repeats(summaries: &[StressSummary]) {
+    println!("{}", "=".repeat(60));
+    println!("ACROSS {} RUNS", summaries.len());
+    println!("{}", "=".repeat(60));
+    println!(
+        "{:>8} {:>14} {:>10} {:>10} {:>10}",
+        "Run", "Throughput/s", "p50 ms", "p99 ms", "Errors"
+    );
+    for (i, summary) in summaries.iter().enumerate() {
+        println!(
+            "{:>8} {:>14.0} {:>10} {:>10} {:>9.2}%",
+            i + 1,
+            summary.throughput,
+            summary.p50_ms,
+            summary.p99_ms,
+            summary.failure_rate() * 100.0
+        );
+    }
+    let throughput: Vec<f64> = summaries.iter().map(|s| s.throughput).collect();
+    let p50: Vec<f64> = summaries.iter().map(|s| s.p50_ms as f64).collect();
+    let p99: Vec<f64> = summaries.iter().map(|s| s.p99_ms as f64).collect();
+    let errors: Vec<f64> = summaries.iter().map(|s| s.failure_rate() * 100.0).collect();
+    for (label, stat) in [("Mean", mean as fn(&[f64]) -> f64), ("Stddev", stddev)] {
+        println!(
+            "{:>8} {:>14.0} {:>10.1} {:>10.1} {:>9.2}%",
+            label,
+            stat(&throughput),
+            stat(&p50),
+            stat(&p99),
+            stat(&errors)
+        );
+    }
+    println!();
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// Sample standard deviation, or 0 for a single value
+fn stddev(values: &[f64]) -> f64 {
+    if values.len() < 2 {
+        return 0.0;
+    }
+    let mean = mean(values);
+    let variance =
+        values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (values.len() - 1) as f64;
+    variance.sqrt()
+}
+
+fn ratio(n: usize, total: usize) -> f64 {
+    if total > 0 {
+        n as f64 / total as f64
+    } else {
+        0.0
+    }
+}
+
+/// Print a side-by-side comparison of two summaries.
+///
+/// Fails if `max_p99_regression` (a percentage) is set and the candidate's
+/// p99 is worse than the baseline's by more than that.
+pub fn compare(
+    baseline_path: &Path,
+    candidate_path: &Path,
+    max_p99_regression: Option<f64>,
+) -> Result<()> {
+    let baseline = StressSummary::load(baseline_path)?;
+    let candidate = StressSummary::load(candidate_path)?;
+
+    println!("Baseline:  {}", baseline_path.display());
+    println!("Candidate: {}", candidate_path.display());
+    if baseline.model != candidate.model {
+        println!(
+            "Warning: latencies were measured differently ({:?} vs {:?} load model)",
+            baseline.model, candidate.model
+        );
+    }
+    println!();
+    println!(
+        "{:<16} {:>14} {:>14} {:>10}",
+        "Metric", "Baseline", "Candidate", "Delta"
+    );
+    println!("{}", "=".repeat(64));
+
+    let rows = [
+        (
+            "Throughput",
+            baseline.throughput,
+            candidate.throughput,
+            "/s",
+            true,
+        ),
+        (
+            "p50 latency",
+            baseline.p50_ms as f64,
+            candidate.p50_ms as f64,
+            "ms",
+            false,
+        ),
+        (
+            "p99 latency",
+            baseline.p99_ms as f64,
+            candidate.p99_ms as f64,
+            "ms",
+            false,
+        ),
+        (
+            "Error rate",
+            baseline.error_rate() * 100.0,
+            candidate.error_rate() * 100.0,
+            "%",
+            false,
+        ),
+        (
+            "  client",
+            baseline.client_error_rate() * 100.0,
+            candidate.client_error_rate() * 100.0,
+            "%",
+            false,
+        ),
+        (
+            "  server",
+            baseline.server_error_rate() * 100.0,
+            candidate.server_error_rate() * 100.0,
+            "%",
+            false,
+        ),
+        (
+            "OCC retry rate",
+            baseline.occ_retry_rate() * 100.0,
+            candidate.occ_retry_rate() * 100.0,
+            "%",
+            false,
+        ),
+    ];
+
+    for (name, base, cand, unit, higher_is_better) in rows {
+        let delta = percent_delta(base, cand);
+        let verdict = if cand == base {
+            "same"
+        } else if (cand > base) == higher_is_better {
+            "better"
+        } else {
+            "worse"
+        };
+        println!(
+            "{:<16} {:>12.2}{:<2} {:>12.2}{:<2} {:>10} {}",
+            name,
+            base,
+            unit,
+            cand,
+            unit,
+            delta.map_or("n/a".to_string(), |d| format!("{:+.2}%", d)),
+            verdict
+        );
+    }
+    println!();
+
+    if let Some(limit) = max_p99_regression {
+        let regression = percent_delta(baseline.p99_ms as f64, candidate.p99_ms as f64);
+        if let Some(regression) = regression.filter(|r| *r > limit) {
+            return Err(anyhow::anyhow!(
+                "p99 regressed by {:.2}% (limit {:.2}%)",
+                regression,
+                limit
+            )
+            .context(Failure::Gate));
+        }
+    }
+
+    Ok(())
+}
+
+fn percent_delta(base: f64, cand: f64) -> Option<f64> {
+    if base == 0.0 {
+        None
+    } else {
+        Some((cand - base) / base * 100.0)
+    }
+}
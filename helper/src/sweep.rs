@@ -0,0 +1,433 @@
+use crate::cli::{RunArgs, SustainedArgs, WorkloadArgs};
+use crate::failure::Failure;
+use crate::lambda::ClientPool;
+use crate::metadata::RunMetadata;
+use crate::progress;
+use crate::stress::{
+    self, Budget, CurvePoint, LoadModel, Pace, Phase, RateSchedule, RequestGenerator,
+    RunControls,
+};
+use crate::summary::StressSummary;
+use anyhow::Result;
+use indicatif::{ProgressBar, ProgressStyle};
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+/// How to search for the parallelism with the best throughput
+pub struct SweepConfig {
+    /// Parallelism of the first step
+    pub start: usize,
+    /// Highest parallelism to try
+    pub max: usize,
+    /// How long each step runs
+    pub step_secs: u64,
+    /// Stop once a step improves throughput by less than this percentage
+    pub plateau_pct: f64,
+    /// Stop once a step's p99 exceeds this
+    pub p99_limit_ms: Option<u64>,
+    /// Where to write each step's throughput and latency, keyed by parallelism
+    pub curve_csv: Option<PathBuf>,
+}
+
+/// Run fixed-duration bursts at doubling parallelism until throughput stops
+/// improving or latency gets too high, then print what each step achieved
+pub async fn run_sweep(
+    client_pool: &ClientPool,
+    num_accounts: u32,
+    workload: &WorkloadArgs,
+    config: &SweepConfig,
+) -> Result<()> {
+    stress::check_accounts(num_accounts, workload)?;
+    println!("Concurrency Sweep");
+    println!("========================================");
+    let metadata = RunMetadata::new(
+        client_pool,
+        num_accounts,
+        format!("{} to {} parallel", config.start, config.max),
+    );
+    metadata.print();
+    println!();
+    println!(
+        "Parallelism: {} doubling up to {}, {}s per step",
+        config.start, config.max, config.step_secs
+    );
+    println!("Clients: {}", client_pool.len());
+    println!("Workload: {}", workload.describe());
+    client_pool.tag_run(metadata.run_id, &workload.describe());
+
+    let mut generator = RequestGenerator::new(workload, num_accounts);
+    println!("Seed: {}", generator.seed);
+    println!();
+
+    let run = RunArgs::default();
+    let mut steps: Vec<(usize, StressSummary)> = Vec::new();
+    let mut curve = Vec::new();
+    let mut best_throughput = 0.0;
+    let mut parallel = config.start.max(1);
+
+    let stop_reason = loop {
+        let pb = progress::spinner();
+        pb.set_style(
+            ProgressStyle::default_spinner()
+                .template("{spinner:.green} {msg}: {pos} completed ({per_sec})")?,
+        );
+        pb.set_message(format!("{} parallel", parallel));
+        pb.enable_steady_tick(Duration::from_millis(100));
+
+        let outcome = stress::run_closed_loop(
+            client_pool,
+            &mut generator,
+            Pace::parallel(parallel),
+            Budget::Duration(Duration::from_secs(config.step_secs)),
+            &run,
+            &pb,
+            &ProgressBar::hidden(),
+        )
+        .await;
+        pb.finish_and_clear();
+
+        let summary = outcome.stats.summary(outcome.elapsed, LoadModel::Closed);
+        println!(
+            "{:>6} parallel: {:.0} calls/sec, p99 {}ms",
+            parallel, summary.throughput, summary.p99_ms
+        );
+
+        let improvement = if best_throughput > 0.0 {
+            (summary.throughput - best_throughput) / best_throughput * 100.0
+        } else {
+            f64::INFINITY
+        };
+        let p99_ms = summary.p99_ms;
+        best_throughput = f64::max(best_throughput, summary.throughput);
+        curve.push(CurvePoint::of(parallel as u32, &summary));
+        steps.push((parallel, summary));
+
+        if outcome.interrupted {
+            break "interrupted".to_string();
+        }
+        if let Some(message) = outcome.panicked {
+            break format!("a request task failed: {}", message);
+        }
+        if let Some(limit) = config.p99_limit_ms
+            && p99_ms > limit
+        {
+            break format!("p99 exceeded {}ms", limit);
+        }
+        if improvement < config.plateau_pct {
+            break format!("throughput improved by less than {}%", config.plateau_pct);
+        }
+        if parallel >= config.max {
+            break format!("reached {} parallel", config.max);
+        }
+        parallel = (parallel * 2).min(config.max);
+    };
+
+    println!();
+    println!("Stopped: {}", stop_reason);
+    println!();
+    println!(
+        "{:>10} {:>14} {:>10} {:>10} {:>10}",
+        "Parallel", "Throughput/s", "p50 ms", "p99 ms", "Errors"
+    );
+    println!("{}", "=".repeat(58));
+    for (parallel, summary) in &steps {
+        let marker = if summary.throughput == best_throughput {
+            " <- best"
+        } else {
+            ""
+        };
+        println!(
+            "{:>10} {:>14.0} {:>10} {:>10} {:>9.2}%{}",
+            parallel,
+            summary.throughput,
+            summary.p50_ms,
+            summary.p99_ms,
+            summary.error_rate() * 100.0,
+            marker
+        );
+    }
+    println!();
+    if let Some(path) = &config.curve_csv {
+        stress::write_curve_csv(path, &metadata, "parallel", &curve)?;
+    }
+
+    Ok(())
+}
+
+/// Run `phase` with AIMD choosing the concurrency, then again with the
+/// concurrency fixed at the mean target of the AIMD run's second half, and
+/// print how the two compare
+pub async fn compare_aimd(
+    client_pool: &ClientPool,
+    num_accounts: u32,
+    workload: &WorkloadArgs,
+    phase: Phase,
+) -> Result<()> {
+    stress::check_accounts(num_accounts, workload)?;
+    println!("AIMD vs Fixed Concurrency");
+    println!("========================================");
+    let metadata = RunMetadata::new(
+        client_pool,
+        num_accounts,
+        format!("{}/sec for {}s, twice", phase.rate, phase.duration_secs),
+    );
+    metadata.print();
+    println!();
+    println!("Clients: {}", client_pool.len());
+    println!("Workload: {}", workload.describe());
+    client_pool.tag_run(metadata.run_id, &workload.describe());
+
+    let mut generator = RequestGenerator::new(workload, num_accounts);
+    println!("Seed: {}", generator.seed);
+    println!();
+
+    let controls = RunControls::new();
+    let running = controls.running.clone();
+    tokio::spawn(async move {
+        let why = stress::stop_requested().await;
+        println!("\n{}, stopping the comparison...", why);
+        running.store(false, Ordering::SeqCst);
+    });
+
+    let run = RunArgs::default();
+    let schedule = RateSchedule::Phases(vec![phase]);
+
+    println!("Running with AIMD...");
+    let aimd = stress::run_rate_schedule(
+        client_pool,
+        &mut generator,
+        &schedule,
+        &run,
+        &SustainedArgs::default(),
+        &metadata,
+        None,
+        &controls,
+    )
+    .await?;
+    anyhow::ensure!(
+        controls.running.load(Ordering::SeqCst),
+        "Interrupted before the fixed-concurrency run"
+    );
+    let settled = &aimd.targets[aimd.targets.len() / 2..];
+    let fixed = match settled.len() {
+        0 => 1,
+        n => (settled.iter().sum::<usize>() / n).max(1),
+    };
+    println!("AIMD settled at {} in flight", fixed);
+
+    println!("Running with concurrency fixed at {}...", fixed);
+    let options = SustainedArgs {
+        fixed_concurrency: Some(fixed as u32),
+        ..SustainedArgs::default()
+    };
+    let fixed_outcome = stress::run_rate_schedule(
+        client_pool,
+        &mut generator,
+        &schedule,
+        &run,
+        &options,
+        &metadata,
+        None,
+        &controls,
+    )
+    .await?;
+
+    let aimd = aimd.stats.summary(aimd.elapsed, LoadModel::Closed);
+    let fixed_summary = fixed_outcome
+        .stats
+        .summary(fixed_outcome.elapsed, LoadModel::Closed);
+    println!();
+    println!(
+        "{:<16} {:>14} {:>14}",
+        "Metric",
+        "AIMD",
+        format!("Fixed ({})", fixed)
+    );
+    println!("{}", "=".repeat(46));
+    println!(
+        "{:<16} {:>12.0}/s {:>12.0}/s",
+        "Throughput", aimd.throughput, fixed_summary.throughput
+    );
+    println!(
+        "{:<16} {:>12}ms {:>12}ms",
+        "p50 latency", aimd.p50_ms, fixed_summary.p50_ms
+    );
+    println!(
+        "{:<16} {:>12}ms {:>12}ms",
+        "p99 latency", aimd.p99_ms, fixed_summary.p99_ms
+    );
+    println!(
+        "{:<16} {:>13.2}% {:>13.2}%",
+        "Error rate",
+        aimd.error_rate() * 100.0,
+        fixed_summary.error_rate() * 100.0
+    );
+    println!();
+
+    Ok(())
+}
+
+/// Service levels a rate must meet to count as sustainable, and the range to
+/// search for it
+pub struct RateSearch {
+    /// Lowest rate to try; the search fails if even this misses the SLOs
+    pub min_rate: u32,
+    /// Highest rate to try
+    pub max_rate: u32,
+    /// Stop once the highest passing and lowest failing rates are this close
+    pub resolution: u32,
+    /// How long each probe runs
+    pub probe_secs: u64,
+    pub max_p99_ms: u64,
+    pub max_error_rate: f64,
+    /// Where to write each probe's rate, throughput and latency
+    pub curve_csv: Option<PathBuf>,
+}
+
+/// Fraction of the target rate a probe must actually complete, so a rate the
+/// generator couldn't keep up with doesn't pass on latency alone
+const MIN_ACHIEVED_FRACTION: f64 = 0.95;
+
+/// Binary-search for the highest sustained rate that meets the latency and
+/// error SLOs, running a short probe at each candidate rate
+pub async fn find_max_rate(
+    client_pool: &ClientPool,
+    num_accounts: u32,
+    workload: &WorkloadArgs,
+    search: &RateSearch,
+) -> Result<StressSummary> {
+    stress::check_accounts(num_accounts, workload)?;
+    if search.min_rate == 0 || search.min_rate > search.max_rate {
+        return Err(anyhow::anyhow!(
+            "--min-rate must be at least 1 and no more than --max-rate, but the search \
+             was asked for {}/sec to {}/sec",
+            search.min_rate,
+            search.max_rate
+        )
+        .context(Failure::Config));
+    }
+    println!("Maximum Rate Search");
+    println!("========================================");
+    let metadata = RunMetadata::new(
+        client_pool,
+        num_accounts,
+        format!("{}/sec to {}/sec", search.min_rate, search.max_rate),
+    );
+    metadata.print();
+    println!();
+    println!(
+        "Rates: {}/sec to {}/sec, to within {}/sec, {}s per probe",
+        search.min_rate, search.max_rate, search.resolution, search.probe_secs
+    );
+    println!(
+        "SLOs: p99 <= {}ms, error rate <= {:.2}%",
+        search.max_p99_ms,
+        search.max_error_rate * 100.0
+    );
+    println!("Clients: {}", client_pool.len());
+    println!("Workload: {}", workload.describe());
+    client_pool.tag_run(metadata.run_id, &workload.describe());
+
+    let mut generator = RequestGenerator::new(workload, num_accounts);
+    println!("Seed: {}", generator.seed);
+    println!();
+
+    let controls = RunControls::new();
+    let running = controls.running.clone();
+    tokio::spawn(async move {
+        let why = stress::stop_requested().await;
+        println!("\n{}, stopping the search...", why);
+        running.store(false, Ordering::SeqCst);
+    });
+
+    let run = RunArgs {
+        max_error_rate: Some(search.max_error_rate),
+        ..RunArgs::default()
+    };
+    let options = SustainedArgs::default();
+
+    // Highest rate known to pass, and lowest known to fail
+    let mut passed: Option<(u32, StressSummary)> = None;
+    let mut curve = Vec::new();
+    let mut failed = search.max_rate.saturating_add(1);
+    let mut rate = search.min_rate;
+
+    while controls.running.load(Ordering::SeqCst) {
+        let schedule = RateSchedule::Phases(vec![Phase {
+            rate,
+            duration_secs: search.probe_secs,
+        }]);
+        let outcome = stress::run_rate_schedule(
+            client_pool,
+            &mut generator,
+            &schedule,
+            &run,
+            &options,
+            &metadata,
+            None,
+            &controls,
+        )
+        .await?;
+        if !controls.running.load(Ordering::SeqCst) {
+            break;
+        }
+
+        let summary = outcome.stats.summary(outcome.elapsed, LoadModel::Closed);
+        let verdict = if outcome.aborted.is_some() || summary.error_rate() > search.max_error_rate
+        {
+            Err(format!("error rate {:.2}%", summary.error_rate() * 100.0))
+        } else if summary.p99_ms > search.max_p99_ms {
+            Err(format!("p99 {}ms", summary.p99_ms))
+        } else if summary.throughput < rate as f64 * MIN_ACHIEVED_FRACTION {
+            Err(format!("only reached {:.0}/sec", summary.throughput))
+        } else {
+            Ok(())
+        };
+        println!(
+            "{:>7}/sec: {:.0} calls/sec, p99 {}ms, {:.2}% errors -> {}",
+            rate,
+            summary.throughput,
+            summary.p99_ms,
+            summary.error_rate() * 100.0,
+            match &verdict {
+                Ok(()) => "pass".to_string(),
+                Err(reason) => format!("fail ({})", reason),
+            }
+        );
+
+        curve.push(CurvePoint::of(rate, &summary));
+        match verdict {
+            Ok(()) => passed = Some((rate, summary)),
+            Err(_) => failed = rate,
+        }
+        let Some((low, _)) = &passed else {
+            break;
+        };
+        if failed - low <= search.resolution.max(1) {
+            break;
+        }
+        rate = low + (failed - low) / 2;
+    }
+
+    println!();
+    if let Some(path) = &search.curve_csv {
+        stress::write_curve_csv(path, &metadata, "offered_rate", &curve)?;
+    }
+    let Some((rate, mut summary)) = passed else {
+        return Err(anyhow::anyhow!(
+            "No rate met the SLOs; even {}/sec failed or the search was interrupted",
+            search.min_rate
+        )
+        .context(Failure::Gate));
+    };
+    println!("Maximum sustainable rate: {}/sec", rate);
+    println!("  Throughput: {:.0} calls/sec", summary.throughput);
+    println!("  p50: {}ms, p99: {}ms", summary.p50_ms, summary.p99_ms);
+    println!("  Error rate: {:.2}%", summary.error_rate() * 100.0);
+    println!("  OCC retry rate: {:.2}%", summary.occ_retry_rate() * 100.0);
+    println!();
+
+    summary.metadata = Some(metadata);
+    Ok(summary)
+}
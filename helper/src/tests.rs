@@ -2,35 +2,154 @@ use crate::{
     credentials::CredentialCache,
     db,
     lambda::{self, greeting, tpcb, ClientPool},
-    stress,
+    cli::{DbArgs, HistoryRole, RunArgs, WorkloadArgs},
+    report,
+    stress::{self, Pace},
 };
 use anyhow::Result;
+use std::path::Path;
+use std::time::Instant;
 
-#[derive(sqlx::FromRow)]
-struct Transaction {
-    id: uuid::Uuid,
-    payer_id: i32,
-    payee_id: i32,
-    amount: i32,
-    created_at: chrono::NaiveDateTime,
+/// A chapter of the demo, as `test-chapter --chapter` runs it
+pub struct Chapter {
+    pub number: u32,
+    pub title: &'static str,
+    /// What the chapter's test exercises
+    pub description: &'static str,
 }
 
-pub async fn run_test(client_pool: &ClientPool, creds: &CredentialCache, chapter: u32) -> Result<()> {
+/// Every chapter `run_test` knows, in order. Each test prints its title.
+pub const CHAPTERS: [Chapter; 5] = [
+    Chapter {
+        number: 0,
+        title: "Basic Lambda invocation with DSQL connection",
+        description: "Calls the greeting endpoint and checks the Lambda reached DSQL",
+    },
+    Chapter {
+        number: 1,
+        title: "Money transfer",
+        description: "Sends one transfer of 10 from account 1 to 2 and checks for a balance",
+    },
+    Chapter {
+        number: 2,
+        title: "Stress Test - 10K Invocations",
+        description: "Sends 10,000 transfers among 1,000 accounts, 1,000 at a time",
+    },
+    Chapter {
+        number: 3,
+        title: "Transaction history with UUID primary keys",
+        description: "Sends a transfer, then lists account 1's latest transactions",
+    },
+    Chapter {
+        number: 4,
+        title: "1M Invocations",
+        description: "Sends 1,000,000 transfers among 1,000,000 accounts, 10,000 at a time \
+                      (needs setup-ch04)",
+    },
+];
+
+/// Print every chapter with what its test does
+pub fn list_chapters() {
+    for chapter in &CHAPTERS {
+        println!("Chapter {}: {}", chapter.number, chapter.title);
+        println!("  {}", chapter.description);
+    }
+}
+
+fn announce(number: u32) {
+    println!("Testing Chapter {}: {}\n", number, CHAPTERS[number as usize].title);
+}
+
+pub async fn run_test(
+    client_pool: &ClientPool,
+    creds: &CredentialCache,
+    db_args: &DbArgs,
+    chapter: u32,
+    summary_json: Option<&Path>,
+) -> Result<()> {
     match chapter {
         0 => test_chapter0(client_pool).await,
         1 => test_chapter1(client_pool).await,
-        2 => test_chapter2(client_pool).await,
-        3 => test_chapter3(client_pool, creds).await,
-        4 => test_chapter4(client_pool).await,
-        _ => {
-            eprintln!("Unknown test chapter: {}", chapter);
-            std::process::exit(1);
+        2 => test_chapter2(client_pool, summary_json).await,
+        3 => test_chapter3(client_pool, creds, db_args).await,
+        4 => test_chapter4(client_pool, summary_json).await,
+        _ => anyhow::bail!("Unknown test chapter: {}; run `chapters` to list them", chapter),
+    }
+}
+
+/// Send `amount` from `payer_id` to `payee_id` `repeat` times, one after
+/// another, printing each reply in full and a summary at the end; chapter
+/// 1's test with the pair chosen. Fails if any transfer did.
+pub async fn transfer(
+    client_pool: &ClientPool,
+    payer_id: u32,
+    payee_id: u32,
+    amount: u32,
+    repeat: u32,
+) -> Result<()> {
+    println!("Transferring {} from account {} to {}\n", amount, payer_id, payee_id);
+
+    let mut succeeded = 0;
+    let mut retries = 0;
+    let mut round_trips = Vec::with_capacity(repeat as usize);
+    for i in 1..=repeat {
+        let req = tpcb::Request {
+            payer_id,
+            payee_id,
+            amount: tpcb::Amount::Whole(amount),
+            idempotency_key: None,
+            deadline_ms: client_pool.deadline_ms(),
+        };
+        let start = Instant::now();
+        let result: Result<tpcb::Response> = lambda::invoke(client_pool.get(), req).await;
+        let round_trip = start.elapsed().as_millis();
+        round_trips.push(round_trip);
+
+        let response = match result {
+            Ok(response) => response,
+            Err(err) => {
+                println!("#{:<4} ❌ invocation failed after {}ms: {:#}", i, round_trip, err);
+                continue;
+            }
+        };
+        retries += response.retries.unwrap_or(0);
+        let duration = response
+            .duration
+            .map_or_else(|| "-".to_string(), |ms| format!("{}ms", ms));
+        let detail = format!(
+            "duration {}, round trip {}ms, retries {}",
+            duration,
+            round_trip,
+            response.retries.unwrap_or(0)
+        );
+        match (&response.error, response.balance) {
+            (None, Some(balance)) => {
+                succeeded += 1;
+                println!("#{:<4} ✅ balance {} ({})", i, balance, detail);
+            }
+            (Some(err), _) => {
+                let code = response.error_code.as_deref().unwrap_or("-");
+                println!("#{:<4} ❌ {} [{}] ({})", i, err, code, detail);
+            }
+            (None, None) => println!("#{:<4} ❌ reply had no balance ({})", i, detail),
         }
     }
+
+    let failed = repeat - succeeded;
+    println!();
+    println!("{} succeeded, {} failed, {} OCC retries", succeeded, failed, retries);
+    if let (Some(min), Some(max)) = (round_trips.iter().min(), round_trips.iter().max()) {
+        let mean = round_trips.iter().sum::<u128>() / round_trips.len() as u128;
+        println!("Round trip: min {}ms, mean {}ms, max {}ms", min, mean, max);
+    }
+    if failed > 0 {
+        anyhow::bail!("{} of {} transfers failed", failed, repeat);
+    }
+    Ok(())
 }
 
 async fn test_chapter0(client_pool: &ClientPool) -> Result<()> {
-    println!("Testing Chapter 0: Basic Lambda invocation with DSQL connection\n");
+    announce(0);
 
     let req = greeting::Request {
         name: "reinvent".to_string(),
@@ -49,12 +168,14 @@ async fn test_chapter0(client_pool: &ClientPool) -> Result<()> {
 }
 
 async fn test_chapter1(client_pool: &ClientPool) -> Result<()> {
-    println!("Testing Chapter 1: Money transfer\n");
+    announce(1);
 
     let req = tpcb::Request {
         payer_id: 1,
         payee_id: 2,
-        amount: 10,
+        amount: tpcb::Amount::Whole(10),
+        idempotency_key: None,
+        deadline_ms: None,
     };
 
     let response: tpcb::Response = lambda::invoke(client_pool.get(), req).await?;
@@ -69,20 +190,37 @@ async fn test_chapter1(client_pool: &ClientPool) -> Result<()> {
     Ok(())
 }
 
-async fn test_chapter2(client_pool: &ClientPool) -> Result<()> {
-    println!("Testing Chapter 2: Stress Test - 10K Invocations\n");
-    stress::run_stress_test(client_pool, 10_000, 1_000, 1_000).await?;
+async fn test_chapter2(client_pool: &ClientPool, summary_json: Option<&Path>) -> Result<()> {
+    announce(2);
+    let summary = stress::run_stress_test(
+        client_pool,
+        10_000,
+        Pace::parallel(1_000),
+        1_000,
+        &WorkloadArgs::default(),
+        &RunArgs::default(),
+    )
+    .await?;
+    if let Some(path) = summary_json {
+        summary.save(path)?;
+    }
     println!("✅ Chapter 2 test complete");
     Ok(())
 }
 
-async fn test_chapter3(client_pool: &ClientPool, creds: &CredentialCache) -> Result<()> {
-    println!("Testing Chapter 3: Transaction history with UUID primary keys\n");
+async fn test_chapter3(
+    client_pool: &ClientPool,
+    creds: &CredentialCache,
+    db_args: &DbArgs,
+) -> Result<()> {
+    announce(3);
 
     let req = tpcb::Request {
         payer_id: 1,
         payee_id: 2,
-        amount: 10,
+        amount: tpcb::Amount::Whole(10),
+        idempotency_key: None,
+        deadline_ms: None,
     };
 
     println!(
@@ -105,39 +243,31 @@ async fn test_chapter3(client_pool: &ClientPool, creds: &CredentialCache) -> Res
 
     // Query the database to verify transaction was recorded
     println!("\nChecking transactions table...");
-    let pool = db::get_pool(creds).await?;
-
-    let transactions: Vec<Transaction> = sqlx::query_as(
-        "SELECT id, payer_id, payee_id, amount, created_at
-         FROM transactions
-         WHERE payer_id = $1
-         ORDER BY created_at DESC
-         LIMIT 5",
-    )
-    .bind(1i32)
-    .fetch_all(&pool)
-    .await?;
+    let pool = db::get_pool(creds, db_args).await?;
+
+    let transactions = db::account_history(&pool, 1, HistoryRole::Payer, 5, None).await?;
 
     println!("Found {} recent transactions:", transactions.len());
-    for (i, tx) in transactions.iter().enumerate() {
-        println!(
-            "  {}. ID: {}, Payer: {}, Payee: {}, Amount: {}, Time: {}",
-            i + 1,
-            tx.id,
-            tx.payer_id,
-            tx.payee_id,
-            tx.amount,
-            tx.created_at
-        );
-    }
+    report::print_transactions(&transactions);
 
     println!("\n✅ Chapter 3 test PASSED");
     Ok(())
 }
 
-async fn test_chapter4(client_pool: &ClientPool) -> Result<()> {
-    println!("Testing Chapter 4: 100K Invocations\n");
-    stress::run_stress_test(client_pool, 1_000_000, 10_000, 1_000_000).await?;
+async fn test_chapter4(client_pool: &ClientPool, summary_json: Option<&Path>) -> Result<()> {
+    announce(4);
+    let summary = stress::run_stress_test(
+        client_pool,
+        1_000_000,
+        Pace::parallel(10_000),
+        1_000_000,
+        &WorkloadArgs::default(),
+        &RunArgs::default(),
+    )
+    .await?;
+    if let Some(path) = summary_json {
+        summary.save(path)?;
+    }
     println!("✅ Chapter 4 test complete");
     Ok(())
 }